@@ -84,8 +84,4 @@ impl node::Server for ServerNode {
     fn register_shard(&mut self, s: Arc<Self::S>) {
         self.shard = s;
     }
-
-    async fn execute(&self, t: txn::Txn) -> Result<()> {
-        Ok(())
-    }
 }
@@ -0,0 +1,157 @@
+use std::time::Duration;
+
+// every power of two of nanoseconds is split into `SUB_BUCKETS` linear buckets,
+// and the values below `2 * SUB_BUCKETS` have their own buckets.
+const SUB_BITS: u32 = 5;
+const SUB_BUCKETS: u64 = 1 << SUB_BITS;
+const LINEAR: u64 = 2 * SUB_BUCKETS;
+const BUCKETS: usize = (LINEAR + (64 - SUB_BITS as u64 - 1) * SUB_BUCKETS) as usize;
+
+fn bucket_of(nanos: u64) -> usize {
+    if nanos < LINEAR {
+        return nanos as usize;
+    }
+    let msb = 63 - nanos.leading_zeros();
+    let shift = msb - SUB_BITS;
+    let group = (msb - SUB_BITS - 1) as u64;
+    (LINEAR + group * SUB_BUCKETS + (nanos >> shift) - SUB_BUCKETS) as usize
+}
+
+// bucket_max returns the largest value in the bucket.
+fn bucket_max(bucket: usize) -> u64 {
+    let bucket = bucket as u64;
+    if bucket < LINEAR {
+        return bucket;
+    }
+    let group = (bucket - LINEAR) / SUB_BUCKETS;
+    let sub = (bucket - LINEAR) % SUB_BUCKETS + SUB_BUCKETS;
+    let shift = group + 1;
+    ((((sub + 1) as u128) << shift) - 1) as u64
+}
+
+/// Histogram counts the samples in log-linear buckets, so its memory is bounded,
+/// and the percentiles are at most 1/32 larger than the exact ones.
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: Duration,
+    max: Duration,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; BUCKETS],
+            count: 0,
+            sum: Duration::from_secs(0),
+            max: Duration::from_secs(0),
+        }
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, d: Duration) {
+        let nanos = d.as_nanos().min(u64::MAX as u128) as u64;
+        self.buckets[bucket_of(nanos)] += 1;
+        self.count += 1;
+        self.sum = self.sum.saturating_add(d);
+        self.max = self.max.max(d);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.buckets.iter_mut().zip(&other.buckets) {
+            *count += other;
+        }
+        self.count += other.count;
+        self.sum = self.sum.saturating_add(other.sum);
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn count(&self) -> usize {
+        self.count as usize
+    }
+
+    /// percentile returns the upper bound of the bucket of the smallest sample
+    /// which is not less than `p` percent of the samples, it's zero if there is no sample.
+    pub fn percentile(&self, p: f64) -> Duration {
+        assert!((0.0..=100.0).contains(&p));
+        if self.count == 0 {
+            return Duration::from_secs(0);
+        }
+        let rank = ((p / 100.0 * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_nanos(bucket_max(bucket)).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            return Duration::from_secs(0);
+        }
+        let nanos = self.sum.as_nanos() / self.count as u128;
+        Duration::from_nanos(nanos as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut h = Histogram::new();
+        assert_eq!(h.percentile(99.0), Duration::from_secs(0));
+        for i in (1..=100).rev() {
+            h.record(Duration::from_millis(i));
+        }
+        assert_eq!(h.count(), 100);
+        let close = |d: Duration, ms: u64| {
+            let exact = Duration::from_millis(ms);
+            d >= exact && d <= exact + exact / 32
+        };
+        assert!(close(h.percentile(50.0), 50));
+        assert!(close(h.percentile(99.0), 99));
+        assert!(close(h.percentile(0.0), 1));
+        assert_eq!(h.max(), Duration::from_millis(100));
+
+        let mut other = Histogram::new();
+        other.record(Duration::from_millis(1000));
+        h.merge(&other);
+        assert_eq!(h.percentile(100.0), Duration::from_millis(1000));
+        assert_eq!(h.mean(), Duration::from_millis(6050) / 101);
+    }
+
+    #[test]
+    fn test_buckets() {
+        // the buckets cover all the values without gaps.
+        let mut min = 0;
+        for bucket in 0..BUCKETS {
+            let max = bucket_max(bucket);
+            assert!(max >= min);
+            assert_eq!(bucket_of(min), bucket);
+            assert_eq!(bucket_of(max), bucket);
+            min = max.wrapping_add(1);
+        }
+        assert_eq!(min, 0);
+
+        let mut h = Histogram::new();
+        h.record(Duration::from_nanos(3));
+        h.record(Duration::from_secs(3600));
+        assert_eq!(h.percentile(50.0), Duration::from_nanos(3));
+        assert_eq!(h.percentile(100.0), Duration::from_secs(3600));
+    }
+}
@@ -0,0 +1,347 @@
+//! A benchmark driver, which runs concurrent clients issuing txns of a `Workload`.
+use crate::node::Server;
+use crate::util::{Error, Result, Rng, Timer};
+use async_trait::async_trait;
+use futures::future::join_all;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+mod histogram;
+pub use histogram::Histogram;
+
+#[async_trait]
+pub trait Workload: Sync {
+    type Server: Server;
+
+    /// prepare loads the initial data before the clients start.
+    async fn prepare(&self, _server: &Self::Server) -> Result<()> {
+        Ok(())
+    }
+
+    /// next chooses the type of the next txn.
+    fn next(&self, rng: &mut Rng) -> &'static str;
+
+    /// execute runs a txn of `txn_type` to the end, an error means the txn is aborted.
+    async fn execute(
+        &self,
+        server: &Self::Server,
+        txn_type: &'static str,
+        rng: &mut Rng,
+    ) -> Result<()>;
+}
+
+/// Limit tells every client when to stop.
+#[derive(Clone, Copy, Debug)]
+pub enum Limit {
+    Duration(Duration),
+    /// The number of txns issued by every client.
+    Count(u64),
+}
+
+#[derive(Clone, Debug)]
+pub struct BenchConfig {
+    pub clients: usize,
+    pub limit: Limit,
+    /// The client `i` uses the rng seeded by `seed + i`.
+    pub seed: u64,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            clients: 4,
+            limit: Limit::Count(100),
+            seed: 0,
+        }
+    }
+}
+
+/// TxnStats is the result of a txn type, only the latencies of committed txns are recorded.
+#[derive(Clone, Debug, Default)]
+pub struct TxnStats {
+    pub committed: u64,
+    pub aborted: u64,
+    pub latency: Histogram,
+}
+
+impl TxnStats {
+    pub fn abort_rate(&self) -> f64 {
+        let total = self.committed + self.aborted;
+        if total == 0 {
+            return 0.0;
+        }
+        self.aborted as f64 / total as f64
+    }
+
+    fn merge(&mut self, other: &TxnStats) {
+        self.committed += other.committed;
+        self.aborted += other.aborted;
+        self.latency.merge(&other.latency);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub elapsed: Duration,
+    pub txns: BTreeMap<&'static str, TxnStats>,
+}
+
+impl Report {
+    /// total merges the stats of all txn types.
+    pub fn total(&self) -> TxnStats {
+        let mut total = TxnStats::default();
+        for stats in self.txns.values() {
+            total.merge(stats);
+        }
+        total
+    }
+
+    /// throughput returns the committed txns per second.
+    pub fn throughput(&self, txn_type: Option<&str>) -> f64 {
+        let committed = match txn_type {
+            Some(t) => self.txns.get(t).map_or(0, |s| s.committed),
+            None => self.total().committed,
+        };
+        if self.elapsed.as_nanos() == 0 {
+            return 0.0;
+        }
+        committed as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<12} {:>10} {:>10} {:>8} {:>10} {:>10} {:>10} {:>10}",
+            "txn", "committed", "tps", "abort%", "p50", "p95", "p99", "max"
+        )?;
+        let total = self.total();
+        let rows = self
+            .txns
+            .iter()
+            .map(|(name, stats)| (*name, stats.clone()))
+            .chain(std::iter::once(("total", total)));
+        for (name, stats) in rows {
+            let tps = match name {
+                "total" => self.throughput(None),
+                _ => self.throughput(Some(name)),
+            };
+            writeln!(
+                f,
+                "{:<12} {:>10} {:>10.1} {:>8.2} {:>10?} {:>10?} {:>10?} {:>10?}",
+                name,
+                stats.committed,
+                tps,
+                stats.abort_rate() * 100.0,
+                stats.latency.percentile(50.0),
+                stats.latency.percentile(95.0),
+                stats.latency.percentile(99.0),
+                stats.latency.max(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// run spawns `config.clients` concurrent clients, the client `i` sends its txns to
+/// `servers[i % servers.len()]`, and the workload is prepared on the first server.
+pub async fn run<W: Workload, T: Timer>(
+    workload: &W,
+    servers: &[&W::Server],
+    config: &BenchConfig,
+    timer: &T,
+) -> Result<Report> {
+    if servers.is_empty() {
+        return Err(Error::ConfigError("no servers to bench".to_owned()));
+    }
+    workload.prepare(servers[0]).await?;
+    let start = timer.now();
+    let clients = (0..config.clients).map(|i| {
+        let server = servers[i % servers.len()];
+        run_client(
+            workload,
+            server,
+            config.limit,
+            config.seed + i as u64,
+            timer,
+        )
+    });
+    let results = join_all(clients).await;
+    let mut report = Report {
+        elapsed: timer.now() - start,
+        txns: BTreeMap::new(),
+    };
+    for txns in results {
+        for (name, stats) in txns {
+            report.txns.entry(name).or_default().merge(&stats);
+        }
+    }
+    Ok(report)
+}
+
+async fn run_client<W: Workload, T: Timer>(
+    workload: &W,
+    server: &W::Server,
+    limit: Limit,
+    seed: u64,
+    timer: &T,
+) -> BTreeMap<&'static str, TxnStats> {
+    let mut rng = Rng::new(seed);
+    let mut txns: BTreeMap<&'static str, TxnStats> = BTreeMap::new();
+    let start = timer.now();
+    let mut count = 0;
+    loop {
+        let finished = match limit {
+            Limit::Duration(d) => timer.now() - start >= d,
+            Limit::Count(n) => count >= n,
+        };
+        if finished {
+            return txns;
+        }
+        count += 1;
+        let txn_type = workload.next(&mut rng);
+        let begin = timer.now();
+        let res = workload.execute(server, txn_type, &mut rng).await;
+        let latency = timer.now() - begin;
+        let stats = txns.entry(txn_type).or_default();
+        match res {
+            Ok(()) => {
+                stats.committed += 1;
+                stats.latency.record(latency);
+            }
+            Err(_) => stats.aborted += 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::Simulator;
+    use crate::txn::percolator::test::{new_server, TestServer};
+    use crate::txn::percolator::PercolatorTxn;
+    use crate::txn::Txn;
+    use crate::util::test::run_in_tokio;
+    use crate::util::TokioTimer;
+
+    // Counter increases one of 4 hot counters, or reads all of them.
+    struct Counter;
+
+    #[async_trait]
+    impl Workload for Counter {
+        type Server = TestServer;
+
+        async fn prepare(&self, server: &TestServer) -> Result<()> {
+            let mut txn = PercolatorTxn::new();
+            for key in [1, 2, 51, 52] {
+                txn.put(key, 0)?;
+            }
+            server.execute(txn).await
+        }
+
+        fn next(&self, rng: &mut Rng) -> &'static str {
+            if rng.gen_bool(0.2) {
+                "read"
+            } else {
+                "incr"
+            }
+        }
+
+        async fn execute(
+            &self,
+            server: &TestServer,
+            txn_type: &'static str,
+            rng: &mut Rng,
+        ) -> Result<()> {
+            let mut txn = PercolatorTxn::new();
+            txn.execute(server).await?;
+            if txn_type == "read" {
+                for key in [1, 2, 51, 52] {
+                    txn.get(server, &key).await?;
+                }
+            } else {
+                let key = [1, 2, 51, 52][rng.gen_range(0, 4) as usize];
+                let v = txn.get(server, &key).await?.unwrap();
+                txn.put(key, v + 1)?;
+            }
+            txn.commit(server).await
+        }
+    }
+
+    #[test]
+    fn test_bench() {
+        run_in_tokio(async move {
+            let server = new_server();
+            let config = BenchConfig {
+                clients: 8,
+                limit: Limit::Count(20),
+                seed: 0,
+            };
+            let report = run(&Counter, &[&server], &config, &TokioTimer::new())
+                .await
+                .unwrap();
+            let total = report.total();
+            assert_eq!(total.committed + total.aborted, 160);
+            assert_eq!(total.latency.count() as u64, total.committed);
+            assert!(report.txns["incr"].committed > 0);
+            assert!(report.throughput(None) > 0.0);
+
+            // the committed increments are all visible.
+            let mut txn = PercolatorTxn::new();
+            let mut sum = 0;
+            for key in [1, 2, 51, 52] {
+                sum += txn.get(&server, &key).await.unwrap().unwrap();
+            }
+            assert_eq!(sum as u64, report.txns["incr"].committed);
+
+            assert_eq!(
+                run(&Counter, &[], &config, &TokioTimer::new())
+                    .await
+                    .unwrap_err(),
+                Error::ConfigError("no servers to bench".to_owned())
+            );
+            std::mem::forget(server);
+        });
+    }
+
+    #[test]
+    fn test_bench_duration() {
+        let sim = Simulator::new(0);
+        let s = sim.clone();
+        let report = sim.block_on(async move {
+            let server = new_server();
+            let config = BenchConfig {
+                clients: 2,
+                limit: Limit::Duration(Duration::from_secs(1)),
+                seed: 0,
+            };
+            let report = run(&Sleepy(s.clone()), &[&server], &config, &s).await;
+            std::mem::forget(server);
+            report.unwrap()
+        });
+        sim.shutdown();
+        // every txn takes 10ms in the virtual time.
+        assert_eq!(report.txns["sleep"].committed, 200);
+        assert_eq!(report.elapsed, Duration::from_secs(1));
+        let stats = &report.txns["sleep"];
+        assert_eq!(stats.latency.percentile(99.0), Duration::from_millis(10));
+    }
+
+    struct Sleepy(Simulator);
+
+    #[async_trait]
+    impl Workload for Sleepy {
+        type Server = TestServer;
+
+        fn next(&self, _: &mut Rng) -> &'static str {
+            "sleep"
+        }
+
+        async fn execute(&self, _: &TestServer, _: &'static str, _: &mut Rng) -> Result<()> {
+            self.0.sleep(Duration::from_millis(10)).await;
+            Ok(())
+        }
+    }
+}
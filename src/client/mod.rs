@@ -1,16 +1,299 @@
-use crate::cluster::Cluster;
-use crate::txn::Txn;
+//! The client side of interactive txns, every operation is a request to a server,
+//! and the server keeps the txn state until it's committed or rolled back.
+use crate::codec::{Encode, Key, Value};
+use crate::request::Sender;
+use crate::util::{CodecError, Error, Result};
+use async_trait::async_trait;
 
-pub trait Client<C: Cluster> {
-    type Cluster = C;
+pub enum TxnRequest<K: Key, V: Value> {
+    Begin,
+    Get {
+        txn: u64,
+        key: K,
+    },
+    Put {
+        txn: u64,
+        key: K,
+        value: V,
+    },
+    Del {
+        txn: u64,
+        key: K,
+    },
+    /// Scan the keys in `[lower, upper)`.
+    Scan {
+        txn: u64,
+        lower: K,
+        upper: K,
+    },
+    Commit {
+        txn: u64,
+    },
+    Rollback {
+        txn: u64,
+    },
+}
+
+#[derive(Debug)]
+pub enum TxnResponse<K: Key, V: Value> {
+    /// The id of the new txn.
+    Begin(u64),
+    Get(Option<V>),
+    Scan(Vec<(K, V)>),
+    Done,
+}
+
+impl<K: Key + Encode, V: Value + Encode> Encode for TxnRequest<K, V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            TxnRequest::Begin => 0u8.encode(buf),
+            TxnRequest::Get { txn, key } => {
+                (1u8, *txn).encode(buf);
+                key.encode(buf);
+            }
+            TxnRequest::Put { txn, key, value } => {
+                (2u8, *txn).encode(buf);
+                key.encode(buf);
+                value.encode(buf);
+            }
+            TxnRequest::Del { txn, key } => {
+                (3u8, *txn).encode(buf);
+                key.encode(buf);
+            }
+            TxnRequest::Scan { txn, lower, upper } => {
+                (4u8, *txn).encode(buf);
+                lower.encode(buf);
+                upper.encode(buf);
+            }
+            TxnRequest::Commit { txn } => (5u8, *txn).encode(buf),
+            TxnRequest::Rollback { txn } => (6u8, *txn).encode(buf),
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        let tag = u8::decode(buf)?;
+        if tag == 0 {
+            return Ok(TxnRequest::Begin);
+        }
+        let txn = u64::decode(buf)?;
+        let req = match tag {
+            1 => TxnRequest::Get {
+                txn,
+                key: K::decode(buf)?,
+            },
+            2 => {
+                let key = K::decode(buf)?;
+                let value = V::decode(buf)?;
+                TxnRequest::Put { txn, key, value }
+            }
+            3 => TxnRequest::Del {
+                txn,
+                key: K::decode(buf)?,
+            },
+            4 => {
+                let lower = K::decode(buf)?;
+                let upper = K::decode(buf)?;
+                TxnRequest::Scan { txn, lower, upper }
+            }
+            5 => TxnRequest::Commit { txn },
+            6 => TxnRequest::Rollback { txn },
+            _ => {
+                let msg = format!("invalid txn request tag {}", tag);
+                return Err(CodecError::InvalidData(msg).into());
+            }
+        };
+        Ok(req)
+    }
+}
+
+impl<K: Key + Encode, V: Value + Encode> Encode for TxnResponse<K, V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            TxnResponse::Begin(txn) => (0u8, *txn).encode(buf),
+            TxnResponse::Get(value) => {
+                1u8.encode(buf);
+                value.encode(buf);
+            }
+            TxnResponse::Scan(kvs) => {
+                2u8.encode(buf);
+                kvs.encode(buf);
+            }
+            TxnResponse::Done => 3u8.encode(buf),
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        let res = match u8::decode(buf)? {
+            0 => TxnResponse::Begin(u64::decode(buf)?),
+            1 => TxnResponse::Get(Option::decode(buf)?),
+            2 => TxnResponse::Scan(Vec::decode(buf)?),
+            3 => TxnResponse::Done,
+            tag => {
+                let msg = format!("invalid txn response tag {}", tag);
+                return Err(CodecError::InvalidData(msg).into());
+            }
+        };
+        Ok(res)
+    }
+}
+
+#[async_trait]
+pub trait Client {
+    type Txn;
+
+    async fn begin(&self) -> Result<Self::Txn>;
+}
 
-    pub fn begin() -> Txn;
+/// InteractiveTxnClient sends the operations of its txns to a single server.
+pub struct InteractiveTxnClient<'a, SE: Sender> {
+    sender: &'a SE,
 }
 
-pub struct InteractiveTxnClient<C: Cluster> {
-    cluster: C,
+impl<'a, SE: Sender> InteractiveTxnClient<'a, SE> {
+    pub fn new(sender: &'a SE) -> Self {
+        Self { sender }
+    }
 }
 
-impl<C> Client<C> for InteractiveTxnClient<C> where C: Cluster {
-    type Cluster = C;
+#[async_trait]
+impl<'a, K, V, SE> Client for InteractiveTxnClient<'a, SE>
+where
+    K: Key + Send + 'static,
+    V: Value + Send + 'static,
+    SE: Sender<Req = TxnRequest<K, V>, Res = TxnResponse<K, V>> + Sync,
+{
+    type Txn = InteractiveTxn<'a, SE>;
+
+    async fn begin(&self) -> Result<Self::Txn> {
+        match self.sender.send(TxnRequest::Begin).await? {
+            TxnResponse::Begin(id) => Ok(InteractiveTxn {
+                sender: self.sender,
+                id,
+            }),
+            _ => Err(Error::Unknown),
+        }
+    }
+}
+
+/// InteractiveTxn is the handle of a txn on the server,
+/// it should be finished by `commit` or `rollback`, otherwise the txn is leaked in the server.
+pub struct InteractiveTxn<'a, SE: Sender> {
+    sender: &'a SE,
+    id: u64,
+}
+
+impl<'a, K, V, SE> InteractiveTxn<'a, SE>
+where
+    K: Key,
+    V: Value,
+    SE: Sender<Req = TxnRequest<K, V>, Res = TxnResponse<K, V>>,
+{
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub async fn get(&self, key: K) -> Result<Option<V>> {
+        match self
+            .sender
+            .send(TxnRequest::Get { txn: self.id, key })
+            .await?
+        {
+            TxnResponse::Get(v) => Ok(v),
+            _ => Err(Error::Unknown),
+        }
+    }
+
+    pub async fn put(&self, key: K, value: V) -> Result<()> {
+        let req = TxnRequest::Put {
+            txn: self.id,
+            key,
+            value,
+        };
+        self.done(req).await
+    }
+
+    pub async fn del(&self, key: K) -> Result<()> {
+        self.done(TxnRequest::Del { txn: self.id, key }).await
+    }
+
+    pub async fn scan(&self, lower: K, upper: K) -> Result<Vec<(K, V)>> {
+        let req = TxnRequest::Scan {
+            txn: self.id,
+            lower,
+            upper,
+        };
+        match self.sender.send(req).await? {
+            TxnResponse::Scan(kvs) => Ok(kvs),
+            _ => Err(Error::Unknown),
+        }
+    }
+
+    pub async fn commit(self) -> Result<()> {
+        self.done(TxnRequest::Commit { txn: self.id }).await
+    }
+
+    pub async fn rollback(self) -> Result<()> {
+        self.done(TxnRequest::Rollback { txn: self.id }).await
+    }
+
+    async fn done(&self, req: TxnRequest<K, V>) -> Result<()> {
+        match self.sender.send(req).await? {
+            TxnResponse::Done => Ok(()),
+            _ => Err(Error::Unknown),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::channel::new_channel_connect;
+    use crate::txn::percolator::test::new_server;
+    use crate::txn::percolator::SessionServer;
+    use crate::util::test::run_in_tokio;
+    use crate::util::TxnError;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_interactive_txn() {
+        run_in_tokio(async move {
+            let sender = new_channel_connect(Arc::new(SessionServer::new(new_server())));
+            let client = InteractiveTxnClient::new(&sender);
+
+            let txn = client.begin().await.unwrap();
+            for i in 45..55 {
+                txn.put(i, i).await.unwrap();
+            }
+            txn.del(50).await.unwrap();
+            assert_eq!(txn.get(49).await.unwrap(), Some(49));
+            assert_eq!(txn.get(50).await.unwrap(), None);
+            txn.commit().await.unwrap();
+
+            // the rolled back writes are discarded.
+            let txn = client.begin().await.unwrap();
+            txn.put(60, 60).await.unwrap();
+            txn.rollback().await.unwrap();
+
+            let t1 = client.begin().await.unwrap();
+            let t2 = client.begin().await.unwrap();
+            assert_ne!(t1.id(), t2.id());
+            let kvs = t1.scan(48, 61).await.unwrap();
+            assert_eq!(
+                kvs,
+                vec![(48, 48), (49, 49), (51, 51), (52, 52), (53, 53), (54, 54)]
+            );
+            t1.put(48, 0).await.unwrap();
+            t2.put(48, 1).await.unwrap();
+            t2.commit().await.unwrap();
+            assert!(matches!(
+                t1.commit().await.unwrap_err(),
+                Error::TxnError(TxnError::WriteConflict { .. })
+            ));
+
+            let txn = client.begin().await.unwrap();
+            assert_eq!(txn.get(48).await.unwrap(), Some(1));
+            txn.commit().await.unwrap();
+            std::mem::forget(sender);
+        });
+    }
 }
@@ -0,0 +1,328 @@
+use crate::cluster::Cluster;
+use crate::codec::Key;
+use crate::node::{Node, Server};
+use crate::request::fault::{FaultSender, Network};
+use crate::request::Transport;
+use crate::shard::{KeySpaceSpilt, ReplicaGroup, Shard};
+use crate::util::{Either, Error, Result};
+use std::sync::Arc;
+
+/// The shard of the servers built by `ClusterBuilder`,
+/// every region is a group of replicas on different storage nodes,
+/// which are reached through the network of the cluster.
+pub type ReplicatedKeySpace<K, SE> = KeySpaceSpilt<K, ReplicaGroup<FaultSender<SE>>>;
+
+/// ClusterBuilder describes the topology of a cluster and builds it, e.g.
+///
+/// ```ignore
+/// let cluster = ClusterBuilder::new(ChannelTransport)
+///     .servers(2)
+///     .storage_nodes(3)
+///     .split_keys(vec![0, 100, 200])
+///     .replication(3)
+///     .build(|| StorageNode::new(), || ServerNode::new())
+///     .await?;
+/// ```
+///
+/// Every split key is the start of a region, so there are `n + 1` regions for `n` split keys,
+/// and the first region has no lower bound.
+/// The replicas of the i-th region are placed on the storage nodes from `i % storage_nodes`,
+/// and the first one is the leader.
+pub struct ClusterBuilder<K: Key, T> {
+    servers: usize,
+    storage_nodes: usize,
+    split_keys: Vec<K>,
+    replication: usize,
+    transport: T,
+    network: Option<Network>,
+}
+
+impl<K: Key, T> ClusterBuilder<K, T> {
+    /// new describes a cluster of one server and one storage node, without split keys.
+    pub fn new(transport: T) -> Self {
+        Self {
+            servers: 1,
+            storage_nodes: 1,
+            split_keys: vec![],
+            replication: 1,
+            transport,
+            network: None,
+        }
+    }
+
+    pub fn servers(mut self, n: usize) -> Self {
+        self.servers = n;
+        self
+    }
+
+    pub fn storage_nodes(mut self, n: usize) -> Self {
+        self.storage_nodes = n;
+        self
+    }
+
+    pub fn split_keys(mut self, mut keys: Vec<K>) -> Self {
+        keys.sort();
+        self.split_keys = keys;
+        self
+    }
+
+    /// replication is the number of replicas of every region,
+    /// it can't be more than the number of storage nodes.
+    pub fn replication(mut self, n: usize) -> Self {
+        self.replication = n;
+        self
+    }
+
+    /// network injects the faults into the requests to the members and from the servers
+    /// to the storage nodes, it must use the clock of the transport,
+    /// e.g. `Network::new(seed, sim.clone())` when the transport is a `Simulator`.
+    pub fn network(mut self, network: Network) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    fn validate(&self) -> Result<()> {
+        let error = |msg: String| Err(Error::ConfigError(msg));
+        if self.servers == 0 {
+            return error("no servers".to_owned());
+        }
+        if let Some(keys) = self.split_keys.windows(2).find(|keys| keys[0] == keys[1]) {
+            return error(format!("duplicate split key {}", keys[0].to_string()));
+        }
+        if self.replication == 0 || self.replication > self.storage_nodes {
+            return error(format!(
+                "replication {} with {} storage nodes",
+                self.replication, self.storage_nodes
+            ));
+        }
+        Ok(())
+    }
+
+    /// build creates the nodes by `new_node` and `new_server`, and connects them by the transport,
+    /// it fails with `Error::ConfigError` if the topology is invalid.
+    /// The storage nodes take the ids from 0, followed by the servers.
+    /// The senders to the storage nodes are owned by the shards of the servers and never closed,
+    /// so the receivers spawned for the storage nodes are not stopped by `remove`.
+    pub async fn build<N, S, NF, SF>(
+        self,
+        mut new_node: NF,
+        mut new_server: SF,
+    ) -> Result<Cluster<N, S, S::Req, S::Res, <T as Transport<S>>::S>>
+    where
+        N: Node + Send + Sync,
+        N::Req: 'static,
+        S: Server<S = ReplicatedKeySpace<K, <T as Transport<N>>::S>> + Send + Sync,
+        T: Transport<N> + Transport<S>,
+        <T as Transport<N>>::S: Sync,
+        NF: FnMut() -> N,
+        SF: FnMut() -> S,
+    {
+        self.validate()?;
+
+        let mut cluster = match self.network {
+            Some(network) => Cluster::with_network(network)?,
+            None => Cluster::new()?,
+        };
+        let mut nodes = Vec::with_capacity(self.storage_nodes);
+        for _ in 0..self.storage_nodes {
+            let node = Arc::new(new_node());
+            nodes.push((cluster.join_storage(node.clone()), node));
+        }
+
+        for _ in 0..self.servers {
+            // every server has its own senders to the storage nodes,
+            // so the partitions between the members apply to them.
+            let id = cluster.next_id();
+            let mut groups = Vec::with_capacity(self.split_keys.len() + 1);
+            for i in 0..=self.split_keys.len() {
+                let mut replicas = Vec::with_capacity(self.replication);
+                for j in 0..self.replication {
+                    let (node_id, node) = &nodes[(i + j) % nodes.len()];
+                    let sender = Transport::<N>::connect(&self.transport, node.clone()).await?;
+                    replicas.push(cluster.wrap(sender, id, *node_id));
+                }
+                groups.push(ReplicaGroup::new(replicas));
+            }
+            let mut groups = groups.into_iter();
+            let mut shard = KeySpaceSpilt::with_node(groups.next().unwrap());
+            for (key, group) in self.split_keys.iter().zip(groups) {
+                shard.split(key.to_owned(), Either::Right(group))?;
+            }
+
+            let mut server = new_server();
+            server.register_shard(Arc::new(shard));
+            let server = Arc::new(server);
+            let sender = Transport::<S>::connect(&self.transport, server.clone()).await?;
+            cluster.join_server(sender, server);
+        }
+        Ok(cluster)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::test::{KvNode, KvServer};
+    use crate::cluster::Role;
+    use crate::request::channel::ChannelTransport;
+    use crate::request::fault::FaultConfig;
+    use crate::request::tcp::TcpTransport;
+    use crate::sim::Simulator;
+    use crate::storage::{Engine, InMemEngine};
+    use crate::util::test::run_in_tokio;
+    use crate::util::RequestError;
+    use std::time::Duration;
+
+    async fn check_topology<T>(builder: ClusterBuilder<i32, T>)
+    where
+        T: Transport<KvNode>,
+        T: Transport<KvServer<<T as Transport<KvNode>>::S>>,
+        <T as Transport<KvNode>>::S: Sync,
+        <T as Transport<KvServer<<T as Transport<KvNode>>::S>>>::S: Sync,
+    {
+        let cluster = builder
+            .servers(2)
+            .storage_nodes(3)
+            .split_keys(vec![100, 0, 200])
+            .replication(2)
+            .build(
+                || KvNode {
+                    engine: InMemEngine::new(),
+                },
+                || KvServer {
+                    shard: Arc::new(KeySpaceSpilt::new()),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(cluster.ids(Role::Storage), vec![0, 1, 2]);
+        assert_eq!(cluster.ids(Role::Server), vec![3, 4]);
+        assert_eq!(
+            cluster.server(3).unwrap().shard.boundaries(),
+            vec![0, 100, 200]
+        );
+
+        for key in [-1, 50, 150, 250] {
+            assert_eq!(cluster.send(3, (key, Some(key))).await.unwrap(), Some(key));
+        }
+        for key in [-1, 50, 150, 250] {
+            assert_eq!(cluster.send(4, (key, None)).await.unwrap(), Some(key));
+        }
+        // the regions [.., 0), [0, 100), [100, 200) and [200, ..)
+        // are on the nodes {0, 1}, {1, 2}, {2, 0} and {0, 1}.
+        let keys = |id| {
+            let node = cluster.node(id).unwrap();
+            node.engine.scan(&i32::MIN, &i32::MAX).unwrap()
+        };
+        assert_eq!(keys(0), vec![-1, 150, 250]);
+        assert_eq!(keys(1), vec![-1, 50, 250]);
+        assert_eq!(keys(2), vec![50, 150]);
+        std::mem::forget(cluster);
+    }
+
+    #[test]
+    fn test_build() {
+        run_in_tokio(check_topology(ClusterBuilder::new(ChannelTransport)));
+        run_in_tokio(check_topology(ClusterBuilder::new(TcpTransport::default())));
+    }
+
+    #[test]
+    fn test_invalid_topology() {
+        run_in_tokio(async move {
+            let build = |builder: ClusterBuilder<i32, ChannelTransport>| async move {
+                let res = builder
+                    .build(
+                        || KvNode {
+                            engine: InMemEngine::new(),
+                        },
+                        || KvServer {
+                            shard: Arc::new(KeySpaceSpilt::new()),
+                        },
+                    )
+                    .await;
+                res.err().unwrap().to_string()
+            };
+            let builder = || ClusterBuilder::new(ChannelTransport).split_keys(vec![0]);
+            assert_eq!(build(builder().servers(0)).await, "config error no servers");
+            assert_eq!(
+                build(builder().split_keys(vec![1, 0, 1])).await,
+                "config error duplicate split key 1"
+            );
+            assert_eq!(
+                build(builder().storage_nodes(2).replication(3)).await,
+                "config error replication 3 with 2 storage nodes"
+            );
+        });
+    }
+
+    #[test]
+    fn test_replace_storage() {
+        run_in_tokio(async move {
+            let mut cluster = ClusterBuilder::new(ChannelTransport)
+                .storage_nodes(2)
+                .split_keys(vec![0])
+                .build(
+                    || KvNode {
+                        engine: InMemEngine::new(),
+                    },
+                    || KvServer {
+                        shard: Arc::new(KeySpaceSpilt::new()),
+                    },
+                )
+                .await
+                .unwrap();
+            // the storage nodes of the builder have no senders in the cluster.
+            let node = Arc::new(KvNode {
+                engine: InMemEngine::new(),
+            });
+            let sender = ChannelTransport.connect(node.clone()).await.unwrap();
+            let old = cluster.replace_node(1, sender, node.clone()).unwrap();
+            assert!(old.is_none());
+            assert!(Arc::ptr_eq(&cluster.node(1).unwrap(), &node));
+            assert_eq!(cluster.send(1, (7, Some(7))).await.unwrap(), Some(7));
+            std::mem::forget(cluster);
+        });
+    }
+
+    #[test]
+    fn test_partition_storage() {
+        run_in_tokio(async move {
+            let cluster = ClusterBuilder::new(ChannelTransport)
+                .servers(2)
+                .build(
+                    || KvNode {
+                        engine: InMemEngine::new(),
+                    },
+                    || KvServer {
+                        shard: Arc::new(KeySpaceSpilt::new()),
+                    },
+                )
+                .await
+                .unwrap();
+            // there is one region without split keys.
+            assert!(cluster.server(1).unwrap().shard.boundaries().is_empty());
+            cluster.set_fault_config(FaultConfig {
+                timeout: Duration::from_millis(10),
+                ..FaultConfig::default()
+            });
+            // only the server 1 is cut off from the storage node.
+            cluster.partition(&[1], &[0], None);
+            match cluster.send(1, (1, Some(1))).await.unwrap_err() {
+                Error::RequestError(RequestError::NetworkError(_)) => {}
+                e => panic!("unexpected error {:?}", e),
+            }
+            assert_eq!(cluster.send(2, (1, Some(1))).await.unwrap(), Some(1));
+            cluster.heal();
+            assert_eq!(cluster.send(1, (1, None)).await.unwrap(), Some(1));
+            std::mem::forget(cluster);
+        });
+    }
+
+    #[test]
+    fn test_build_simulated() {
+        let sim = Simulator::new(0);
+        let builder = ClusterBuilder::new(sim.clone()).network(Network::new(0, sim.clone()));
+        sim.block_on(check_topology(builder));
+        sim.shutdown();
+    }
+}
@@ -1,13 +1,64 @@
+use crate::bench::{self, BenchConfig, Report, Workload};
+use crate::client::{InteractiveTxnClient, TxnRequest, TxnResponse};
+use crate::codec::{Key, Value};
 use crate::node::{Node, Server};
+use crate::request::fault::{FaultConfig, FaultSender, Network};
 use crate::request::{Request, Response, Sender};
+use crate::sim::Simulator;
 use crate::storage::Engine;
-use crate::txn::Txn;
-use crate::util::{RequestError, Result};
+use crate::tso::TSONode;
+use crate::util::{RequestError, Result, Timer, TokioTimer};
 use std::collections::BTreeMap;
+use std::fmt;
 use std::marker::PhantomData;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
-pub struct Cluster<'a, N, S, Req, Res, SE>
+mod builder;
+pub use builder::{ClusterBuilder, ReplicatedKeySpace};
+
+#[cfg(test)]
+mod test;
+
+/// Role is what a member does in the cluster.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Server,
+    Storage,
+    Tso,
+}
+
+enum Member<N, S> {
+    Server(Arc<S>),
+    Storage(Arc<N>),
+    Tso(Arc<TSONode>),
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Role::Server => "server",
+            Role::Storage => "storage node",
+            Role::Tso => "tso",
+        };
+        f.write_str(name)
+    }
+}
+
+impl<N, S> Member<N, S> {
+    fn role(&self) -> Role {
+        match self {
+            Member::Server(_) => Role::Server,
+            Member::Storage(_) => Role::Storage,
+            Member::Tso(_) => Role::Tso,
+        }
+    }
+}
+
+/// Cluster is the registry of the nodes, every member gets an id when it joins,
+/// and the ids are not reused after the members are removed.
+pub struct Cluster<N, S, Req, Res, SE>
 where
     N: Node,
     S: Server,
@@ -16,13 +67,15 @@ where
     SE: Sender,
 {
     phantom: PhantomData<(Req, Res)>,
-    nodes: Vec<&'a N>,
-    servers: Vec<&'a S>,
-    map: BTreeMap<u64, SE>,
+    members: BTreeMap<u64, Member<N, S>>,
+    // the tso is accessed by `TSOClient`s, so it has no sender here,
+    // nor the storage nodes joined by `join_storage`.
+    map: BTreeMap<u64, FaultSender<SE>>,
     id: AtomicU64,
+    network: Network,
 }
 
-impl<'a, N, S, Req, Res, SE> Cluster<'a, N, S, Req, Res, SE>
+impl<N, S, Req, Res, SE> Cluster<N, S, Req, Res, SE>
 where
     N: Node,
     S: Server,
@@ -30,39 +83,432 @@ where
     Res: Response,
     SE: Sender<Res = Res, Req = Req>,
 {
+    /// CLIENT is the id of the cluster itself when it sends requests to the members,
+    /// e.g. `partition(&[Cluster::CLIENT], &[1], None)` makes the member 1 unreachable.
+    pub const CLIENT: u64 = u64::MAX;
+
     pub fn new() -> Result<Self> {
+        Self::with_network(Network::new(0, TokioTimer::new()))
+    }
+
+    /// with_network creates a cluster whose faults are injected by `network`,
+    /// the senders of the joined members are wrapped by `Network::wrap`,
+    /// and the senders between the members should be wrapped by `wrap`.
+    pub fn with_network(network: Network) -> Result<Self> {
         Ok(Cluster {
-            nodes: vec![],
-            servers: vec![],
+            members: BTreeMap::new(),
             phantom: PhantomData,
             id: AtomicU64::new(0),
             map: BTreeMap::new(),
+            network,
         })
     }
 
+    /// with_simulator creates a cluster whose network runs on the virtual clock of `sim`,
+    /// the members should be connected by the simulator too, e.g. `sim.connect(node)`.
+    pub fn with_simulator(sim: &Simulator) -> Result<Self> {
+        Self::with_network(Network::new(sim.gen_range(0, u64::MAX), sim.clone()))
+    }
+
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    pub fn set_fault_config(&self, config: FaultConfig) {
+        self.network.set_config(config);
+    }
+
+    /// partition isolates the nodes in `left` from the nodes in `right`,
+    /// e.g. `partition(&[1, 2], &[3], Some(Duration::from_millis(500)))`.
+    pub fn partition(&self, left: &[u64], right: &[u64], duration: Option<Duration>) {
+        self.network.partition(left, right, duration);
+    }
+
+    pub fn heal(&self) {
+        self.network.heal();
+    }
+
+    /// bench runs the workload against the servers of the cluster.
+    pub async fn bench<W, T>(&self, workload: &W, config: &BenchConfig, timer: &T) -> Result<Report>
+    where
+        W: Workload<Server = S>,
+        T: Timer,
+    {
+        let servers = self.servers();
+        let servers: Vec<&S> = servers.iter().map(|s| s.as_ref()).collect();
+        bench::run(workload, &servers, config, timer).await
+    }
+
+    /// wrap injects the faults of the network into the sender from the member `from`
+    /// to the member `to`, e.g. the shard of a server reaches the storage nodes by such senders,
+    /// so that the partitions between the members apply to them.
+    pub fn wrap<T: Sender>(&self, sender: T, from: u64, to: u64) -> FaultSender<T> {
+        self.network.wrap(sender, from, to)
+    }
+
+    /// next_id is the id of the next member to join,
+    /// so the senders of a member can be wrapped before it joins.
+    pub fn next_id(&self) -> u64 {
+        self.id.load(Ordering::Relaxed)
+    }
+
     fn get_node_id(&mut self) -> u64 {
         self.id.fetch_add(1, Ordering::Relaxed)
     }
 
-    fn join_node(&mut self, sender: SE, n: &'a mut N) {
-        self.nodes.push(n);
+    /// join_node adds a storage node, which is reached by `sender`.
+    pub fn join_node(&mut self, sender: SE, n: Arc<N>) -> u64 {
+        let id = self.get_node_id();
+        self.members.insert(id, Member::Storage(n));
+        self.map
+            .insert(id, self.network.wrap(sender, Self::CLIENT, id));
+        id
+    }
+
+    /// join_storage adds a storage node which is only reached by the shards of the servers,
+    /// so it can't be sent to by the cluster.
+    pub fn join_storage(&mut self, n: Arc<N>) -> u64 {
         let id = self.get_node_id();
-        self.map.insert(id, sender);
+        self.members.insert(id, Member::Storage(n));
+        id
     }
 
-    fn join_server(&mut self, sender: SE, s: &'a mut S) {
-        self.servers.push(s);
+    /// join_server adds a server, which is reached by `sender`.
+    pub fn join_server(&mut self, sender: SE, s: Arc<S>) -> u64 {
         let id = self.get_node_id();
-        self.map.insert(id, sender);
+        self.members.insert(id, Member::Server(s));
+        self.map
+            .insert(id, self.network.wrap(sender, Self::CLIENT, id));
+        id
+    }
+
+    pub fn join_tso(&mut self, tso: Arc<TSONode>) -> u64 {
+        let id = self.get_node_id();
+        self.members.insert(id, Member::Tso(tso));
+        id
+    }
+
+    /// remove closes the sender of the member and returns it.
+    pub fn remove(&mut self, id: u64) -> Result<Option<SE>> {
+        self.members
+            .remove(&id)
+            .ok_or(RequestError::NodeNotFound(id))?;
+        Ok(self.map.remove(&id).map(|sender| {
+            let mut sender = sender.into_inner();
+            sender.close();
+            sender
+        }))
+    }
+
+    /// replace_node swaps the storage node `id` for a new one with the same id,
+    /// and returns the closed sender of the old one,
+    /// which is `None` if the old one joined by `join_storage`.
+    pub fn replace_node(&mut self, id: u64, sender: SE, n: Arc<N>) -> Result<Option<SE>> {
+        self.node(id)?;
+        self.members.insert(id, Member::Storage(n));
+        Ok(self.replace_sender(id, sender))
+    }
+
+    /// replace_server swaps the server `id` for a new one with the same id,
+    /// and returns the closed sender of the old one.
+    pub fn replace_server(&mut self, id: u64, sender: SE, s: Arc<S>) -> Result<Option<SE>> {
+        self.server(id)?;
+        self.members.insert(id, Member::Server(s));
+        Ok(self.replace_sender(id, sender))
+    }
+
+    fn replace_sender(&mut self, id: u64, sender: SE) -> Option<SE> {
+        let sender = self.network.wrap(sender, Self::CLIENT, id);
+        self.map.insert(id, sender).map(|old| {
+            let mut old = old.into_inner();
+            old.close();
+            old
+        })
+    }
+
+    pub fn role(&self, id: u64) -> Result<Role> {
+        match self.members.get(&id) {
+            Some(m) => Ok(m.role()),
+            None => Err(RequestError::NodeNotFound(id).into()),
+        }
+    }
+
+    /// ids returns the ids of the members in `role`, in the order they joined.
+    pub fn ids(&self, role: Role) -> Vec<u64> {
+        self.members
+            .iter()
+            .filter(|(_, m)| m.role() == role)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    pub fn node(&self, id: u64) -> Result<Arc<N>> {
+        match self.members.get(&id) {
+            Some(Member::Storage(n)) => Ok(n.clone()),
+            _ => Err(RequestError::NodeNotFound(id).into()),
+        }
+    }
+
+    pub fn server(&self, id: u64) -> Result<Arc<S>> {
+        match self.members.get(&id) {
+            Some(Member::Server(s)) => Ok(s.clone()),
+            _ => Err(RequestError::NodeNotFound(id).into()),
+        }
+    }
+
+    pub fn tso(&self, id: u64) -> Result<Arc<TSONode>> {
+        match self.members.get(&id) {
+            Some(Member::Tso(t)) => Ok(t.clone()),
+            _ => Err(RequestError::NodeNotFound(id).into()),
+        }
+    }
+
+    pub fn nodes(&self) -> Vec<Arc<N>> {
+        self.members
+            .values()
+            .filter_map(|m| match m {
+                Member::Storage(n) => Some(n.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn servers(&self) -> Vec<Arc<S>> {
+        self.members
+            .values()
+            .filter_map(|m| match m {
+                Member::Server(s) => Some(s.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn sender(&self, id: u64) -> Result<&FaultSender<SE>> {
+        self.map
+            .get(&id)
+            .ok_or_else(|| RequestError::NodeNotFound(id).into())
+    }
+
+    /// send sends `req` to the member `id`, the errors of the sender are returned as they are.
+    pub async fn send(&self, id: u64, req: Req) -> Result<Res>
+    where
+        SE: Sync,
+        Req: 'static,
+    {
+        self.sender(id)?.send(req).await
     }
+}
 
-    pub async fn send(&mut self, id: u64, req: Req) -> Result<Res> {
-        match self.map.get_mut(&id) {
-            Some(n) => n
-                .send(req)
-                .await
-                .map_err(|e| RequestError::SendError(e.to_string()).into()),
-            None => panic!("node not found"),
+impl<N, S, K, V, SE> Cluster<N, S, TxnRequest<K, V>, TxnResponse<K, V>, SE>
+where
+    N: Node,
+    S: Server,
+    K: Key + Send,
+    V: Value + Send,
+    SE: Sender<Req = TxnRequest<K, V>, Res = TxnResponse<K, V>> + Sync,
+    TxnRequest<K, V>: 'static,
+{
+    /// client creates an interactive txn client, whose requests go through the server `id`,
+    /// it fails with `RequestError::RoleMismatch` if the member isn't a server.
+    pub fn client(&self, id: u64) -> Result<InteractiveTxnClient<'_, FaultSender<SE>>> {
+        let actual = self.role(id)?;
+        if actual != Role::Server {
+            let expected = Role::Server;
+            return Err(RequestError::RoleMismatch {
+                id,
+                expected,
+                actual,
+            }
+            .into());
         }
+        Ok(InteractiveTxnClient::new(self.sender(id)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test::{KvNode, KvServer};
+    use super::*;
+    use crate::client::Client;
+    use crate::request::channel::{new_channel_connect, ChannelSender};
+    use crate::shard::{KeySpaceSpilt, ReplicaGroup, Shard};
+    use crate::sim::SimSender;
+    use crate::storage::InMemEngine;
+    use crate::txn::percolator::test::{new_server, TestServer};
+    use crate::txn::percolator::SessionServer;
+    use crate::util::test::run_in_tokio;
+    use crate::util::Either;
+    use crate::util::Error;
+
+    type Server = SessionServer<TestServer>;
+    type TestCluster = Cluster<
+        Server,
+        Server,
+        TxnRequest<i32, i32>,
+        TxnResponse<i32, i32>,
+        ChannelSender<TxnRequest<i32, i32>, TxnResponse<i32, i32>>,
+    >;
+
+    #[test]
+    fn test_membership() {
+        run_in_tokio(async move {
+            let mut cluster = TestCluster::new().unwrap();
+            let server = Arc::new(SessionServer::new(new_server()));
+            let s1 = cluster.join_server(new_channel_connect(server.clone()), server);
+            let tso = cluster.join_tso(Arc::new(TSONode::new()));
+            let server = Arc::new(SessionServer::new(new_server()));
+            let s2 = cluster.join_server(new_channel_connect(server.clone()), server);
+            assert_eq!((s1, tso, s2), (0, 1, 2));
+            assert_eq!(cluster.ids(Role::Server), vec![0, 2]);
+            assert_eq!(cluster.ids(Role::Tso), vec![1]);
+            assert_eq!(cluster.role(tso).unwrap(), Role::Tso);
+            assert!(cluster.nodes().is_empty());
+
+            let txn = cluster.client(s2).unwrap().begin().await.unwrap();
+            txn.put(1, 1).await.unwrap();
+            txn.commit().await.unwrap();
+
+            let not_found = |id| Error::RequestError(RequestError::NodeNotFound(id));
+            let removed = cluster.remove(s2).unwrap();
+            assert!(removed.is_some());
+            assert_eq!(cluster.ids(Role::Server), vec![0]);
+            assert_eq!(cluster.client(s2).err().unwrap(), not_found(s2));
+            assert_eq!(
+                cluster.send(s2, TxnRequest::Begin).await.unwrap_err(),
+                not_found(s2)
+            );
+            // the tso is not a server.
+            let mismatch = cluster.client(tso).err().unwrap();
+            assert_eq!(
+                mismatch,
+                RequestError::RoleMismatch {
+                    id: tso,
+                    expected: Role::Server,
+                    actual: Role::Tso,
+                }
+                .into()
+            );
+            assert_eq!(
+                mismatch.to_string(),
+                "request error node 1 is a tso, not a server"
+            );
+            assert!(cluster.remove(tso).unwrap().is_none());
+            assert_eq!(cluster.remove(tso).err().unwrap(), not_found(tso));
+
+            // the replaced server keeps its id.
+            let server = Arc::new(SessionServer::new(new_server()));
+            let old = cluster
+                .replace_server(s1, new_channel_connect(server.clone()), server.clone())
+                .unwrap();
+            assert!(old.is_some());
+            assert!(Arc::ptr_eq(&cluster.server(s1).unwrap(), &server));
+            let txn = cluster.client(s1).unwrap().begin().await.unwrap();
+            assert_eq!(txn.get(1).await.unwrap(), None);
+            txn.commit().await.unwrap();
+            assert_eq!(cluster.node(s1).err().unwrap(), not_found(s1));
+            std::mem::forget(old);
+            std::mem::forget(removed);
+            std::mem::forget(cluster);
+        });
+    }
+
+    #[test]
+    fn test_partition() {
+        run_in_tokio(async move {
+            let mut cluster = TestCluster::new().unwrap();
+            cluster.set_fault_config(FaultConfig {
+                timeout: Duration::from_millis(10),
+                ..FaultConfig::default()
+            });
+            let server = Arc::new(SessionServer::new(new_server()));
+            let s1 = cluster.join_server(new_channel_connect(server.clone()), server);
+            let server = Arc::new(SessionServer::new(new_server()));
+            let s2 = cluster.join_server(new_channel_connect(server.clone()), server);
+
+            cluster.partition(&[TestCluster::CLIENT], &[s1], None);
+            match cluster.send(s1, TxnRequest::Begin).await.unwrap_err() {
+                Error::RequestError(RequestError::NetworkError(_)) => {}
+                e => panic!("unexpected error {:?}", e),
+            }
+            assert!(cluster.client(s1).unwrap().begin().await.is_err());
+            assert!(cluster.send(s2, TxnRequest::Begin).await.is_ok());
+
+            cluster.heal();
+            let txn = cluster.client(s1).unwrap().begin().await.unwrap();
+            txn.put(1, 1).await.unwrap();
+            txn.commit().await.unwrap();
+            std::mem::forget(cluster);
+        });
+    }
+
+    #[test]
+    fn test_partition_members() {
+        type KvSender = ChannelSender<(i32, Option<i32>), Option<i32>>;
+        type KvCluster =
+            Cluster<KvNode, KvServer<KvSender>, (i32, Option<i32>), Option<i32>, KvSender>;
+        run_in_tokio(async move {
+            let mut cluster = KvCluster::new().unwrap();
+            cluster.set_fault_config(FaultConfig {
+                timeout: Duration::from_millis(10),
+                ..FaultConfig::default()
+            });
+            let node = Arc::new(KvNode {
+                engine: InMemEngine::new(),
+            });
+            let n = cluster.join_storage(node.clone());
+            // the shard of the server reaches the storage node through the network.
+            let s = cluster.next_id();
+            let replica = cluster.wrap(new_channel_connect(node), s, n);
+            let mut shard = KeySpaceSpilt::new();
+            shard
+                .split(0, Either::Left(ReplicaGroup::new(vec![replica])))
+                .unwrap();
+            let server = Arc::new(KvServer {
+                shard: Arc::new(shard),
+            });
+            assert_eq!(
+                cluster.join_server(new_channel_connect(server.clone()), server),
+                s
+            );
+            assert_eq!(cluster.send(s, (1, Some(1))).await.unwrap(), Some(1));
+
+            cluster.partition(&[s], &[n], None);
+            match cluster.send(s, (1, None)).await.unwrap_err() {
+                Error::RequestError(RequestError::NetworkError(_)) => {}
+                e => panic!("unexpected error {:?}", e),
+            }
+            cluster.heal();
+            assert_eq!(cluster.send(s, (1, None)).await.unwrap(), Some(1));
+            std::mem::forget(cluster);
+        });
+    }
+
+    #[test]
+    fn test_simulator() {
+        type SimCluster = Cluster<
+            KvNode,
+            KvServer<SimSender<(i32, Option<i32>), Option<i32>>>,
+            (i32, Option<i32>),
+            Option<i32>,
+            SimSender<(i32, Option<i32>), Option<i32>>,
+        >;
+        let sim = Simulator::new(0);
+        let s = sim.clone();
+        sim.block_on(async move {
+            let mut cluster = SimCluster::with_simulator(&s).unwrap();
+            let node = Arc::new(KvNode {
+                engine: InMemEngine::new(),
+            });
+            let id = cluster.join_node(s.connect(node.clone()), node);
+
+            // the lost request times out on the virtual clock.
+            cluster.partition(&[SimCluster::CLIENT], &[id], Some(Duration::from_secs(5)));
+            let start = s.now();
+            assert!(cluster.send(id, (1, Some(1))).await.is_err());
+            assert_eq!(s.now() - start, Duration::from_secs(1));
+            s.sleep(Duration::from_secs(5)).await;
+            assert_eq!(cluster.send(id, (1, Some(1))).await.unwrap(), Some(1));
+        });
+        sim.shutdown();
     }
 }
@@ -0,0 +1,63 @@
+use crate::cluster::ReplicatedKeySpace;
+use crate::node::{Node, Server};
+use crate::request::Sender;
+use crate::shard::ReplicatedShard;
+use crate::storage::{Engine, InMemEngine};
+use crate::util::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// KvNode puts the value if there is one, and returns the value of the key.
+pub struct KvNode {
+    pub engine: InMemEngine<i32, i32>,
+}
+
+#[async_trait]
+impl Node for KvNode {
+    type Req = (i32, Option<i32>);
+    type Res = Option<i32>;
+
+    async fn process(&self, (key, value): Self::Req) -> Result<Self::Res> {
+        if let Some(value) = value {
+            self.engine.put(key, value)?;
+        }
+        self.engine.get(&key)
+    }
+}
+
+/// KvServer writes to all the replicas, and reads from the leader.
+pub struct KvServer<SE>
+where
+    SE: Sender<Req = (i32, Option<i32>), Res = Option<i32>> + Sync,
+{
+    pub shard: Arc<ReplicatedKeySpace<i32, SE>>,
+}
+
+#[async_trait]
+impl<SE> Node for KvServer<SE>
+where
+    SE: Sender<Req = (i32, Option<i32>), Res = Option<i32>> + Sync,
+{
+    type Req = (i32, Option<i32>);
+    type Res = Option<i32>;
+
+    async fn process(&self, req: Self::Req) -> Result<Self::Res> {
+        if req.1.is_none() {
+            return self.shard.key2leader(&req.0).send(req).await;
+        }
+        let results = self.shard.key2group(&req.0).send_all(req).await;
+        let mut results = results.into_iter().collect::<Result<Vec<_>>>()?;
+        Ok(results.pop().unwrap())
+    }
+}
+
+impl<SE> Server for KvServer<SE>
+where
+    SE: Sender<Req = (i32, Option<i32>), Res = Option<i32>> + Sync,
+{
+    type S = ReplicatedKeySpace<i32, SE>;
+
+    fn register_shard(&mut self, s: Arc<Self::S>) {
+        self.shard = s;
+    }
+}
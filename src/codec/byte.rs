@@ -1,8 +1,9 @@
-use crate::codec::{Key, Value};
+use crate::codec::{Encode, Key, Value};
+use crate::util::Result;
 use std::borrow::ToOwned;
 use std::cmp::Ordering;
 
-#[derive(Eq, PartialEq)]
+#[derive(Eq, PartialEq, Hash)]
 pub struct ByteKey {
     inner: Vec<u8>,
 }
@@ -56,6 +57,10 @@ impl ByteKey {
     pub fn new(bytes: &[u8]) -> ByteKey {
         ByteKey { inner: bytes.to_owned() }
     }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.inner
+    }
 }
 
 impl Value for ByteValue {}
@@ -78,3 +83,29 @@ impl ToString for ByteValue {
         }
     }
 }
+
+impl ByteValue {
+    pub fn new(bytes: &[u8]) -> ByteValue {
+        ByteValue { inner: bytes.to_owned() }
+    }
+}
+
+impl Encode for ByteKey {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.inner.encode(buf);
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        Ok(ByteKey { inner: Vec::decode(buf)? })
+    }
+}
+
+impl Encode for ByteValue {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.inner.encode(buf);
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        Ok(ByteValue { inner: Vec::decode(buf)? })
+    }
+}
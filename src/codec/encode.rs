@@ -0,0 +1,212 @@
+use crate::util::{CodecError, Error, Result};
+
+/// Encode converts the data from and to bytes, to persist it or send it over the network.
+pub trait Encode: Sized {
+    fn encode(&self, buf: &mut Vec<u8>);
+    /// decode reads a value from the front of `buf`, and advances `buf` past it.
+    fn decode(buf: &mut &[u8]) -> Result<Self>;
+    /// encoded_len is the size of the value on the wire.
+    fn encoded_len(&self) -> usize {
+        let mut buf = vec![];
+        self.encode(&mut buf);
+        buf.len()
+    }
+}
+
+/// take_bytes splits the first `n` bytes off `buf`.
+pub fn take_bytes<'a>(buf: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    if buf.len() < n {
+        return Err(CodecError::UnexpectedEof.into());
+    }
+    let (bytes, rest) = buf.split_at(n);
+    *buf = rest;
+    Ok(bytes)
+}
+
+macro_rules! impl_encode_int {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for $t {
+                fn encode(&self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_be_bytes());
+                }
+
+                fn decode(buf: &mut &[u8]) -> Result<Self> {
+                    let bytes = take_bytes(buf, std::mem::size_of::<$t>())?;
+                    let mut arr = [0; std::mem::size_of::<$t>()];
+                    arr.copy_from_slice(bytes);
+                    Ok(<$t>::from_be_bytes(arr))
+                }
+            }
+        )*
+    };
+}
+
+impl_encode_int!(u8, u16, u32, u64, i32, i64);
+
+impl Encode for usize {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as u64).encode(buf);
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        Ok(u64::decode(buf)? as usize)
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as u8).encode(buf);
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        match u8::decode(buf)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            v => Err(CodecError::InvalidData(format!("invalid bool {}", v)).into()),
+        }
+    }
+}
+
+impl Encode for () {
+    fn encode(&self, _: &mut Vec<u8>) {}
+
+    fn decode(_: &mut &[u8]) -> Result<Self> {
+        Ok(())
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u32).encode(buf);
+        self.iter().for_each(|t| t.encode(buf));
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        let len = u32::decode(buf)? as usize;
+        // the length may be broken, don't trust it to allocate.
+        let mut res = Vec::with_capacity(len.min(buf.len()));
+        for _ in 0..len {
+            res.push(T::decode(buf)?);
+        }
+        Ok(res)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Some(t) => {
+                true.encode(buf);
+                t.encode(buf);
+            }
+            None => false.encode(buf),
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        if bool::decode(buf)? {
+            Ok(Some(T::decode(buf)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+        self.1.encode(buf);
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        let a = A::decode(buf)?;
+        Ok((a, B::decode(buf)?))
+    }
+}
+
+impl<A: Encode, B: Encode, C: Encode> Encode for (A, B, C) {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+        self.1.encode(buf);
+        self.2.encode(buf);
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        let a = A::decode(buf)?;
+        let b = B::decode(buf)?;
+        Ok((a, b, C::decode(buf)?))
+    }
+}
+
+impl<T: Encode> Encode for Result<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Ok(t) => {
+                0u8.encode(buf);
+                t.encode(buf);
+            }
+            Err(e) => {
+                1u8.encode(buf);
+                e.encode(buf);
+            }
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        match u8::decode(buf)? {
+            0 => Ok(Ok(T::decode(buf)?)),
+            1 => Ok(Err(Error::decode(buf)?)),
+            v => Err(CodecError::InvalidData(format!("invalid result tag {}", v)).into()),
+        }
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u32).encode(buf);
+        buf.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        let len = u32::decode(buf)? as usize;
+        let bytes = take_bytes(buf, len)?.to_vec();
+        String::from_utf8(bytes).map_err(|e| CodecError::InvalidData(e.to_string()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::byte::{ByteKey, ByteValue};
+    use crate::util::Error;
+
+    #[test]
+    fn test_encode() {
+        let mut buf = vec![];
+        7u8.encode(&mut buf);
+        (-3i32).encode(&mut buf);
+        u64::MAX.encode(&mut buf);
+        b"abc".to_vec().encode(&mut buf);
+        "中文".to_owned().encode(&mut buf);
+        ByteKey::new(b"key").encode(&mut buf);
+        ByteValue::new(b"").encode(&mut buf);
+
+        let mut r = buf.as_slice();
+        assert_eq!(u8::decode(&mut r).unwrap(), 7);
+        assert_eq!(i32::decode(&mut r).unwrap(), -3);
+        assert_eq!(u64::decode(&mut r).unwrap(), u64::MAX);
+        assert_eq!(Vec::<u8>::decode(&mut r).unwrap(), b"abc".to_vec());
+        assert_eq!(String::decode(&mut r).unwrap(), "中文");
+        assert!(ByteKey::decode(&mut r).unwrap() == ByteKey::new(b"key"));
+        assert_eq!(ByteValue::decode(&mut r).unwrap().to_string(), "");
+        assert!(r.is_empty());
+
+        let mut r = &buf[..3];
+        assert_eq!(u8::decode(&mut r).unwrap(), 7);
+        assert_eq!(
+            i32::decode(&mut r).unwrap_err(),
+            Error::CodecError(CodecError::UnexpectedEof)
+        );
+    }
+}
@@ -0,0 +1,378 @@
+use crate::cluster::Role;
+use crate::codec::Encode;
+use crate::util::{
+    CodecError, ConsensusError, Error, RequestError, Result, ShardError, StorageError, TSOError,
+    TxnError,
+};
+use std::time::Duration;
+
+fn invalid_tag<T>(name: &str, tag: u8) -> Result<T> {
+    Err(CodecError::InvalidData(format!("invalid {} tag {}", name, tag)).into())
+}
+
+impl Encode for Error {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Error::TSOError(e) => {
+                0u8.encode(buf);
+                e.encode(buf);
+            }
+            Error::RequestError(e) => {
+                1u8.encode(buf);
+                e.encode(buf);
+            }
+            Error::ShardError(e) => {
+                2u8.encode(buf);
+                e.encode(buf);
+            }
+            Error::TxnError(e) => {
+                3u8.encode(buf);
+                e.encode(buf);
+            }
+            Error::ConsensusError(e) => {
+                4u8.encode(buf);
+                e.encode(buf);
+            }
+            Error::CodecError(e) => {
+                5u8.encode(buf);
+                e.encode(buf);
+            }
+            Error::StorageError(e) => {
+                6u8.encode(buf);
+                e.encode(buf);
+            }
+            Error::Unknown => 7u8.encode(buf),
+            Error::ConfigError(s) => {
+                8u8.encode(buf);
+                s.encode(buf);
+            }
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        let e = match u8::decode(buf)? {
+            0 => Error::TSOError(TSOError::decode(buf)?),
+            1 => Error::RequestError(RequestError::decode(buf)?),
+            2 => Error::ShardError(ShardError::decode(buf)?),
+            3 => Error::TxnError(TxnError::decode(buf)?),
+            4 => Error::ConsensusError(ConsensusError::decode(buf)?),
+            5 => Error::CodecError(CodecError::decode(buf)?),
+            6 => Error::StorageError(StorageError::decode(buf)?),
+            7 => Error::Unknown,
+            8 => Error::ConfigError(String::decode(buf)?),
+            tag => return invalid_tag("error", tag),
+        };
+        Ok(e)
+    }
+}
+
+impl Encode for TSOError {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            TSOError::InvalidCount(n) => {
+                0u8.encode(buf);
+                n.encode(buf);
+            }
+            TSOError::PhysicalOverflow(n) => {
+                1u8.encode(buf);
+                n.encode(buf);
+            }
+            TSOError::ClockError(s) => {
+                2u8.encode(buf);
+                s.encode(buf);
+            }
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        let e = match u8::decode(buf)? {
+            0 => TSOError::InvalidCount(u64::decode(buf)?),
+            1 => TSOError::PhysicalOverflow(u64::decode(buf)?),
+            2 => TSOError::ClockError(String::decode(buf)?),
+            tag => return invalid_tag("tso error", tag),
+        };
+        Ok(e)
+    }
+}
+
+impl Encode for RequestError {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            RequestError::SendError(s) => {
+                0u8.encode(buf);
+                s.encode(buf);
+            }
+            RequestError::NetworkError(s) => {
+                1u8.encode(buf);
+                s.encode(buf);
+            }
+            RequestError::Timeout(d) => {
+                2u8.encode(buf);
+                (d.as_nanos() as u64).encode(buf);
+            }
+            RequestError::NodeNotFound(id) => {
+                3u8.encode(buf);
+                id.encode(buf);
+            }
+            RequestError::RoleMismatch {
+                id,
+                expected,
+                actual,
+            } => {
+                4u8.encode(buf);
+                id.encode(buf);
+                expected.encode(buf);
+                actual.encode(buf);
+            }
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        let e = match u8::decode(buf)? {
+            0 => RequestError::SendError(String::decode(buf)?),
+            1 => RequestError::NetworkError(String::decode(buf)?),
+            2 => RequestError::Timeout(Duration::from_nanos(u64::decode(buf)?)),
+            3 => RequestError::NodeNotFound(u64::decode(buf)?),
+            4 => RequestError::RoleMismatch {
+                id: u64::decode(buf)?,
+                expected: Role::decode(buf)?,
+                actual: Role::decode(buf)?,
+            },
+            tag => return invalid_tag("request error", tag),
+        };
+        Ok(e)
+    }
+}
+
+impl Encode for Role {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Role::Server => 0u8.encode(buf),
+            Role::Storage => 1u8.encode(buf),
+            Role::Tso => 2u8.encode(buf),
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        let role = match u8::decode(buf)? {
+            0 => Role::Server,
+            1 => Role::Storage,
+            2 => Role::Tso,
+            tag => return invalid_tag("role", tag),
+        };
+        Ok(role)
+    }
+}
+
+impl Encode for ShardError {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            ShardError::SplitError(s) => {
+                0u8.encode(buf);
+                s.encode(buf);
+            }
+            ShardError::MergeError(s) => {
+                1u8.encode(buf);
+                s.encode(buf);
+            }
+            ShardError::ReassignError { bucket, node } => {
+                2u8.encode(buf);
+                bucket.encode(buf);
+                node.encode(buf);
+            }
+            ShardError::AddNodeError(id) => {
+                3u8.encode(buf);
+                id.encode(buf);
+            }
+            ShardError::RemoveNodeError(id) => {
+                4u8.encode(buf);
+                id.encode(buf);
+            }
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        let e = match u8::decode(buf)? {
+            0 => ShardError::SplitError(String::decode(buf)?),
+            1 => ShardError::MergeError(String::decode(buf)?),
+            2 => ShardError::ReassignError {
+                bucket: usize::decode(buf)?,
+                node: usize::decode(buf)?,
+            },
+            3 => ShardError::AddNodeError(u64::decode(buf)?),
+            4 => ShardError::RemoveNodeError(u64::decode(buf)?),
+            tag => return invalid_tag("shard error", tag),
+        };
+        Ok(e)
+    }
+}
+
+impl Encode for TxnError {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            TxnError::WriteConflict {
+                key,
+                start_ts,
+                conflict_ts,
+            } => {
+                0u8.encode(buf);
+                key.encode(buf);
+                start_ts.encode(buf);
+                conflict_ts.encode(buf);
+            }
+            TxnError::KeyIsLocked { key, lock_ts } => {
+                1u8.encode(buf);
+                key.encode(buf);
+                lock_ts.encode(buf);
+            }
+            TxnError::RolledBack(ts) => {
+                2u8.encode(buf);
+                ts.encode(buf);
+            }
+            TxnError::Committed(ts) => {
+                3u8.encode(buf);
+                ts.encode(buf);
+            }
+            TxnError::InvalidState(s) => {
+                4u8.encode(buf);
+                s.encode(buf);
+            }
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        let e = match u8::decode(buf)? {
+            0 => TxnError::WriteConflict {
+                key: String::decode(buf)?,
+                start_ts: u64::decode(buf)?,
+                conflict_ts: u64::decode(buf)?,
+            },
+            1 => TxnError::KeyIsLocked {
+                key: String::decode(buf)?,
+                lock_ts: u64::decode(buf)?,
+            },
+            2 => TxnError::RolledBack(u64::decode(buf)?),
+            3 => TxnError::Committed(u64::decode(buf)?),
+            4 => TxnError::InvalidState(String::decode(buf)?),
+            tag => return invalid_tag("txn error", tag),
+        };
+        Ok(e)
+    }
+}
+
+impl Encode for ConsensusError {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            ConsensusError::NotLeader(leader) => {
+                0u8.encode(buf);
+                leader.encode(buf);
+            }
+            ConsensusError::ProposalDropped(index) => {
+                1u8.encode(buf);
+                index.encode(buf);
+            }
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        let e = match u8::decode(buf)? {
+            0 => ConsensusError::NotLeader(Option::decode(buf)?),
+            1 => ConsensusError::ProposalDropped(u64::decode(buf)?),
+            tag => return invalid_tag("consensus error", tag),
+        };
+        Ok(e)
+    }
+}
+
+impl Encode for CodecError {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            CodecError::UnexpectedEof => 0u8.encode(buf),
+            CodecError::InvalidData(s) => {
+                1u8.encode(buf);
+                s.encode(buf);
+            }
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        let e = match u8::decode(buf)? {
+            0 => CodecError::UnexpectedEof,
+            1 => CodecError::InvalidData(String::decode(buf)?),
+            tag => return invalid_tag("codec error", tag),
+        };
+        Ok(e)
+    }
+}
+
+impl Encode for StorageError {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            StorageError::Io(s) => {
+                0u8.encode(buf);
+                s.encode(buf);
+            }
+            StorageError::Corrupted(s) => {
+                1u8.encode(buf);
+                s.encode(buf);
+            }
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        let e = match u8::decode(buf)? {
+            0 => StorageError::Io(String::decode(buf)?),
+            1 => StorageError::Corrupted(String::decode(buf)?),
+            tag => return invalid_tag("storage error", tag),
+        };
+        Ok(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_error() {
+        let errors: Vec<Error> = vec![
+            TSOError::ClockError("skew".to_owned()).into(),
+            RequestError::NetworkError("lost".to_owned()).into(),
+            RequestError::Timeout(Duration::from_millis(10)).into(),
+            RequestError::NodeNotFound(3).into(),
+            RequestError::RoleMismatch {
+                id: 3,
+                expected: Role::Server,
+                actual: Role::Tso,
+            }
+            .into(),
+            ShardError::ReassignError { bucket: 1, node: 2 }.into(),
+            TxnError::WriteConflict {
+                key: "k".to_owned(),
+                start_ts: 1,
+                conflict_ts: 2,
+            }
+            .into(),
+            TxnError::KeyIsLocked {
+                key: "k".to_owned(),
+                lock_ts: 3,
+            }
+            .into(),
+            ConsensusError::NotLeader(Some(2)).into(),
+            ConsensusError::NotLeader(None).into(),
+            CodecError::UnexpectedEof.into(),
+            StorageError::Corrupted("wal".to_owned()).into(),
+            Error::ConfigError("no servers".to_owned()),
+            Error::Unknown,
+        ];
+        for e in errors {
+            let res: Result<u64> = Err(e);
+            let mut buf = vec![];
+            res.encode(&mut buf);
+            assert_eq!(buf.len(), res.encoded_len());
+            assert_eq!(Result::<u64>::decode(&mut buf.as_slice()).unwrap(), res);
+        }
+        assert!(Error::decode(&mut [8u8].as_ref()).is_err());
+        assert!(Role::decode(&mut [3u8].as_ref()).is_err());
+    }
+}
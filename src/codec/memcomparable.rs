@@ -0,0 +1,299 @@
+//! An order-preserving encoding, the encoded bytes compare in the same order as the values.
+//!
+//! Every datum starts with a flag of its type, so the values of different types
+//! are ordered by their types, in the order of the variants of `Datum`.
+use crate::codec::byte::ByteKey;
+use crate::codec::{take_bytes, Encode};
+use crate::util::{CodecError, Result};
+
+const INT_FLAG: u8 = 1;
+const UINT_FLAG: u8 = 2;
+const BYTES_FLAG: u8 = 3;
+const STRING_FLAG: u8 = 4;
+const TUPLE_FLAG: u8 = 5;
+// less than any flag, so a tuple is ordered before the ones it's a prefix of.
+const TUPLE_END: u8 = 0;
+
+const GROUP: usize = 8;
+const MARKER: u8 = 0xFF;
+
+/// Datum is a value in a key, the derived order is the order of the encoded bytes.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Datum {
+    Int(i64),
+    Uint(u64),
+    Bytes(Vec<u8>),
+    String(String),
+    Tuple(Vec<Datum>),
+}
+
+impl From<i64> for Datum {
+    fn from(v: i64) -> Self {
+        Datum::Int(v)
+    }
+}
+
+impl From<u64> for Datum {
+    fn from(v: u64) -> Self {
+        Datum::Uint(v)
+    }
+}
+
+impl From<&[u8]> for Datum {
+    fn from(v: &[u8]) -> Self {
+        Datum::Bytes(v.to_vec())
+    }
+}
+
+impl From<&str> for Datum {
+    fn from(v: &str) -> Self {
+        Datum::String(v.to_owned())
+    }
+}
+
+impl From<String> for Datum {
+    fn from(v: String) -> Self {
+        Datum::String(v)
+    }
+}
+
+impl From<Vec<Datum>> for Datum {
+    fn from(v: Vec<Datum>) -> Self {
+        Datum::Tuple(v)
+    }
+}
+
+pub fn encode_i64(v: i64, buf: &mut Vec<u8>) {
+    // flip the sign bit, so the negative numbers are ordered before the positive ones.
+    encode_u64(v as u64 ^ (1 << 63), buf);
+}
+
+pub fn decode_i64(buf: &mut &[u8]) -> Result<i64> {
+    Ok((decode_u64(buf)? ^ (1 << 63)) as i64)
+}
+
+pub fn encode_u64(v: u64, buf: &mut Vec<u8>) {
+    v.encode(buf);
+}
+
+pub fn decode_u64(buf: &mut &[u8]) -> Result<u64> {
+    u64::decode(buf)
+}
+
+/// encode_bytes writes the bytes in groups of 8, every group is padded with 0
+/// and followed by a marker, which is `0xFF` minus the number of the padding bytes.
+pub fn encode_bytes(v: &[u8], buf: &mut Vec<u8>) {
+    for chunk in v.chunks(GROUP) {
+        buf.extend_from_slice(chunk);
+        let pad = GROUP - chunk.len();
+        buf.extend(std::iter::repeat_n(0, pad));
+        buf.push(MARKER - pad as u8);
+    }
+    // the last group must be padded.
+    if v.len().is_multiple_of(GROUP) {
+        buf.extend_from_slice(&[0; GROUP]);
+        buf.push(MARKER - GROUP as u8);
+    }
+}
+
+pub fn decode_bytes(buf: &mut &[u8]) -> Result<Vec<u8>> {
+    let mut res = vec![];
+    loop {
+        let group = take_bytes(buf, GROUP + 1)?;
+        let pad = (MARKER - group[GROUP]) as usize;
+        if pad > GROUP {
+            let msg = format!("invalid bytes marker {}", group[GROUP]);
+            return Err(CodecError::InvalidData(msg).into());
+        }
+        let (data, padding) = group[..GROUP].split_at(GROUP - pad);
+        if padding.iter().any(|b| *b != 0) {
+            return Err(CodecError::InvalidData("invalid bytes padding".to_owned()).into());
+        }
+        res.extend_from_slice(data);
+        if pad > 0 {
+            return Ok(res);
+        }
+    }
+}
+
+impl Datum {
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Datum::Int(v) => {
+                buf.push(INT_FLAG);
+                encode_i64(*v, buf);
+            }
+            Datum::Uint(v) => {
+                buf.push(UINT_FLAG);
+                encode_u64(*v, buf);
+            }
+            Datum::Bytes(v) => {
+                buf.push(BYTES_FLAG);
+                encode_bytes(v, buf);
+            }
+            Datum::String(v) => {
+                buf.push(STRING_FLAG);
+                encode_bytes(v.as_bytes(), buf);
+            }
+            Datum::Tuple(v) => {
+                buf.push(TUPLE_FLAG);
+                v.iter().for_each(|d| d.encode(buf));
+                buf.push(TUPLE_END);
+            }
+        }
+    }
+
+    pub fn decode(buf: &mut &[u8]) -> Result<Datum> {
+        let flag = u8::decode(buf)?;
+        let datum = match flag {
+            INT_FLAG => Datum::Int(decode_i64(buf)?),
+            UINT_FLAG => Datum::Uint(decode_u64(buf)?),
+            BYTES_FLAG => Datum::Bytes(decode_bytes(buf)?),
+            STRING_FLAG => {
+                let v = String::from_utf8(decode_bytes(buf)?);
+                Datum::String(v.map_err(|e| CodecError::InvalidData(e.to_string()))?)
+            }
+            TUPLE_FLAG => {
+                let mut v = vec![];
+                while buf.first() != Some(&TUPLE_END) {
+                    v.push(Datum::decode(buf)?);
+                }
+                *buf = &buf[1..];
+                Datum::Tuple(v)
+            }
+            _ => return Err(CodecError::InvalidData(format!("unknown flag {}", flag)).into()),
+        };
+        Ok(datum)
+    }
+}
+
+/// encode_key encodes the datums of a composite key,
+/// the keys are ordered by the datums from the first one.
+pub fn encode_key(datums: &[Datum]) -> Vec<u8> {
+    let mut buf = vec![];
+    datums.iter().for_each(|d| d.encode(&mut buf));
+    buf
+}
+
+pub fn decode_key(mut bytes: &[u8]) -> Result<Vec<Datum>> {
+    let mut datums = vec![];
+    while !bytes.is_empty() {
+        datums.push(Datum::decode(&mut bytes)?);
+    }
+    Ok(datums)
+}
+
+impl ByteKey {
+    pub fn from_datums(datums: &[Datum]) -> ByteKey {
+        ByteKey::new(&encode_key(datums))
+    }
+
+    pub fn to_datums(&self) -> Result<Vec<Datum>> {
+        decode_key(self.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shard::{KeySpaceSpilt, Shard};
+    use crate::util::test::IdSender;
+    use crate::util::{Either, Error, Rng};
+
+    fn random_datum(rng: &mut Rng, depth: u32) -> Datum {
+        let small = |rng: &mut Rng| rng.gen_range(0, 20) as usize;
+        match rng.gen_range(0, if depth > 1 { 4 } else { 5 }) {
+            0 => Datum::Int(rng.next_u64() as i64 >> rng.gen_range(0, 64)),
+            1 => Datum::Uint(rng.next_u64() >> rng.gen_range(0, 64)),
+            2 => {
+                let len = small(rng);
+                Datum::Bytes((0..len).map(|_| rng.gen_range(0, 3) as u8).collect())
+            }
+            3 => {
+                let len = small(rng);
+                Datum::String((0..len).map(|_| ['a', 'b', 'é'][small(rng) % 3]).collect())
+            }
+            _ => {
+                let len = small(rng) % 4;
+                Datum::Tuple((0..len).map(|_| random_datum(rng, depth + 1)).collect())
+            }
+        }
+    }
+
+    #[test]
+    fn test_order() {
+        let mut rng = Rng::new(0);
+        let mut keys: Vec<Vec<Datum>> = (0..2000)
+            .map(|_| {
+                let len = rng.gen_range(1, 4);
+                (0..len).map(|_| random_datum(&mut rng, 0)).collect()
+            })
+            .collect();
+        keys.extend(vec![
+            vec![Datum::Int(i64::MIN)],
+            vec![Datum::Int(-1)],
+            vec![Datum::Int(0)],
+            vec![Datum::Uint(u64::MAX)],
+            vec![Datum::Bytes(vec![0; 8])],
+            vec![Datum::Bytes(vec![0; 7])],
+            vec![Datum::Bytes(vec![])],
+            vec![Datum::Tuple(vec![])],
+        ]);
+        for key in &keys {
+            assert_eq!(&decode_key(&encode_key(key)).unwrap(), key);
+        }
+        let mut by_value = keys.clone();
+        by_value.sort();
+        let mut by_bytes = keys;
+        by_bytes.sort_by_key(|k| encode_key(k));
+        assert_eq!(by_value, by_bytes);
+    }
+
+    #[test]
+    fn test_decode_error() {
+        let mut bytes = encode_key(&[Datum::from("abc")]);
+        let last = bytes.len() - 1;
+        bytes[last] = 0;
+        assert!(matches!(
+            decode_key(&bytes),
+            Err(Error::CodecError(CodecError::InvalidData(_)))
+        ));
+        assert_eq!(
+            decode_key(&bytes[..5]).unwrap_err(),
+            Error::CodecError(CodecError::UnexpectedEof)
+        );
+        assert!(decode_key(&[9]).is_err());
+    }
+
+    #[test]
+    fn test_composite_key_split() {
+        let row = |table: i64, id: i64| ByteKey::from_datums(&[table.into(), id.into()]);
+        let mut shard = KeySpaceSpilt::new();
+        shard
+            .split(row(1, i64::MIN), Either::Left(IdSender(0)))
+            .unwrap();
+        // split table 1 at row -10 and 100, the table 2 has its own region.
+        shard
+            .split(row(1, -10), Either::Right(IdSender(1)))
+            .unwrap();
+        shard
+            .split(row(1, 100), Either::Right(IdSender(2)))
+            .unwrap();
+        let table2 = ByteKey::from_datums(&[2i64.into()]);
+        shard.split(table2, Either::Right(IdSender(3))).unwrap();
+
+        assert_eq!(shard.key2node(&row(1, -11)).0, 0);
+        assert_eq!(shard.key2node(&row(1, -10)).0, 1);
+        assert_eq!(shard.key2node(&row(1, 99)).0, 1);
+        assert_eq!(shard.key2node(&row(1, 100)).0, 2);
+        assert_eq!(shard.key2node(&row(1, i64::MAX)).0, 2);
+        assert_eq!(shard.key2node(&row(2, i64::MIN)).0, 3);
+        let key = ByteKey::from_datums(&[1i64.into(), "name".into()]);
+        // a string is ordered after any int.
+        assert_eq!(shard.key2node(&key).0, 2);
+        assert_eq!(
+            key.to_datums().unwrap(),
+            vec![Datum::Int(1), Datum::String("name".to_owned())]
+        );
+    }
+}
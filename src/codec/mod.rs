@@ -2,3 +2,7 @@ pub trait Key: ToOwned<Owned = Self> + ToString + Ord {}
 pub trait Value: ToOwned<Owned = Self> + ToString {}
 
 pub mod byte;
+pub mod memcomparable;
+mod encode;
+mod error;
+pub use encode::{take_bytes, Encode};
@@ -1,3 +1,14 @@
-pub trait Consensus<T> {
+use crate::util::Result;
+use async_trait::async_trait;
 
+#[async_trait]
+pub trait Consensus<T> {
+    /// propose replicates the entry, it returns the log index of the entry
+    /// after the entry is committed and applied.
+    async fn propose(&self, entry: T) -> Result<u64>;
+    fn is_leader(&self) -> bool;
+    fn leader(&self) -> Option<u64>;
 }
+
+mod raft;
+pub use raft::{Entry, RaftConfig, RaftNode, RaftRequest, RaftResponse};
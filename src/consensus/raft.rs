@@ -0,0 +1,748 @@
+use crate::consensus::Consensus;
+use crate::node::Node;
+use crate::request::Sender;
+use crate::util::{ConsensusError, Result};
+use async_trait::async_trait;
+use futures::future::join_all;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+#[derive(Clone, Debug)]
+pub struct Entry<T> {
+    pub term: u64,
+    pub index: u64,
+    /// `None` is the empty entry appended by a new leader.
+    pub data: Option<T>,
+}
+
+pub enum RaftRequest<T> {
+    RequestVote {
+        term: u64,
+        candidate: u64,
+        last_log_index: u64,
+        last_log_term: u64,
+    },
+    AppendEntries {
+        term: u64,
+        leader: u64,
+        prev_log_index: u64,
+        prev_log_term: u64,
+        entries: Vec<Entry<T>>,
+        leader_commit: u64,
+    },
+}
+
+#[derive(Debug)]
+pub enum RaftResponse {
+    RequestVote {
+        term: u64,
+        granted: bool,
+    },
+    /// `last_index` is the match index if success,
+    /// otherwise it's a hint of the next index the leader should try.
+    AppendEntries {
+        term: u64,
+        success: bool,
+        last_index: u64,
+    },
+}
+
+#[derive(Clone)]
+pub struct RaftConfig {
+    /// A follower starts campaign after `[election_tick, 2 * election_tick)` ticks without leader.
+    pub election_tick: u64,
+    pub heartbeat_tick: u64,
+    pub max_entries_per_msg: usize,
+}
+
+impl Default for RaftConfig {
+    fn default() -> Self {
+        Self {
+            election_tick: 10,
+            heartbeat_tick: 2,
+            max_entries_per_msg: 64,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Role {
+    Follower,
+    Candidate,
+    Leader,
+}
+
+struct RaftState<T> {
+    term: u64,
+    voted_for: Option<u64>,
+    role: Role,
+    leader: Option<u64>,
+    // log[0] is a sentinel entry, so that the index of an entry is its position.
+    log: Vec<Entry<T>>,
+    commit_index: u64,
+    last_applied: u64,
+    next_index: HashMap<u64, u64>,
+    match_index: HashMap<u64, u64>,
+    elapsed: u64,
+    randomized_timeout: u64,
+    // xorshift seed for randomized election timeout.
+    seed: u64,
+}
+
+impl<T> RaftState<T> {
+    fn last_index(&self) -> u64 {
+        self.log.len() as u64 - 1
+    }
+
+    fn last_term(&self) -> u64 {
+        self.log.last().unwrap().term
+    }
+
+    fn term_of(&self, index: u64) -> Option<u64> {
+        self.log.get(index as usize).map(|e| e.term)
+    }
+
+    fn reset_timeout(&mut self, election_tick: u64) {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.elapsed = 0;
+        self.randomized_timeout = election_tick + self.seed % election_tick;
+    }
+}
+
+/// RaftNode is a raft peer, it's driven by `tick` and talks to other peers by `Sender`s.
+pub struct RaftNode<T, S>
+where
+    S: Sender<Req = RaftRequest<T>, Res = RaftResponse>,
+{
+    id: u64,
+    config: RaftConfig,
+    peers: RwLock<Vec<(u64, Arc<S>)>>,
+    state: Mutex<RaftState<T>>,
+    // notified when entries are applied or the role is changed.
+    notify_tx: watch::Sender<()>,
+    notify_rx: watch::Receiver<()>,
+    apply: Box<dyn Fn(u64, T) + Send + Sync>,
+}
+
+enum Action {
+    Campaign,
+    Heartbeat,
+}
+
+impl<T, S> RaftNode<T, S>
+where
+    T: Clone + Send + Sync + 'static,
+    S: Sender<Req = RaftRequest<T>, Res = RaftResponse> + Sync + 'static,
+{
+    /// new creates a follower, `apply` is called with every committed entry in log order.
+    pub fn new<F>(id: u64, config: RaftConfig, apply: F) -> Self
+    where
+        F: Fn(u64, T) + Send + Sync + 'static,
+    {
+        let mut state = RaftState {
+            term: 0,
+            voted_for: None,
+            role: Role::Follower,
+            leader: None,
+            log: vec![Entry {
+                term: 0,
+                index: 0,
+                data: None,
+            }],
+            commit_index: 0,
+            last_applied: 0,
+            next_index: HashMap::new(),
+            match_index: HashMap::new(),
+            elapsed: 0,
+            randomized_timeout: 0,
+            seed: id.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1,
+        };
+        state.reset_timeout(config.election_tick);
+        let (notify_tx, notify_rx) = watch::channel(());
+        Self {
+            id,
+            config,
+            peers: RwLock::new(vec![]),
+            state: Mutex::new(state),
+            notify_tx,
+            notify_rx,
+            apply: Box::new(apply),
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn add_peer(&self, id: u64, sender: S) {
+        let mut state = self.state.lock().unwrap();
+        // a leader starts replicating to the new peer from its last entry.
+        if state.role == Role::Leader {
+            let next = state.last_index() + 1;
+            state.next_index.insert(id, next);
+            state.match_index.insert(id, 0);
+        }
+        self.peers.write().unwrap().push((id, Arc::new(sender)));
+    }
+
+    pub fn term(&self) -> u64 {
+        self.state.lock().unwrap().term
+    }
+
+    pub fn commit_index(&self) -> u64 {
+        self.state.lock().unwrap().commit_index
+    }
+
+    pub fn last_applied(&self) -> u64 {
+        self.state.lock().unwrap().last_applied
+    }
+
+    /// run ticks the node every `interval` in background.
+    pub fn run(self: Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let node = self.clone();
+                tokio::spawn(async move { node.tick().await });
+            }
+        })
+    }
+
+    /// tick advances the logical clock, it may start a campaign or send heartbeats.
+    pub async fn tick(&self) {
+        let action = {
+            let mut state = self.state.lock().unwrap();
+            state.elapsed += 1;
+            match state.role {
+                Role::Leader if state.elapsed >= self.config.heartbeat_tick => {
+                    state.elapsed = 0;
+                    Some(Action::Heartbeat)
+                }
+                Role::Follower | Role::Candidate if state.elapsed >= state.randomized_timeout => {
+                    Some(Action::Campaign)
+                }
+                _ => None,
+            }
+        };
+        match action {
+            Some(Action::Campaign) => self.campaign().await,
+            Some(Action::Heartbeat) => self.broadcast_append().await,
+            None => (),
+        }
+    }
+
+    fn peers(&self) -> Vec<(u64, Arc<S>)> {
+        self.peers.read().unwrap().clone()
+    }
+
+    fn quorum(&self) -> usize {
+        let voters = self.peers.read().unwrap().len() + 1;
+        voters / 2 + 1
+    }
+
+    fn become_follower(&self, state: &mut RaftState<T>, term: u64, leader: Option<u64>) {
+        if term > state.term {
+            state.term = term;
+            state.voted_for = None;
+        }
+        let changed = state.role != Role::Follower;
+        state.role = Role::Follower;
+        state.leader = leader;
+        state.reset_timeout(self.config.election_tick);
+        if changed {
+            let _ = self.notify_tx.send(());
+        }
+    }
+
+    fn become_leader(&self, state: &mut RaftState<T>) {
+        state.role = Role::Leader;
+        state.leader = Some(self.id);
+        state.elapsed = 0;
+        let next = state.last_index() + 1;
+        for (id, _) in self.peers.read().unwrap().iter() {
+            state.next_index.insert(*id, next);
+            state.match_index.insert(*id, 0);
+        }
+        // the entries of previous terms are committed along with the empty entry.
+        let entry = Entry {
+            term: state.term,
+            index: next,
+            data: None,
+        };
+        state.log.push(entry);
+        self.maybe_commit(state);
+    }
+
+    async fn campaign(&self) {
+        let (term, req) = {
+            let mut state = self.state.lock().unwrap();
+            state.term += 1;
+            state.voted_for = Some(self.id);
+            state.role = Role::Candidate;
+            state.leader = None;
+            state.reset_timeout(self.config.election_tick);
+            let req = (state.last_index(), state.last_term());
+            (state.term, req)
+        };
+        let peers = self.peers();
+        let votes = join_all(peers.iter().map(|(_, peer)| {
+            peer.send(RaftRequest::RequestVote {
+                term,
+                candidate: self.id,
+                last_log_index: req.0,
+                last_log_term: req.1,
+            })
+        }))
+        .await;
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.term != term || state.role != Role::Candidate {
+                return;
+            }
+            let mut granted = 1;
+            for vote in votes {
+                if let Ok(RaftResponse::RequestVote {
+                    term: vote_term,
+                    granted: vote_granted,
+                }) = vote
+                {
+                    if vote_term > state.term {
+                        self.become_follower(&mut state, vote_term, None);
+                        return;
+                    }
+                    if vote_granted {
+                        granted += 1;
+                    }
+                }
+            }
+            if granted < self.quorum() {
+                return;
+            }
+            self.become_leader(&mut state);
+        }
+        self.broadcast_append().await;
+    }
+
+    async fn broadcast_append(&self) {
+        let peers = self.peers();
+        join_all(
+            peers
+                .iter()
+                .map(|(id, peer)| self.replicate_to(*id, peer.as_ref())),
+        )
+        .await;
+    }
+
+    async fn replicate_to(&self, peer: u64, sender: &S) {
+        let (term, req) = {
+            let state = self.state.lock().unwrap();
+            if state.role != Role::Leader {
+                return;
+            }
+            let next = match state.next_index.get(&peer) {
+                Some(next) => *next,
+                None => return,
+            };
+            let end = state
+                .log
+                .len()
+                .min(next as usize + self.config.max_entries_per_msg);
+            let req = RaftRequest::AppendEntries {
+                term: state.term,
+                leader: self.id,
+                prev_log_index: next - 1,
+                prev_log_term: state.term_of(next - 1).unwrap(),
+                entries: state.log[next as usize..end].to_vec(),
+                leader_commit: state.commit_index,
+            };
+            (state.term, req)
+        };
+        let res = sender.send(req).await;
+        let mut state = self.state.lock().unwrap();
+        if state.term != term || state.role != Role::Leader {
+            return;
+        }
+        if let Ok(RaftResponse::AppendEntries {
+            term: res_term,
+            success,
+            last_index,
+        }) = res
+        {
+            if res_term > state.term {
+                self.become_follower(&mut state, res_term, None);
+                return;
+            }
+            if success {
+                if let Some(&match_index) = state.match_index.get(&peer) {
+                    let match_index = match_index.max(last_index);
+                    state.match_index.insert(peer, match_index);
+                    state.next_index.insert(peer, match_index + 1);
+                    self.maybe_commit(&mut state);
+                }
+            } else if let Some(&next) = state.next_index.get(&peer) {
+                state
+                    .next_index
+                    .insert(peer, last_index.min(next - 1).max(1));
+            }
+        }
+    }
+
+    // maybe_commit advances the commit index to the largest index replicated on a quorum.
+    fn maybe_commit(&self, state: &mut RaftState<T>) {
+        let quorum = self.quorum();
+        for index in (state.commit_index + 1..=state.last_index()).rev() {
+            if state.term_of(index) != Some(state.term) {
+                break;
+            }
+            let replicated = 1 + state.match_index.values().filter(|&&m| m >= index).count();
+            if replicated >= quorum {
+                state.commit_index = index;
+                break;
+            }
+        }
+        self.apply_committed(state);
+    }
+
+    fn apply_committed(&self, state: &mut RaftState<T>) {
+        if state.last_applied >= state.commit_index {
+            return;
+        }
+        while state.last_applied < state.commit_index {
+            state.last_applied += 1;
+            let entry = &state.log[state.last_applied as usize];
+            if let Some(data) = &entry.data {
+                (self.apply)(entry.index, data.clone());
+            }
+        }
+        let _ = self.notify_tx.send(());
+    }
+
+    fn handle_request_vote(
+        &self,
+        term: u64,
+        candidate: u64,
+        last_log_index: u64,
+        last_log_term: u64,
+    ) -> RaftResponse {
+        let mut state = self.state.lock().unwrap();
+        if term > state.term {
+            self.become_follower(&mut state, term, None);
+        }
+        let up_to_date = last_log_term > state.last_term()
+            || (last_log_term == state.last_term() && last_log_index >= state.last_index());
+        let granted = term == state.term
+            && (state.voted_for.is_none() || state.voted_for == Some(candidate))
+            && up_to_date;
+        if granted {
+            state.voted_for = Some(candidate);
+            state.reset_timeout(self.config.election_tick);
+        }
+        RaftResponse::RequestVote {
+            term: state.term,
+            granted,
+        }
+    }
+
+    fn handle_append_entries(
+        &self,
+        term: u64,
+        leader: u64,
+        prev_log_index: u64,
+        prev_log_term: u64,
+        entries: Vec<Entry<T>>,
+        leader_commit: u64,
+    ) -> RaftResponse {
+        let mut state = self.state.lock().unwrap();
+        if term < state.term {
+            return RaftResponse::AppendEntries {
+                term: state.term,
+                success: false,
+                last_index: 0,
+            };
+        }
+        self.become_follower(&mut state, term, Some(leader));
+        if prev_log_index > state.last_index() {
+            return RaftResponse::AppendEntries {
+                term: state.term,
+                success: false,
+                last_index: state.last_index() + 1,
+            };
+        }
+        if state.term_of(prev_log_index) != Some(prev_log_term) {
+            return RaftResponse::AppendEntries {
+                term: state.term,
+                success: false,
+                last_index: prev_log_index,
+            };
+        }
+        let last_new_index = prev_log_index + entries.len() as u64;
+        for entry in entries {
+            match state.term_of(entry.index) {
+                Some(t) if t == entry.term => continue,
+                Some(_) => {
+                    // the committed entries never conflict.
+                    assert!(entry.index > state.commit_index);
+                    state.log.truncate(entry.index as usize);
+                }
+                None => (),
+            }
+            state.log.push(entry);
+        }
+        let commit_index = leader_commit.min(last_new_index);
+        if commit_index > state.commit_index {
+            state.commit_index = commit_index;
+            self.apply_committed(&mut state);
+        }
+        RaftResponse::AppendEntries {
+            term: state.term,
+            success: true,
+            last_index: last_new_index,
+        }
+    }
+}
+
+#[async_trait]
+impl<T, S> Node for RaftNode<T, S>
+where
+    T: Clone + Send + Sync + 'static,
+    S: Sender<Req = RaftRequest<T>, Res = RaftResponse> + Sync + 'static,
+{
+    type Req = RaftRequest<T>;
+    type Res = RaftResponse;
+
+    async fn process(&self, req: Self::Req) -> Result<Self::Res> {
+        let res = match req {
+            RaftRequest::RequestVote {
+                term,
+                candidate,
+                last_log_index,
+                last_log_term,
+            } => self.handle_request_vote(term, candidate, last_log_index, last_log_term),
+            RaftRequest::AppendEntries {
+                term,
+                leader,
+                prev_log_index,
+                prev_log_term,
+                entries,
+                leader_commit,
+            } => self.handle_append_entries(
+                term,
+                leader,
+                prev_log_index,
+                prev_log_term,
+                entries,
+                leader_commit,
+            ),
+        };
+        Ok(res)
+    }
+}
+
+#[async_trait]
+impl<T, S> Consensus<T> for RaftNode<T, S>
+where
+    T: Clone + Send + Sync + 'static,
+    S: Sender<Req = RaftRequest<T>, Res = RaftResponse> + Sync + 'static,
+{
+    /// The proposal fails if the leadership is lost before it's applied,
+    /// however, the entry may be committed by the next leader.
+    async fn propose(&self, data: T) -> Result<u64> {
+        let mut notify_rx = self.notify_rx.clone();
+        let (term, index) = {
+            let mut state = self.state.lock().unwrap();
+            if state.role != Role::Leader {
+                return Err(ConsensusError::NotLeader(state.leader).into());
+            }
+            let entry = Entry {
+                term: state.term,
+                index: state.last_index() + 1,
+                data: Some(data),
+            };
+            state.log.push(entry);
+            self.maybe_commit(&mut state);
+            (state.term, state.last_index())
+        };
+        self.broadcast_append().await;
+        loop {
+            {
+                let state = self.state.lock().unwrap();
+                if state.last_applied >= index {
+                    if state.term_of(index) == Some(term) {
+                        return Ok(index);
+                    }
+                    return Err(ConsensusError::ProposalDropped(index).into());
+                }
+                if state.term != term || state.role != Role::Leader {
+                    return Err(ConsensusError::ProposalDropped(index).into());
+                }
+            }
+            if notify_rx.changed().await.is_err() {
+                return Err(ConsensusError::ProposalDropped(index).into());
+            }
+        }
+    }
+
+    fn is_leader(&self) -> bool {
+        self.state.lock().unwrap().role == Role::Leader
+    }
+
+    fn leader(&self) -> Option<u64> {
+        self.state.lock().unwrap().leader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::channel::{new_channel_connect, ChannelSender};
+    use crate::storage::{Engine, InMemEngine};
+    use crate::util::test::run_in_tokio;
+    use crate::util::Error;
+
+    type Raft = RaftNode<(i32, i32), ChannelSender<RaftRequest<(i32, i32)>, RaftResponse>>;
+    type Peer = (Arc<Raft>, Arc<InMemEngine<i32, i32>>);
+
+    // new_group creates a raft group whose state machines are in-memory engines.
+    fn new_group(n: u64) -> Vec<Peer> {
+        let group: Vec<_> = (1..=n)
+            .map(|id| {
+                let engine = Arc::new(InMemEngine::new());
+                let e = engine.clone();
+                let apply = move |_, (k, v)| e.put(k, v).unwrap();
+                (
+                    Arc::new(RaftNode::new(id, RaftConfig::default(), apply)),
+                    engine,
+                )
+            })
+            .collect();
+        for (node, _) in group.iter() {
+            for (peer, _) in group.iter() {
+                if peer.id() != node.id() {
+                    node.add_peer(peer.id(), new_channel_connect(peer.clone()));
+                }
+            }
+        }
+        group
+    }
+
+    #[test]
+    fn test_single_node() {
+        run_in_tokio(async move {
+            let node = new_group(1).pop().unwrap();
+            assert_eq!(
+                node.0.propose((1, 1)).await.unwrap_err(),
+                Error::ConsensusError(ConsensusError::NotLeader(None))
+            );
+            while !node.0.is_leader() {
+                node.0.tick().await;
+            }
+            for i in 0..10 {
+                node.0.propose((i, i)).await.unwrap();
+                assert_eq!(node.1.get(&i).unwrap(), Some(i));
+            }
+        });
+    }
+
+    #[test]
+    fn test_replication() {
+        run_in_tokio(async move {
+            let group = new_group(3);
+            // only the first node ticks, so it'll be the leader.
+            while !group[0].0.is_leader() {
+                group[0].0.tick().await;
+            }
+            assert_eq!(group[1].0.leader(), Some(1));
+            assert_eq!(
+                group[1].0.propose((1, 1)).await.unwrap_err(),
+                Error::ConsensusError(ConsensusError::NotLeader(Some(1)))
+            );
+            let mut last_index = 0;
+            for i in 0..100 {
+                last_index = group[0].0.propose((i, i)).await.unwrap();
+            }
+            for i in 0..100 {
+                assert_eq!(group[0].1.get(&i).unwrap(), Some(i));
+            }
+            // the followers learn the commit index by heartbeat.
+            for _ in 0..RaftConfig::default().heartbeat_tick {
+                group[0].0.tick().await;
+            }
+            for (node, engine) in group.iter() {
+                assert_eq!(node.commit_index(), last_index);
+                assert_eq!(node.last_applied(), last_index);
+                assert_eq!(engine.scan(&0, &100).unwrap(), (0..100).collect::<Vec<_>>());
+            }
+            std::mem::forget(group);
+        });
+    }
+
+    #[test]
+    fn test_reelection() {
+        run_in_tokio(async move {
+            let group = new_group(3);
+            while !group[0].0.is_leader() {
+                group[0].0.tick().await;
+            }
+            group[0].0.propose((1, 1)).await.unwrap();
+            // the leader stops sending heartbeats, the second node takes over.
+            while !group[1].0.is_leader() {
+                group[1].0.tick().await;
+            }
+            group[1].0.propose((2, 2)).await.unwrap();
+            // the old leader has stepped down on the vote request.
+            assert_eq!(group[0].0.term(), group[1].0.term());
+            assert_eq!(group[0].0.leader(), Some(2));
+            assert_eq!(
+                group[0].0.propose((3, 3)).await.unwrap_err(),
+                Error::ConsensusError(ConsensusError::NotLeader(Some(2)))
+            );
+            for _ in 0..RaftConfig::default().heartbeat_tick {
+                group[1].0.tick().await;
+            }
+            for (_, engine) in group.iter() {
+                assert_eq!(engine.get(&1).unwrap(), Some(1));
+                assert_eq!(engine.get(&2).unwrap(), Some(2));
+                assert_eq!(engine.get(&3).unwrap(), None);
+            }
+            std::mem::forget(group);
+        });
+    }
+
+    #[test]
+    fn test_add_peer() {
+        run_in_tokio(async move {
+            let mut group = new_group(2);
+            while !group[0].0.is_leader() {
+                group[0].0.tick().await;
+            }
+            for i in 0..10 {
+                group[0].0.propose((i, i)).await.unwrap();
+            }
+            // the leader catches the new peer up by heartbeats.
+            let engine = Arc::new(InMemEngine::new());
+            let e = engine.clone();
+            let apply = move |_, (k, v)| e.put(k, v).unwrap();
+            let node = Arc::new(RaftNode::new(3, RaftConfig::default(), apply));
+            for (peer, _) in group.iter() {
+                node.add_peer(peer.id(), new_channel_connect(peer.clone()));
+                peer.add_peer(node.id(), new_channel_connect(node.clone()));
+            }
+            group.push((node, engine));
+            let last_index = group[0].0.propose((10, 10)).await.unwrap();
+            for _ in 0..RaftConfig::default().heartbeat_tick {
+                group[0].0.tick().await;
+            }
+            for (node, engine) in group.iter() {
+                assert_eq!(node.commit_index(), last_index);
+                assert_eq!(engine.scan(&0, &11).unwrap(), (0..11).collect::<Vec<_>>());
+            }
+            std::mem::forget(group);
+        });
+    }
+}
@@ -1,10 +1,15 @@
+pub mod bench;
+pub mod client;
 mod cluster;
 pub mod codec;
+pub mod consensus;
 pub mod node;
 pub mod request;
 pub mod shard;
+pub mod sim;
 pub mod storage;
+pub mod tso;
 pub mod txn;
 pub mod util;
 
-pub use cluster::Cluster;
+pub use cluster::{Cluster, ClusterBuilder, ReplicatedKeySpace, Role};
@@ -18,7 +18,19 @@ pub trait Server: Node + Sync {
     type S: Shard;
 
     fn register_shard(&mut self, s: Arc<Self::S>);
-    async fn execute(&self, t: Txn) -> Result<()>;
+
+    /// execute runs the txn to the end, the txn will be rolled back if the execution fails.
+    async fn execute<T>(&self, mut t: T) -> Result<()>
+    where
+        T: Txn<Server = Self> + Send,
+        Self: Sized,
+    {
+        if let Err(e) = t.execute(self).await {
+            t.rollback(self).await?;
+            return Err(e);
+        }
+        t.commit(self).await
+    }
 }
 
 #[cfg(test)]
@@ -1,21 +1,28 @@
 use async_trait::async_trait;
-use futures::task::Poll;
 use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::runtime::{Builder, Runtime};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::oneshot;
 
 use crate::node::Node;
-use crate::request::{Receiver as myReceiver, Request, Response, Sender as mySender};
-use crate::util::{poll_future_notify, RequestError, Result};
+use crate::request::{Receiver as myReceiver, Request, Response, Sender as mySender, Transport};
+use crate::util::{RequestError, Result, Timer};
+use futures::future::{self, Either};
+
+// the requests waiting for their responses, by the serial id.
+type Pending<Res> = Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Res>>>>>;
 
 pub struct ChannelSender<Req: Request, Res: Response> {
     req_tx: Sender<(u64, Req)>,
     serial: AtomicU64,
-    res_map: Arc<Mutex<HashMap<u64, Result<Res>>>>,
+    pending: Pending<Res>,
     terminate: Arc<AtomicBool>,
+    // the default timeout of `send`, and the timer to wait.
+    timeout: Option<(Duration, Arc<dyn Timer>)>,
     pool: Runtime,
 }
 
@@ -41,21 +48,40 @@ where
     let pool = Builder::new_multi_thread()
         .worker_threads(1)
         .thread_name("request-handle-pool")
+        .enable_all()
         .build()
         .unwrap();
     let tx = ChannelSender::new(req_tx, pool);
     let rx: ChannelReceiver<Req, Res, N> =
         ChannelReceiver::new(req_rx, res_tx, tx.terminate.clone(), node);
     let terminate = tx.terminate.clone();
-    let res_map = tx.res_map.clone();
-    tx.pool.spawn(polling_resp(res_rx, res_map, terminate));
+    let pending = tx.pending.clone();
+    tx.pool.spawn(polling_resp(res_rx, pending, terminate));
     tx.pool.spawn(myReceiver::collect_req(rx));
     tx
 }
 
+/// ChannelTransport connects the nodes in the same process by `new_channel_connect`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChannelTransport;
+
+#[async_trait]
+impl<N> Transport<N> for ChannelTransport
+where
+    N: Node + Sync + Send + 'static,
+    N::Req: 'static,
+    N::Res: 'static,
+{
+    type S = ChannelSender<N::Req, N::Res>;
+
+    async fn connect(&self, node: Arc<N>) -> Result<Self::S> {
+        Ok(new_channel_connect(node))
+    }
+}
+
 async fn polling_resp<Res>(
     mut res_rx: Receiver<(u64, Result<Res>)>,
-    res_map: Arc<Mutex<HashMap<u64, Result<Res>>>>,
+    pending: Pending<Res>,
     terminate: Arc<AtomicBool>,
 ) where
     Res: Response,
@@ -65,48 +91,86 @@ async fn polling_resp<Res>(
             return;
         }
         match res_rx.recv().await {
-            Some(res) => {
-                let mut m = res_map.lock().unwrap();
-                m.insert(res.0, res.1);
-                drop(m);
+            Some((serial_id, res)) => {
+                // the request may be timed out or cancelled.
+                if let Some(tx) = pending.lock().unwrap().remove(&serial_id) {
+                    let _ = tx.send(res);
+                }
             }
             None => return,
         }
     }
 }
 
+// PendingGuard unregisters the request when it's finished or cancelled,
+// so the late response is dropped.
+struct PendingGuard<'a, Res> {
+    serial_id: u64,
+    pending: &'a Pending<Res>,
+}
+
+impl<'a, Res> Drop for PendingGuard<'a, Res> {
+    fn drop(&mut self) {
+        self.pending.lock().unwrap().remove(&self.serial_id);
+    }
+}
+
 impl<Req: Request, Res: Response> ChannelSender<Req, Res> {
     fn new(req_tx: Sender<(u64, Req)>, pool: Runtime) -> Self {
         Self {
             req_tx,
             serial: AtomicU64::new(0),
-            res_map: Arc::new(Mutex::new(HashMap::new())),
+            pending: Arc::new(Mutex::new(HashMap::new())),
             terminate: Arc::new(AtomicBool::new(false)),
+            timeout: None,
             pool,
         }
     }
 
+    /// with_timeout sets the default timeout of `send`, which is waited by `timer`.
+    pub fn with_timeout<T: Timer + 'static>(mut self, timeout: Duration, timer: T) -> Self {
+        self.timeout = Some((timeout, Arc::new(timer)));
+        self
+    }
+
     fn new_serial_id(&self) -> u64 {
         self.serial.fetch_add(1, Ordering::Relaxed)
     }
-}
 
-async fn wait_resp<Res>(id: u64, res_map: Arc<Mutex<HashMap<u64, Result<Res>>>>) -> Result<Res>
-where
-    Res: Response + 'static,
-{
-    let f = futures::future::poll_fn(move |cx| {
-        cx.waker().wake_by_ref();
-        let mut m = res_map.lock().unwrap();
-        let poll_result = match m.remove(&id) {
-            Some(res) => Poll::Ready(res),
-            None => Poll::Pending,
+    /// send_timeout fails with `RequestError::Timeout` if there is no response in `timeout`,
+    /// which is waited by the given timer, it waits forever if `timeout` is `None`.
+    /// Dropping the returned future cancels the request, its response is dropped once it arrives.
+    /// It fails with `RequestError::SendError` once the sender is closed.
+    pub async fn send_timeout(
+        &self,
+        req: Req,
+        timeout: Option<(Duration, &dyn Timer)>,
+    ) -> Result<Res> {
+        let closed = || Err(RequestError::SendError("sender is closed".to_owned()).into());
+        // the responses are not routed anymore, so the request would wait forever.
+        if self.terminate.load(Ordering::Acquire) {
+            return closed();
+        }
+        let serial_id = self.new_serial_id();
+        let (tx, rx) = oneshot::channel();
+        // register before sending, the response may arrive before `send` returns.
+        self.pending.lock().unwrap().insert(serial_id, tx);
+        let _guard = PendingGuard {
+            serial_id,
+            pending: &self.pending,
         };
-        drop(m);
-        poll_result
-    });
-    // TODO: handle the error
-    poll_future_notify(f)
+        self.req_tx
+            .send((serial_id, req))
+            .await
+            .map_err(|e| RequestError::SendError(e.to_string()))?;
+        match timeout {
+            Some((timeout, timer)) => match future::select(rx, timer.sleep(timeout)).await {
+                Either::Left((res, _)) => res.unwrap_or_else(|_| closed()),
+                Either::Right(_) => Err(RequestError::Timeout(timeout).into()),
+            },
+            None => rx.await.unwrap_or_else(|_| closed()),
+        }
+    }
 }
 
 #[async_trait]
@@ -119,16 +183,14 @@ where
     type Res = Res;
 
     async fn send(&self, req: Self::Req) -> Result<Res> {
-        let serial_id = self.new_serial_id();
-        self.req_tx
-            .send((serial_id, req))
-            .await
-            .map_err(|e| RequestError::SendError(e.to_string()))?;
-        wait_resp(serial_id, self.res_map.clone()).await
+        let timeout = self.timeout.as_ref().map(|(d, t)| (*d, t.as_ref()));
+        self.send_timeout(req, timeout).await
     }
 
     fn close(&mut self) {
         self.terminate.store(true, Ordering::Release);
+        // the waiting requests fail as the responses are not routed anymore.
+        self.pending.lock().unwrap().clear();
     }
 }
 
@@ -147,6 +209,7 @@ where
         let pool = Builder::new_multi_thread()
             .worker_threads(1)
             .thread_name("request-handle-pool")
+            .enable_all()
             .build()
             .unwrap();
         Self {
@@ -193,6 +256,7 @@ where
 mod tests {
     use super::*;
     use crate::util::test::run_in_tokio;
+    use crate::util::{Error, TokioTimer};
 
     struct Plus1Node;
 
@@ -234,4 +298,65 @@ mod tests {
             std::mem::forget(tx2);
         });
     }
+
+    // SleepNode sleeps `req` millis before responding.
+    struct SleepNode;
+
+    #[async_trait]
+    impl Node for SleepNode {
+        type Req = u64;
+        type Res = u64;
+        async fn process(&self, req: Self::Req) -> Result<Self::Res> {
+            tokio::time::sleep(Duration::from_millis(req)).await;
+            Ok(req)
+        }
+    }
+
+    #[test]
+    fn test_timeout() {
+        run_in_tokio(async move {
+            let timeout = Duration::from_millis(100);
+            let tx =
+                new_channel_connect(Arc::new(SleepNode)).with_timeout(timeout, TokioTimer::new());
+            assert_eq!(tx.send(1).await.unwrap(), 1);
+            assert_eq!(
+                tx.send(1000).await.unwrap_err(),
+                Error::RequestError(RequestError::Timeout(timeout))
+            );
+            assert_eq!(tx.send_timeout(200, None).await.unwrap(), 200);
+            let short = Duration::from_millis(10);
+            assert_eq!(
+                tx.send_timeout(200, Some((short, &TokioTimer::new())))
+                    .await
+                    .unwrap_err(),
+                Error::RequestError(RequestError::Timeout(short))
+            );
+
+            // the cancelled request is unregistered, and its response is dropped.
+            let cancelled = tx.send_timeout(50, None);
+            assert!(tokio::time::timeout(Duration::from_millis(10), cancelled)
+                .await
+                .is_err());
+            assert!(tx.pending.lock().unwrap().is_empty());
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(tx.send(2).await.unwrap(), 2);
+            assert!(tx.pending.lock().unwrap().is_empty());
+            std::mem::forget(tx);
+        });
+    }
+
+    #[test]
+    fn test_send_after_close() {
+        run_in_tokio(async move {
+            let mut tx = new_channel_connect(Arc::new(SleepNode));
+            assert_eq!(tx.send(1).await.unwrap(), 1);
+            tx.close();
+            assert_eq!(
+                tx.send(1).await.unwrap_err(),
+                Error::RequestError(RequestError::SendError("sender is closed".to_owned()))
+            );
+            assert!(tx.pending.lock().unwrap().is_empty());
+            std::mem::forget(tx);
+        });
+    }
 }
@@ -0,0 +1,392 @@
+use crate::request::Sender;
+use crate::util::{RequestError, Result, Rng, Timer};
+use async_trait::async_trait;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// FaultConfig describes how bad the network is,
+/// the probabilities are applied to every message independently.
+#[derive(Clone, Debug)]
+pub struct FaultConfig {
+    /// The one-way latency is `latency + [0, jitter)`.
+    pub latency: Duration,
+    pub jitter: Duration,
+    pub drop_request: f64,
+    pub drop_response: f64,
+    /// The duplicated request is processed twice, only the first response is returned.
+    pub duplicate: f64,
+    /// The reordered request is delayed by an extra `[0, reorder_delay)`,
+    /// so it may arrive after the requests sent later.
+    pub reorder: f64,
+    pub reorder_delay: Duration,
+    /// The sender gives up after `timeout` if the message is lost.
+    pub timeout: Duration,
+}
+
+impl Default for FaultConfig {
+    fn default() -> Self {
+        Self {
+            latency: Duration::from_millis(0),
+            jitter: Duration::from_millis(0),
+            drop_request: 0.0,
+            drop_response: 0.0,
+            duplicate: 0.0,
+            reorder: 0.0,
+            reorder_delay: Duration::from_millis(10),
+            timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+struct Partition {
+    left: HashSet<u64>,
+    right: HashSet<u64>,
+    until: Option<Duration>,
+}
+
+impl Partition {
+    fn blocks(&self, from: u64, to: u64) -> bool {
+        (self.left.contains(&from) && self.right.contains(&to))
+            || (self.right.contains(&from) && self.left.contains(&to))
+    }
+}
+
+struct NetworkState {
+    config: FaultConfig,
+    partitions: Vec<Partition>,
+    rng: Rng,
+}
+
+// Plan is the fate of a single message, decided when it's sent.
+struct Plan {
+    drop_request: bool,
+    drop_response: bool,
+    duplicate: bool,
+    request_delay: Duration,
+    response_delay: Duration,
+}
+
+/// Network controls the faults of all the `FaultSender`s created from it.
+#[derive(Clone)]
+pub struct Network {
+    state: Arc<Mutex<NetworkState>>,
+    timer: Arc<dyn Timer>,
+}
+
+impl Network {
+    pub fn new<T: Timer + 'static>(seed: u64, timer: T) -> Self {
+        let state = NetworkState {
+            config: FaultConfig::default(),
+            partitions: vec![],
+            rng: Rng::new(seed),
+        };
+        Self {
+            state: Arc::new(Mutex::new(state)),
+            timer: Arc::new(timer),
+        }
+    }
+
+    pub fn set_config(&self, config: FaultConfig) {
+        self.state.lock().unwrap().config = config;
+    }
+
+    pub fn config(&self) -> FaultConfig {
+        self.state.lock().unwrap().config.clone()
+    }
+
+    /// partition blocks the messages between `left` and `right`,
+    /// it heals itself after `duration` if it's given.
+    pub fn partition(&self, left: &[u64], right: &[u64], duration: Option<Duration>) {
+        let until = duration.map(|d| self.timer.now() + d);
+        let partition = Partition {
+            left: left.iter().copied().collect(),
+            right: right.iter().copied().collect(),
+            until,
+        };
+        self.state.lock().unwrap().partitions.push(partition);
+    }
+
+    /// heal removes all the partitions.
+    pub fn heal(&self) {
+        self.state.lock().unwrap().partitions.clear();
+    }
+
+    pub fn is_partitioned(&self, from: u64, to: u64) -> bool {
+        let now = self.timer.now();
+        let mut state = self.state.lock().unwrap();
+        state
+            .partitions
+            .retain(|p| p.until.is_none() || Some(now) < p.until);
+        state.partitions.iter().any(|p| p.blocks(from, to))
+    }
+
+    /// wrap decorates the sender of messages from node `from` to node `to`.
+    pub fn wrap<S: Sender>(&self, sender: S, from: u64, to: u64) -> FaultSender<S> {
+        FaultSender {
+            inner: sender,
+            from,
+            to,
+            network: self.clone(),
+            duplicator: None,
+        }
+    }
+
+    fn plan(&self) -> Plan {
+        let mut state = self.state.lock().unwrap();
+        let NetworkState { config, rng, .. } = &mut *state;
+        let mut delay = || {
+            let jitter = config.jitter.as_nanos() as u64;
+            let jitter = if jitter == 0 {
+                0
+            } else {
+                rng.gen_range(0, jitter)
+            };
+            config.latency + Duration::from_nanos(jitter)
+        };
+        let mut request_delay = delay();
+        let response_delay = delay();
+        let reorder_delay = config.reorder_delay.as_nanos() as u64;
+        if reorder_delay > 0 && rng.gen_bool(config.reorder) {
+            request_delay += Duration::from_nanos(rng.gen_range(0, reorder_delay));
+        }
+        Plan {
+            drop_request: rng.gen_bool(config.drop_request),
+            drop_response: rng.gen_bool(config.drop_response),
+            duplicate: rng.gen_bool(config.duplicate),
+            request_delay,
+            response_delay,
+        }
+    }
+
+    async fn lost(&self, from: u64, to: u64) -> RequestError {
+        let timeout = self.state.lock().unwrap().config.timeout;
+        self.timer.sleep(timeout).await;
+        RequestError::NetworkError(format!("message between {} and {} is lost", from, to))
+    }
+}
+
+type Duplicator<Req> = dyn Fn(&Req) -> Req + Send + Sync;
+
+/// FaultSender injects the faults of the `Network` into any `Sender`.
+pub struct FaultSender<S: Sender> {
+    inner: S,
+    from: u64,
+    to: u64,
+    network: Network,
+    duplicator: Option<Box<Duplicator<S::Req>>>,
+}
+
+impl<S: Sender> FaultSender<S> {
+    /// duplicate_with enables duplicating requests, `f` makes a copy of the request.
+    pub fn duplicate_with<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&S::Req) -> S::Req + Send + Sync + 'static,
+    {
+        self.duplicator = Some(Box::new(f));
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<S> Sender for FaultSender<S>
+where
+    S: Sender + Sync,
+    S::Req: 'static,
+{
+    type Req = S::Req;
+    type Res = S::Res;
+
+    async fn send(&self, req: Self::Req) -> Result<Self::Res> {
+        let (from, to) = (self.from, self.to);
+        let plan = self.network.plan();
+        if plan.drop_request || self.network.is_partitioned(from, to) {
+            return Err(self.network.lost(from, to).await.into());
+        }
+        self.network.timer.sleep(plan.request_delay).await;
+        // the partition may happen when the request is on the way.
+        if self.network.is_partitioned(from, to) {
+            return Err(self.network.lost(from, to).await.into());
+        }
+        let res = match (&self.duplicator, plan.duplicate) {
+            (Some(duplicator), true) => {
+                let dup = duplicator(&req);
+                let (res, _) = futures::join!(self.inner.send(req), self.inner.send(dup));
+                res
+            }
+            _ => self.inner.send(req).await,
+        };
+        if plan.drop_response || self.network.is_partitioned(to, from) {
+            return Err(self.network.lost(to, from).await.into());
+        }
+        self.network.timer.sleep(plan.response_delay).await;
+        res
+    }
+
+    fn close(&mut self) {
+        self.inner.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Node;
+    use crate::sim::Simulator;
+    use crate::util::Error;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[derive(Default)]
+    struct CountNode {
+        count: AtomicU64,
+    }
+
+    #[async_trait]
+    impl Node for CountNode {
+        type Req = u64;
+        type Res = u64;
+
+        async fn process(&self, req: Self::Req) -> Result<Self::Res> {
+            self.count.fetch_add(1, Ordering::SeqCst);
+            Ok(req)
+        }
+    }
+
+    fn is_lost(res: Result<u64>) -> bool {
+        matches!(res, Err(Error::RequestError(RequestError::NetworkError(_))))
+    }
+
+    #[test]
+    fn test_partition() {
+        let sim = Simulator::new(0);
+        let s = sim.clone();
+        sim.block_on(async move {
+            s.set_latency(Duration::from_millis(0), Duration::from_millis(0));
+            let network = Network::new(0, s.clone());
+            let node = Arc::new(CountNode::default());
+            // [1, 2] -> 3, 3 -> 1
+            let tx13 = network.wrap(s.connect(node.clone()), 1, 3);
+            let tx23 = network.wrap(s.connect(node.clone()), 2, 3);
+            let tx31 = network.wrap(s.connect(node.clone()), 3, 1);
+            network.set_config(FaultConfig {
+                timeout: Duration::from_millis(100),
+                ..Default::default()
+            });
+            network.partition(&[1, 2], &[3], Some(Duration::from_millis(500)));
+            let start = s.now();
+            assert!(is_lost(tx13.send(1).await));
+            assert!(is_lost(tx23.send(1).await));
+            assert!(is_lost(tx31.send(1).await));
+            assert_eq!(node.count.load(Ordering::SeqCst), 0);
+            // the sender waits for the timeout.
+            assert_eq!(s.now() - start, Duration::from_millis(300));
+            // the partition is healed.
+            s.sleep(Duration::from_millis(200)).await;
+            assert_eq!(tx13.send(1).await.unwrap(), 1);
+
+            network.partition(&[1], &[3], None);
+            assert!(is_lost(tx13.send(1).await));
+            assert_eq!(tx23.send(2).await.unwrap(), 2);
+            network.heal();
+            assert_eq!(tx13.send(3).await.unwrap(), 3);
+            assert_eq!(node.count.load(Ordering::SeqCst), 3);
+        });
+        sim.shutdown();
+    }
+
+    #[test]
+    fn test_faults() {
+        let sim = Simulator::new(0);
+        let s = sim.clone();
+        sim.block_on(async move {
+            s.set_latency(Duration::from_millis(0), Duration::from_millis(0));
+            let network = Network::new(0, s.clone());
+            let node = Arc::new(CountNode::default());
+            let tx = network
+                .wrap(s.connect(node.clone()), 1, 2)
+                .duplicate_with(|req| *req);
+
+            let config = FaultConfig {
+                latency: Duration::from_millis(10),
+                jitter: Duration::from_millis(5),
+                ..Default::default()
+            };
+            network.set_config(config);
+            let start = s.now();
+            tx.send(1).await.unwrap();
+            let elapsed = s.now() - start;
+            assert!(elapsed >= Duration::from_millis(20) && elapsed < Duration::from_millis(30));
+
+            let config = FaultConfig {
+                drop_response: 1.0,
+                ..Default::default()
+            };
+            network.set_config(config);
+            assert!(is_lost(tx.send(1).await));
+            // the request is processed, but the response is lost.
+            assert_eq!(node.count.load(Ordering::SeqCst), 2);
+
+            let config = FaultConfig {
+                drop_request: 0.5,
+                duplicate: 0.5,
+                ..Default::default()
+            };
+            network.set_config(config);
+            let mut lost = 0;
+            for i in 0..1000 {
+                match tx.send(i).await {
+                    Ok(res) => assert_eq!(res, i),
+                    Err(_) => lost += 1,
+                }
+            }
+            assert!(lost > 400 && lost < 600);
+            let processed = node.count.load(Ordering::SeqCst) - 2;
+            let delivered = 1000 - lost;
+            assert!(processed > delivered + delivered / 3);
+        });
+        sim.shutdown();
+    }
+
+    #[test]
+    fn test_reorder() {
+        let sim = Simulator::new(0);
+        let s = sim.clone();
+        let order = sim.block_on(async move {
+            s.set_latency(Duration::from_millis(0), Duration::from_millis(0));
+            let network = Network::new(0, s.clone());
+            let config = FaultConfig {
+                reorder: 0.5,
+                reorder_delay: Duration::from_millis(100),
+                ..Default::default()
+            };
+            network.set_config(config);
+            let tx = Arc::new(network.wrap(s.connect(Arc::new(CountNode::default())), 1, 2));
+            let order = Arc::new(Mutex::new(vec![]));
+            let (done_tx, done_rx) = futures::channel::mpsc::unbounded();
+            for i in 0..100 {
+                let (tx, order, done_tx) = (tx.clone(), order.clone(), done_tx.clone());
+                s.spawn(async move {
+                    let res = tx.send(i).await.unwrap();
+                    order.lock().unwrap().push(res);
+                    done_tx.unbounded_send(()).unwrap();
+                });
+                s.sleep(Duration::from_millis(1)).await;
+            }
+            drop(done_tx);
+            futures::StreamExt::collect::<Vec<_>>(done_rx).await;
+            let order = order.lock().unwrap().clone();
+            order
+        });
+        sim.shutdown();
+        assert_eq!(order.len(), 100);
+        assert!(order.windows(2).any(|w| w[0] > w[1]));
+    }
+}
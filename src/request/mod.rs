@@ -1,6 +1,7 @@
 use crate::node::Node;
 use crate::util::Result;
 use async_trait::async_trait;
+use std::sync::Arc;
 
 pub trait Request: Send {}
 pub trait Response: Send {}
@@ -27,4 +28,16 @@ pub trait Receiver {
     async fn collect_req(mut self);
 }
 
+/// Transport connects the nodes, it decides how the requests are delivered,
+/// e.g. by in-process channels, TCP or the simulator.
+#[async_trait]
+pub trait Transport<N: Node>: Sync {
+    type S: Sender<Req = N::Req, Res = N::Res>;
+
+    async fn connect(&self, node: Arc<N>) -> Result<Self::S>;
+}
+
 pub mod channel;
+pub mod fault;
+pub mod tcp;
+pub mod wire;
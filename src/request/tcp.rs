@@ -0,0 +1,450 @@
+//! A TCP transport, the requests and responses are encoded by `Encode`
+//! and sent in frames of `len | serial id | payload`.
+//!
+//! The concurrent requests of a `TcpSender` share one connection,
+//! the responses are routed back to the requests by the serial id, in any order.
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot, Mutex as AsyncMutex};
+use tokio::task::{AbortHandle, JoinSet};
+
+use crate::codec::Encode;
+use crate::node::Node;
+use crate::request::{Receiver, Request, Response, Sender, Transport};
+use crate::util::{CodecError, Error, RequestError, Result};
+
+// a broken peer shouldn't make us allocate unbounded memory.
+const MAX_FRAME_LEN: usize = 64 << 20;
+
+fn network_error(e: impl ToString) -> Error {
+    RequestError::NetworkError(e.to_string()).into()
+}
+
+fn new_frame<T: Encode>(serial_id: u64, msg: &T) -> Vec<u8> {
+    let mut frame = vec![0; 4];
+    serial_id.encode(&mut frame);
+    msg.encode(&mut frame);
+    let len = (frame.len() - 4) as u32;
+    frame[..4].copy_from_slice(&len.to_be_bytes());
+    frame
+}
+
+/// read_frame returns `None` if the connection is closed between frames.
+async fn read_frame<R: AsyncRead + Unpin>(r: &mut R) -> Result<Option<(u64, Vec<u8>)>> {
+    let mut len = [0; 4];
+    match r.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(network_error(e)),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if !(8..=MAX_FRAME_LEN).contains(&len) {
+        return Err(CodecError::InvalidData(format!("invalid frame length {}", len)).into());
+    }
+    let mut payload = vec![0; len];
+    r.read_exact(&mut payload).await.map_err(network_error)?;
+    let mut buf = payload.as_slice();
+    let serial_id = u64::decode(&mut buf)?;
+    Ok(Some((serial_id, buf.to_vec())))
+}
+
+async fn write_frames<W: AsyncWrite + Unpin>(
+    mut w: W,
+    mut frames: mpsc::UnboundedReceiver<Vec<u8>>,
+) {
+    while let Some(frame) = frames.recv().await {
+        if w.write_all(&frame).await.is_err() {
+            return;
+        }
+    }
+    let _ = w.shutdown().await;
+}
+
+// pending is `None` once the connection is broken, so no request can wait on it forever.
+type Pending<Res> = Arc<Mutex<Option<HashMap<u64, oneshot::Sender<Result<Res>>>>>>;
+
+struct Conn<Res> {
+    frames: mpsc::UnboundedSender<Vec<u8>>,
+    pending: Pending<Res>,
+}
+
+// PendingGuard unregisters the request when it's finished or cancelled,
+// so the late response is dropped.
+struct PendingGuard<Res> {
+    serial_id: u64,
+    pending: Pending<Res>,
+}
+
+impl<Res> Drop for PendingGuard<Res> {
+    fn drop(&mut self) {
+        if let Some(requests) = self.pending.lock().unwrap().as_mut() {
+            requests.remove(&self.serial_id);
+        }
+    }
+}
+
+fn fail_pending<Res>(pending: &Pending<Res>, msg: &str) {
+    if let Some(requests) = pending.lock().unwrap().take() {
+        for (_, tx) in requests {
+            let _ = tx.send(Err(network_error(msg)));
+        }
+    }
+}
+
+/// TcpSender connects to a `TcpReceiver` on the first request,
+/// and connects again by the next request once the connection is broken.
+/// The requests in flight on a broken connection fail with `NetworkError`, they are not retried.
+pub struct TcpSender<Req: Request, Res: Response> {
+    addr: SocketAddr,
+    serial: AtomicU64,
+    conn: AsyncMutex<Option<Conn<Res>>>,
+    // the receiver spawned by `TcpTransport`, it's stopped with the sender.
+    receiver: Option<AbortHandle>,
+    phantom: PhantomData<fn(Req)>,
+}
+
+impl<Req, Res> TcpSender<Req, Res>
+where
+    Req: Request + Encode,
+    Res: Response + Encode + 'static,
+{
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            serial: AtomicU64::new(0),
+            conn: AsyncMutex::new(None),
+            receiver: None,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    fn new_serial_id(&self) -> u64 {
+        self.serial.fetch_add(1, Ordering::Relaxed)
+    }
+
+    async fn connect(&self) -> Result<Conn<Res>> {
+        let stream = TcpStream::connect(self.addr).await.map_err(network_error)?;
+        stream.set_nodelay(true).map_err(network_error)?;
+        let (mut r, w) = stream.into_split();
+        let (frames, rx) = mpsc::unbounded_channel();
+        tokio::spawn(write_frames(w, rx));
+
+        let pending: Pending<Res> = Arc::new(Mutex::new(Some(HashMap::new())));
+        let p = pending.clone();
+        tokio::spawn(async move {
+            let msg = loop {
+                let (serial_id, payload) = match read_frame(&mut r).await {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => break "connection closed".to_owned(),
+                    Err(e) => break e.to_string(),
+                };
+                let res = Result::<Res>::decode(&mut payload.as_slice()).and_then(|res| res);
+                let tx = match p.lock().unwrap().as_mut() {
+                    Some(requests) => requests.remove(&serial_id),
+                    None => return,
+                };
+                // the request may be cancelled.
+                if let Some(tx) = tx {
+                    let _ = tx.send(res);
+                }
+            };
+            fail_pending(&p, &msg);
+        });
+        Ok(Conn { frames, pending })
+    }
+}
+
+#[async_trait]
+impl<Req, Res> Sender for TcpSender<Req, Res>
+where
+    Req: Request + Encode,
+    Res: Response + Encode + 'static,
+{
+    type Req = Req;
+    type Res = Res;
+
+    async fn send(&self, req: Self::Req) -> Result<Res> {
+        let serial_id = self.new_serial_id();
+        let frame = new_frame(serial_id, &req);
+        let (tx, rx) = oneshot::channel();
+        let _guard = {
+            let mut conn = self.conn.lock().await;
+            let broken = match conn.as_ref() {
+                Some(c) => c.frames.is_closed() || c.pending.lock().unwrap().is_none(),
+                None => true,
+            };
+            if broken {
+                *conn = Some(self.connect().await?);
+            }
+            let c = conn.as_ref().unwrap();
+            match c.pending.lock().unwrap().as_mut() {
+                Some(requests) => requests.insert(serial_id, tx),
+                None => return Err(network_error("connection closed")),
+            };
+            let guard = PendingGuard {
+                serial_id,
+                pending: c.pending.clone(),
+            };
+            if c.frames.send(frame).is_err() {
+                fail_pending(&c.pending, "connection closed");
+            }
+            guard
+        };
+        rx.await
+            .unwrap_or_else(|_| Err(network_error("connection closed")))
+    }
+
+    fn close(&mut self) {
+        // the writer shuts down the connection when the frames are dropped.
+        *self.conn.get_mut() = None;
+        if let Some(receiver) = self.receiver.take() {
+            receiver.abort();
+        }
+    }
+}
+
+/// TcpReceiver accepts the connections of `TcpSender`s, and processes their requests on the node.
+pub struct TcpReceiver<N: Node> {
+    listener: TcpListener,
+    node: Arc<N>,
+}
+
+impl<N: Node> TcpReceiver<N> {
+    pub async fn bind(addr: SocketAddr, node: Arc<N>) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await.map_err(network_error)?;
+        Ok(Self { listener, node })
+    }
+
+    /// local_addr is useful when it's bound to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.listener.local_addr().unwrap()
+    }
+}
+
+async fn serve_conn<N>(stream: TcpStream, node: Arc<N>)
+where
+    N: Node + Sync + Send + 'static,
+    N::Req: Encode + 'static,
+    N::Res: Encode + 'static,
+{
+    let _ = stream.set_nodelay(true);
+    let (mut r, w) = stream.into_split();
+    let (frames, rx) = mpsc::unbounded_channel();
+    let read = async move {
+        while let Ok(Some((serial_id, payload))) = read_frame(&mut r).await {
+            let node = node.clone();
+            let frames = frames.clone();
+            tokio::spawn(async move {
+                let res = match N::Req::decode(&mut payload.as_slice()) {
+                    Ok(req) => node.process(req).await,
+                    Err(e) => Err(e),
+                };
+                let _ = frames.send(new_frame(serial_id, &res));
+            });
+        }
+    };
+    // stop reading if the peer can't receive the responses, and vice versa.
+    tokio::select! {
+        _ = read => {}
+        _ = write_frames(w, rx) => {}
+    }
+}
+
+#[async_trait]
+impl<N> Receiver for TcpReceiver<N>
+where
+    N: Node + Sync + Send + 'static,
+    N::Req: Encode + 'static,
+    N::Res: Encode + 'static,
+{
+    type Req = N::Req;
+    type Res = N::Res;
+    type N = N;
+
+    /// collect_req serves until it's dropped, the connections are closed with it.
+    async fn collect_req(mut self) {
+        let mut conns = JoinSet::new();
+        loop {
+            tokio::select! {
+                accepted = self.listener.accept() => match accepted {
+                    Ok((stream, _)) => {
+                        conns.spawn(serve_conn(stream, self.node.clone()));
+                    }
+                    Err(_) => return,
+                },
+                Some(_) = conns.join_next() => {}
+            }
+        }
+    }
+}
+
+/// TcpTransport serves every node on a new port of `ip`, the receivers run in the current tokio runtime.
+/// The receiver of a node is stopped when the sender returned by `connect` is closed.
+#[derive(Clone, Copy, Debug)]
+pub struct TcpTransport {
+    ip: IpAddr,
+}
+
+impl TcpTransport {
+    pub fn new(ip: IpAddr) -> Self {
+        Self { ip }
+    }
+}
+
+impl Default for TcpTransport {
+    fn default() -> Self {
+        Self::new(IpAddr::V4(Ipv4Addr::LOCALHOST))
+    }
+}
+
+#[async_trait]
+impl<N> Transport<N> for TcpTransport
+where
+    N: Node + Sync + Send + 'static,
+    N::Req: Encode + 'static,
+    N::Res: Encode + 'static,
+{
+    type S = TcpSender<N::Req, N::Res>;
+
+    async fn connect(&self, node: Arc<N>) -> Result<Self::S> {
+        let rx = TcpReceiver::bind(SocketAddr::new(self.ip, 0), node).await?;
+        let addr = rx.local_addr();
+        let mut sender = TcpSender::new(addr);
+        sender.receiver = Some(tokio::spawn(rx.collect_req()).abort_handle());
+        Ok(sender)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test::run_in_tokio;
+    use std::time::Duration;
+
+    // SleepNode sleeps `req` millis, and responds it.
+    struct SleepNode;
+
+    #[async_trait]
+    impl Node for SleepNode {
+        type Req = u64;
+        type Res = u64;
+
+        async fn process(&self, req: Self::Req) -> Result<Self::Res> {
+            if req == u64::MAX {
+                return Err(RequestError::SendError("max".to_owned()).into());
+            }
+            tokio::time::sleep(Duration::from_millis(req)).await;
+            Ok(req)
+        }
+    }
+
+    async fn serve(addr: SocketAddr) -> (SocketAddr, tokio::task::JoinHandle<()>) {
+        let rx = TcpReceiver::bind(addr, Arc::new(SleepNode)).await.unwrap();
+        let addr = rx.local_addr();
+        (addr, tokio::spawn(rx.collect_req()))
+    }
+
+    #[test]
+    fn test_multiplex() {
+        run_in_tokio(async move {
+            let (addr, server) = serve("127.0.0.1:0".parse().unwrap()).await;
+            let tx = Arc::new(TcpSender::<u64, u64>::new(addr));
+            // the slow requests don't block the fast ones on the same connection.
+            let sends = (0..20).rev().map(|i| {
+                let tx = tx.clone();
+                async move { (i * 10, tx.send(i * 10).await) }
+            });
+            let start = std::time::Instant::now();
+            for (req, res) in futures::future::join_all(sends).await {
+                assert_eq!(res.unwrap(), req);
+            }
+            assert!(start.elapsed() < Duration::from_millis(190 * 2));
+            assert_eq!(
+                tx.send(u64::MAX).await.unwrap_err(),
+                Error::RequestError(RequestError::SendError("max".to_owned()))
+            );
+            server.abort();
+        });
+    }
+
+    #[test]
+    fn test_reconnect() {
+        run_in_tokio(async move {
+            let (addr, server) = serve("127.0.0.1:0".parse().unwrap()).await;
+            let tx = Arc::new(TcpSender::<u64, u64>::new(addr));
+            assert_eq!(tx.send(1).await.unwrap(), 1);
+
+            let t = tx.clone();
+            let slow = tokio::spawn(async move { t.send(10_000).await });
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            server.abort();
+            assert!(matches!(
+                slow.await.unwrap(),
+                Err(Error::RequestError(RequestError::NetworkError(_)))
+            ));
+            assert!(tx.send(1).await.is_err());
+
+            // the server restarts on the same address.
+            let (_, server) = serve(addr).await;
+            assert_eq!(tx.send(2).await.unwrap(), 2);
+            server.abort();
+        });
+    }
+
+    #[test]
+    fn test_cancel() {
+        run_in_tokio(async move {
+            let (addr, server) = serve("127.0.0.1:0".parse().unwrap()).await;
+            let tx = TcpSender::<u64, u64>::new(addr);
+            assert_eq!(tx.send(1).await.unwrap(), 1);
+            let pending = || {
+                let conn = tx.conn.try_lock().unwrap();
+                let pending = conn.as_ref().unwrap().pending.lock().unwrap();
+                pending.as_ref().unwrap().len()
+            };
+
+            // the cancelled request is unregistered, and its response is dropped.
+            let cancelled = tx.send(50);
+            assert!(tokio::time::timeout(Duration::from_millis(10), cancelled)
+                .await
+                .is_err());
+            assert_eq!(pending(), 0);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(tx.send(2).await.unwrap(), 2);
+            assert_eq!(pending(), 0);
+            server.abort();
+        });
+    }
+
+    #[test]
+    fn test_close_receiver() {
+        run_in_tokio(async move {
+            let mut tx = TcpTransport::default()
+                .connect(Arc::new(SleepNode))
+                .await
+                .unwrap();
+            assert_eq!(tx.send(1).await.unwrap(), 1);
+            let other = TcpSender::<u64, u64>::new(tx.addr());
+            assert_eq!(other.send(2).await.unwrap(), 2);
+
+            // closing the sender stops the receiver, and its connections.
+            tx.close();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert!(matches!(
+                other.send(3).await,
+                Err(Error::RequestError(RequestError::NetworkError(_)))
+            ));
+            assert!(TcpStream::connect(tx.addr()).await.is_err());
+        });
+    }
+}
@@ -0,0 +1,162 @@
+use crate::codec::Encode;
+use crate::request::Sender;
+use crate::util::{Result, Timer};
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// WireStats counts the messages and the bytes sent by a `WireSender`.
+#[derive(Default, Debug)]
+pub struct WireStats {
+    requests: AtomicU64,
+    request_bytes: AtomicU64,
+    response_bytes: AtomicU64,
+}
+
+impl WireStats {
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::SeqCst)
+    }
+
+    pub fn request_bytes(&self) -> u64 {
+        self.request_bytes.load(Ordering::SeqCst)
+    }
+
+    pub fn response_bytes(&self) -> u64 {
+        self.response_bytes.load(Ordering::SeqCst)
+    }
+}
+
+/// WireSender sends the messages in their wire format,
+/// they are encoded and decoded as if they went through the network, and the bytes are counted.
+pub struct WireSender<S: Sender> {
+    inner: S,
+    stats: Arc<WireStats>,
+    // bytes per second, and the timer to delay the transfer.
+    bandwidth: Option<(u64, Arc<dyn Timer>)>,
+}
+
+impl<S: Sender> WireSender<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            stats: Arc::new(WireStats::default()),
+            bandwidth: None,
+        }
+    }
+
+    /// with_bandwidth delays every message by the time to transfer its bytes.
+    pub fn with_bandwidth<T: Timer + 'static>(mut self, bytes_per_sec: u64, timer: T) -> Self {
+        assert!(bytes_per_sec > 0);
+        self.bandwidth = Some((bytes_per_sec, Arc::new(timer)));
+        self
+    }
+
+    /// with_stats shares the stats with other senders, e.g. all the senders of a node.
+    pub fn with_stats(mut self, stats: Arc<WireStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    pub fn stats(&self) -> Arc<WireStats> {
+        self.stats.clone()
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    async fn transfer(&self, bytes: usize) {
+        if let Some((bandwidth, timer)) = &self.bandwidth {
+            let nanos = bytes as u128 * 1_000_000_000 / *bandwidth as u128;
+            timer.sleep(Duration::from_nanos(nanos as u64)).await;
+        }
+    }
+}
+
+#[async_trait]
+impl<S> Sender for WireSender<S>
+where
+    S: Sender + Sync,
+    S::Req: Encode,
+    S::Res: Encode,
+{
+    type Req = S::Req;
+    type Res = S::Res;
+
+    async fn send(&self, req: Self::Req) -> Result<Self::Res> {
+        let mut buf = vec![];
+        req.encode(&mut buf);
+        self.stats.requests.fetch_add(1, Ordering::SeqCst);
+        self.stats
+            .request_bytes
+            .fetch_add(buf.len() as u64, Ordering::SeqCst);
+        self.transfer(buf.len()).await;
+        let req = S::Req::decode(&mut buf.as_slice())?;
+
+        let res = self.inner.send(req).await;
+        let mut buf = vec![];
+        res.encode(&mut buf);
+        self.stats
+            .response_bytes
+            .fetch_add(buf.len() as u64, Ordering::SeqCst);
+        self.transfer(buf.len()).await;
+        Result::<S::Res>::decode(&mut buf.as_slice())?
+    }
+
+    fn close(&mut self) {
+        self.inner.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Node;
+    use crate::sim::Simulator;
+    use crate::util::{Error, RequestError};
+
+    // BytesNode responds the requested number of bytes.
+    struct BytesNode;
+
+    #[async_trait]
+    impl Node for BytesNode {
+        type Req = u64;
+        type Res = Vec<u8>;
+
+        async fn process(&self, req: Self::Req) -> Result<Self::Res> {
+            if req == 0 {
+                return Err(RequestError::SendError("empty".to_owned()).into());
+            }
+            Ok(vec![7; req as usize])
+        }
+    }
+
+    #[test]
+    fn test_wire_sender() {
+        let sim = Simulator::new(0);
+        let s = sim.clone();
+        sim.block_on(async move {
+            s.set_latency(Duration::from_millis(0), Duration::from_millis(0));
+            let tx =
+                WireSender::new(s.connect(Arc::new(BytesNode))).with_bandwidth(1000, s.clone());
+            let start = s.now();
+            // 8 bytes of request, and 1 + 4 + 987 bytes of response.
+            assert_eq!(tx.send(987).await.unwrap(), vec![7; 987]);
+            assert_eq!(s.now() - start, Duration::from_secs(1));
+            let stats = tx.stats();
+            assert_eq!(stats.requests(), 1);
+            assert_eq!(stats.request_bytes(), 8);
+            assert_eq!(stats.response_bytes(), 992);
+
+            // the errors are sent over the wire too.
+            assert_eq!(
+                tx.send(0).await.unwrap_err(),
+                Error::RequestError(RequestError::SendError("empty".to_owned()))
+            );
+            assert_eq!(stats.requests(), 2);
+        });
+        sim.shutdown();
+    }
+}
@@ -0,0 +1,204 @@
+use crate::codec::Key;
+use crate::request::Sender;
+use crate::shard::{Shard, SubRange};
+use crate::util::{Either, Result, ShardError};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+// FnvHasher is stable across builds and platforms,
+// so the buckets of the keys never change.
+pub(super) struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100_0000_01b3);
+        }
+    }
+}
+
+/// HashShard maps the keys into a fixed number of buckets by their hash,
+/// and every bucket is assigned to a node.
+///
+/// The key space can't be split or merged by keys, the buckets are reassigned instead.
+pub struct HashShard<K: Key + Hash, S: Sender> {
+    nodes: Vec<S>,
+    // bucket -> index of the node.
+    buckets: Vec<usize>,
+    phantom: PhantomData<fn(&K)>,
+}
+
+impl<K: Key + Hash, S: Sender> HashShard<K, S> {
+    /// new assigns the buckets to `nodes` in round robin.
+    pub fn new(buckets: usize, nodes: Vec<S>) -> Self {
+        assert!(buckets > 0 && !nodes.is_empty());
+        Self {
+            buckets: (0..buckets).map(|b| b % nodes.len()).collect(),
+            nodes,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn bucket(&self, key: &K) -> usize {
+        let mut hasher = FnvHasher::default();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.buckets.len() as u64) as usize
+    }
+
+    /// assignment returns the node of every bucket.
+    pub fn assignment(&self) -> &[usize] {
+        &self.buckets
+    }
+
+    pub fn node(&self, node: usize) -> Option<&S> {
+        self.nodes.get(node)
+    }
+
+    /// buckets_of returns the buckets assigned to `node`.
+    pub fn buckets_of(&self, node: usize) -> Vec<usize> {
+        (0..self.buckets.len())
+            .filter(|&b| self.buckets[b] == node)
+            .collect()
+    }
+
+    /// add_node adds a node without any bucket, and returns its index.
+    pub fn add_node(&mut self, sender: S) -> usize {
+        self.nodes.push(sender);
+        self.nodes.len() - 1
+    }
+
+    /// reassign moves `bucket` to `node`, and returns the node it was assigned to.
+    pub fn reassign(&mut self, bucket: usize, node: usize) -> Result<usize> {
+        if bucket >= self.buckets.len() || node >= self.nodes.len() {
+            return Err(ShardError::ReassignError { bucket, node }.into());
+        }
+        Ok(std::mem::replace(&mut self.buckets[bucket], node))
+    }
+}
+
+impl<K: Key + Hash, S: Sender> Shard for HashShard<K, S> {
+    type K = K;
+    type S = S;
+
+    fn split(&mut self, key: Self::K, _: Either<Self::S>) -> Result<()> {
+        Err(ShardError::SplitError(key.to_string()).into())
+    }
+
+    fn merge(&mut self, key: &Self::K) -> Result<Self::S> {
+        Err(ShardError::MergeError(key.to_string()).into())
+    }
+
+    fn key2node(&self, key: &Self::K) -> &Self::S {
+        &self.nodes[self.buckets[self.bucket(key)]]
+    }
+
+    /// The keys of a range are spread over the nodes,
+    /// so every node owning a bucket gets the whole range, in the order of nodes.
+    fn split_range(&self, lower: &K, upper: &K) -> Vec<SubRange<'_, K, S>> {
+        if lower >= upper {
+            return vec![];
+        }
+        let mut owners = vec![false; self.nodes.len()];
+        self.buckets.iter().for_each(|&node| owners[node] = true);
+        (0..self.nodes.len())
+            .filter(|&node| owners[node])
+            .map(|node| SubRange {
+                lower: lower.to_owned(),
+                upper: upper.to_owned(),
+                node: &self.nodes[node],
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::byte::ByteKey;
+    use crate::shard::test::{send_and_check, MockSender};
+    use crate::util::test::run_in_tokio;
+    use crate::util::Error;
+
+    #[test]
+    fn test_route() {
+        let (sender1, rx1) = MockSender::new(1);
+        let (sender2, rx2) = MockSender::new(2);
+        let (sender3, rx3) = MockSender::new(3);
+        let mut shard = HashShard::new(16, vec![sender1, sender2]);
+        let mut rxs = [rx1, rx2, rx3];
+        assert_eq!(shard.buckets_of(0), vec![0, 2, 4, 6, 8, 10, 12, 14]);
+        assert_eq!(shard.buckets_of(1).len(), 8);
+
+        let keys: Vec<ByteKey> = (b'a'..=b'z').map(|c| ByteKey::new(&[c])).collect();
+        // the bucket of a key is stable.
+        let k = ByteKey::new(b"a");
+        assert_eq!(shard.bucket(&k), shard.bucket(&keys[0]));
+        let mut nodes = std::collections::HashSet::new();
+        for key in &keys {
+            nodes.insert(shard.assignment()[shard.bucket(key)]);
+        }
+        assert_eq!(nodes.len(), 2);
+
+        run_in_tokio(async move {
+            for (i, key) in keys.iter().enumerate() {
+                let node = shard.assignment()[shard.bucket(key)];
+                let sender = shard.key2node(key);
+                assert_eq!(sender.1, node as i32 + 1);
+                send_and_check(sender, i, &mut rxs[node]).await;
+            }
+
+            // move the bucket of "a" to a new node.
+            let bucket = shard.bucket(&keys[0]);
+            let node = shard.add_node(sender3);
+            assert_eq!(node, 2);
+            assert!(shard.buckets_of(2).is_empty());
+            let before = shard.reassign(bucket, node).unwrap();
+            assert_eq!(before, bucket % 2);
+            assert_eq!(shard.buckets_of(2), vec![bucket]);
+            let sender = shard.key2node(&keys[0]);
+            assert_eq!(sender.1, 3);
+            send_and_check(sender, 100, &mut rxs[2]).await;
+            for key in keys.iter().filter(|k| shard.bucket(k) != bucket) {
+                assert_ne!(shard.key2node(key).1, 3);
+            }
+            assert_eq!(
+                shard.reassign(16, 0).unwrap_err(),
+                Error::ShardError(ShardError::ReassignError {
+                    bucket: 16,
+                    node: 0
+                })
+            );
+            assert!(shard.reassign(0, 3).is_err());
+
+            // every node owning a bucket scans the whole range.
+            let ranges: Vec<_> = shard
+                .split_range(&b"c".into(), &b"g".into())
+                .into_iter()
+                .map(|r| (r.lower.to_string(), r.upper.to_string(), r.node.1))
+                .collect();
+            let expected: Vec<_> = (1..=3)
+                .map(|id| ("c".to_owned(), "g".to_owned(), id))
+                .collect();
+            assert_eq!(ranges, expected);
+            assert!(shard.split_range(&b"e".into(), &b"d".into()).is_empty());
+            // node 3 owns nothing after its bucket moves back.
+            shard.reassign(bucket, before).unwrap();
+            assert_eq!(shard.split_range(&b"c".into(), &b"g".into()).len(), 2);
+
+            let (sender4, _) = MockSender::new(4);
+            assert!(shard.split(b"c".into(), Either::Right(sender4)).is_err());
+            assert!(shard.merge(&b"c".into()).is_err());
+        });
+    }
+}
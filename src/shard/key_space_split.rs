@@ -1,7 +1,7 @@
 use std::collections::BTreeMap;
 use crate::codec::Key;
 use crate::request::Sender;
-use crate::shard::Shard;
+use crate::shard::{Shard, SubRange};
 use crate::util::{ShardError, Either, Result};
 use std::ops::Bound::{Included, Excluded, Unbounded};
 
@@ -18,6 +18,24 @@ impl<K: Key, S: Sender> KeySpaceSpilt<K, S> {
         }
     }
 
+    /// with_node creates a key space whose whole range is on `node`.
+    pub fn with_node(node: S) -> Self {
+        Self {
+            inner: BTreeMap::new(),
+            begin: Some(node),
+        }
+    }
+
+    /// boundaries returns the split keys in order.
+    pub fn boundaries(&self) -> Vec<K> {
+        self.inner.keys().map(|k| k.to_owned()).collect()
+    }
+
+    /// nodes returns all the nodes in the order of their key ranges.
+    pub fn nodes(&self) -> Vec<&S> {
+        self.begin.iter().chain(self.inner.values()).collect()
+    }
+
     fn left_key(&self, key: &K) -> Option<K> {
         let (lower, upper) = (Unbounded, Excluded(key));
         self.inner.range((lower, upper)).next_back().map(|(k, _)| (k.to_owned()))
@@ -56,12 +74,43 @@ impl<K: Key, S: Sender> Shard for KeySpaceSpilt<K, S> {
         Ok(())
     }
 
+    fn merge(&mut self, key: &Self::K) -> Result<Self::S> {
+        let has_left = self.begin.is_some() || self.left_key(key).is_some();
+        match self.inner.get(key) {
+            Some(_) if has_left => Ok(self.inner.remove(key).unwrap()),
+            _ => Err(ShardError::MergeError(key.to_string()).into()),
+        }
+    }
+
     fn key2node(&self, key: &Self::K) -> &Self::S {
         match self.left_value_include(&key) {
             Some(sender) => sender,
             None => self.begin.as_ref().unwrap(),
         }
     }
+
+    fn split_range(&self, lower: &K, upper: &K) -> Vec<SubRange<'_, K, S>> {
+        if lower >= upper {
+            return vec![];
+        }
+        let mut res = vec![];
+        let (mut start, mut node) = (lower.to_owned(), self.key2node(lower));
+        for (key, sender) in self.inner.range((Excluded(lower), Excluded(upper))) {
+            res.push(SubRange {
+                lower: start,
+                upper: key.to_owned(),
+                node,
+            });
+            start = key.to_owned();
+            node = sender;
+        }
+        res.push(SubRange {
+            lower: start,
+            upper: upper.to_owned(),
+            node,
+        });
+        res
+    }
 }
 
 #[cfg(test)]
@@ -144,4 +193,61 @@ mod tests {
             send_and_check(sender, 5, &mut rx3).await;
         });
     }
+
+    #[test]
+    fn test_split_range() {
+        let mut key_space_spilt = KeySpaceSpilt::new();
+        let (sender1, _rx1) = MockSender::<i32>::new(1);
+        let (sender2, _rx2) = MockSender::new(2);
+        let (sender3, _rx3) = MockSender::new(3);
+        // [b...d...f...]
+        key_space_spilt.split(ByteKey::new(b"b"), Either::Left(sender1)).unwrap();
+        key_space_spilt.split(ByteKey::new(b"d"), Either::Right(sender2)).unwrap();
+        key_space_spilt.split(ByteKey::new(b"f"), Either::Right(sender3)).unwrap();
+
+        let ids: Vec<_> = key_space_spilt.nodes().iter().map(|s| s.1).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        let ranges: Vec<_> = key_space_spilt
+            .split_range(&b"c".into(), &b"g".into())
+            .into_iter()
+            .map(|r| (r.lower.to_string(), r.upper.to_string(), r.node.1))
+            .collect();
+        let expected = vec![("c", "d", 1), ("d", "f", 2), ("f", "g", 3)];
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(l, u, id)| (l.to_owned(), u.to_owned(), id))
+            .collect();
+        assert_eq!(ranges, expected);
+        let ranges = key_space_spilt.split_range(&b"d".into(), &b"e".into());
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].node.1, 2);
+        assert!(key_space_spilt.split_range(&b"e".into(), &b"d".into()).is_empty());
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut key_space_spilt = KeySpaceSpilt::new();
+        let (sender1, _rx1) = MockSender::<i32>::new(1);
+        let (sender2, _rx2) = MockSender::new(2);
+        let (sender3, _rx3) = MockSender::new(3);
+        // [b...d...f...]
+        key_space_spilt.split(ByteKey::new(b"b"), Either::Left(sender1)).unwrap();
+        key_space_spilt.split(ByteKey::new(b"d"), Either::Right(sender2)).unwrap();
+        key_space_spilt.split(ByteKey::new(b"f"), Either::Right(sender3)).unwrap();
+
+        // [b...f...]
+        let sender = key_space_spilt.merge(&b"d".into()).unwrap();
+        assert_eq!(sender.1, 2);
+        assert_eq!(key_space_spilt.key2node(&b"e".into()).1, 1);
+        assert_eq!(key_space_spilt.key2node(&b"f".into()).1, 3);
+        match key_space_spilt.merge(&b"d".into()) {
+            Err(e) => assert_eq!(e, Error::ShardError(ShardError::MergeError("d".to_owned()))),
+            Ok(_) => panic!("d is merged"),
+        }
+        // b is not a boundary, it only sets the first region.
+        assert!(key_space_spilt.merge(&b"b".into()).is_err());
+        assert_eq!(key_space_spilt.merge(&b"f".into()).unwrap().1, 3);
+        assert_eq!(key_space_spilt.key2node(&b"z".into()).1, 1);
+    }
 }
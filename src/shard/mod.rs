@@ -1,6 +1,8 @@
 use crate::codec::Key;
 use crate::request::Sender;
 use crate::util::{Either, Result};
+use futures::future::try_join_all;
+use std::future::Future;
 
 pub trait Shard {
     type K: Key;
@@ -11,8 +13,137 @@ pub trait Shard {
     /// Either::Left will put the sender to the left region,
     /// Either::Right will put the sender to the right region.
     fn split(&mut self, key: Self::K, sender: Either<Self::S>) -> Result<()>;
+    /// merge removes the boundary `key`, the region on the right of `key` joins the left one,
+    /// and the sender of the right region is returned.
+    fn merge(&mut self, key: &Self::K) -> Result<Self::S>;
     fn key2node(&self, key: &Self::K) -> &Self::S;
+    /// split_range splits `[lower, upper)` into the sub-ranges of nodes,
+    /// in key order if the shard is partitioned by range.
+    fn split_range(&self, lower: &Self::K, upper: &Self::K) -> Vec<SubRange<'_, Self::K, Self::S>>;
 }
 
+/// SubRange is the part of a range `[lower, upper)` stored in a node.
+pub struct SubRange<'a, K: Key, S: Sender> {
+    pub lower: K,
+    pub upper: K,
+    pub node: &'a S,
+}
+
+/// scatter_gather runs `f` on the sub-ranges of `[lower, upper)` in parallel,
+/// and merges their sorted results in key order.
+pub async fn scatter_gather<'a, Sh, F, Fut, T>(
+    shard: &'a Sh,
+    lower: &Sh::K,
+    upper: &Sh::K,
+    f: F,
+) -> Result<Vec<(Sh::K, T)>>
+where
+    Sh: Shard,
+    F: Fn(SubRange<'a, Sh::K, Sh::S>) -> Fut,
+    Fut: Future<Output = Result<Vec<(Sh::K, T)>>>,
+{
+    let parts = try_join_all(shard.split_range(lower, upper).into_iter().map(f)).await?;
+    Ok(merge_sorted(parts))
+}
+
+/// merge_sorted merges the sorted parts into one.
+pub fn merge_sorted<K: Key, T>(parts: Vec<Vec<(K, T)>>) -> Vec<(K, T)> {
+    let mut res: Vec<(K, T)> = Vec::with_capacity(parts.iter().map(|p| p.len()).sum());
+    for part in parts {
+        // the parts of a range-partitioned shard are usually in order already.
+        let in_order = match (res.last(), part.first()) {
+            (Some(l), Some(f)) => l.0 <= f.0,
+            _ => true,
+        };
+        if in_order {
+            res.extend(part);
+            continue;
+        }
+        let prev = std::mem::take(&mut res);
+        let (mut a, mut b) = (prev.into_iter().peekable(), part.into_iter().peekable());
+        loop {
+            let take_a = match (a.peek(), b.peek()) {
+                (Some(x), Some(y)) => x.0 <= y.0,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            res.push(if take_a { a.next() } else { b.next() }.unwrap());
+        }
+    }
+    res
+}
+
+mod hash;
 mod key_space_split;
+mod replica;
+mod ring;
+mod splitter;
+#[cfg(test)]
+mod test;
+pub use hash::HashShard;
 pub use key_space_split::KeySpaceSpilt;
+pub use replica::{ReplicaGroup, ReplicatedShard};
+pub use ring::{HashRing, MovedRange};
+pub use splitter::{AutoSplitter, SplitConfig};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Node;
+    use crate::request::channel::new_channel_connect;
+    use crate::util::test::run_in_tokio;
+    use async_trait::async_trait;
+    use std::sync::Arc;
+
+    // RangeNode returns its keys in the requested range.
+    struct RangeNode(Vec<i32>);
+
+    #[async_trait]
+    impl Node for RangeNode {
+        type Req = (i32, i32);
+        type Res = Vec<(i32, i32)>;
+
+        async fn process(&self, (lower, upper): Self::Req) -> Result<Self::Res> {
+            let keys = self.0.iter().filter(|&&k| lower <= k && k < upper);
+            Ok(keys.map(|&k| (k, k * 10)).collect())
+        }
+    }
+
+    #[test]
+    fn test_scatter_gather() {
+        run_in_tokio(async move {
+            let mut shard = KeySpaceSpilt::new();
+            for (i, key) in [0, 10, 20].iter().enumerate() {
+                let keys = (*key..*key + 10).step_by(3).collect();
+                let sender = new_channel_connect(Arc::new(RangeNode(keys)));
+                let sender = if i == 0 {
+                    Either::Left(sender)
+                } else {
+                    Either::Right(sender)
+                };
+                shard.split(*key, sender).unwrap();
+            }
+            let kvs = scatter_gather(&shard, &5, &25, |r| r.node.send((r.lower, r.upper)))
+                .await
+                .unwrap();
+            let keys: Vec<_> = kvs.into_iter().map(|(k, _)| k).collect();
+            assert_eq!(keys, vec![6, 9, 10, 13, 16, 19, 20, 23]);
+            std::mem::forget(shard);
+        });
+    }
+
+    #[test]
+    fn test_merge_sorted() {
+        let parts = vec![
+            vec![(1, 'a'), (5, 'a')],
+            vec![(2, 'b'), (5, 'b')],
+            vec![(7, 'c')],
+        ];
+        let merged = merge_sorted(parts);
+        assert_eq!(
+            merged,
+            vec![(1, 'a'), (2, 'b'), (5, 'a'), (5, 'b'), (7, 'c')]
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use crate::request::Sender;
+use crate::shard::Shard;
+use crate::util::Result;
+use async_trait::async_trait;
+use futures::future::join_all;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// ReplicaGroup is the replica set of a region, one of them is the leader.
+///
+/// It's a `Sender` to the leader, so it can be put into any `Shard`.
+pub struct ReplicaGroup<S: Sender> {
+    replicas: Vec<S>,
+    // the leader may be changed by the routing, e.g. on a not-leader error.
+    leader: AtomicUsize,
+}
+
+impl<S: Sender> ReplicaGroup<S> {
+    /// new makes the first replica the leader.
+    pub fn new(replicas: Vec<S>) -> Self {
+        assert!(!replicas.is_empty());
+        Self {
+            replicas,
+            leader: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn leader(&self) -> &S {
+        &self.replicas[self.leader_index()]
+    }
+
+    pub fn leader_index(&self) -> usize {
+        self.leader.load(Ordering::SeqCst)
+    }
+
+    pub fn set_leader(&self, index: usize) {
+        assert!(index < self.replicas.len());
+        self.leader.store(index, Ordering::SeqCst);
+    }
+
+    pub fn followers(&self) -> Vec<&S> {
+        let leader = self.leader_index();
+        let followers = self
+            .replicas
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != leader);
+        followers.map(|(_, s)| s).collect()
+    }
+
+    pub fn replicas(&self) -> &[S] {
+        &self.replicas
+    }
+
+    /// send_all sends `req` to all the replicas, and returns the responses in order.
+    pub async fn send_all(&self, req: S::Req) -> Vec<Result<S::Res>>
+    where
+        S::Req: Clone,
+    {
+        join_all(self.replicas.iter().map(|s| s.send(req.clone()))).await
+    }
+}
+
+#[async_trait]
+impl<S: Sender + Sync> Sender for ReplicaGroup<S> {
+    type Req = S::Req;
+    type Res = S::Res;
+
+    async fn send(&self, req: Self::Req) -> Result<Self::Res> {
+        self.leader().send(req).await
+    }
+
+    fn close(&mut self) {
+        self.replicas.iter_mut().for_each(|s| s.close());
+    }
+}
+
+/// ReplicatedShard routes the keys to the leaders or the whole groups
+/// of a shard whose regions are `ReplicaGroup`s.
+pub trait ReplicatedShard: Shard {
+    type R: Sender;
+
+    fn key2leader(&self, key: &Self::K) -> &Self::R;
+    fn key2group(&self, key: &Self::K) -> &ReplicaGroup<Self::R>;
+}
+
+impl<T, R> ReplicatedShard for T
+where
+    T: Shard<S = ReplicaGroup<R>>,
+    R: Sender,
+{
+    type R = R;
+
+    fn key2leader(&self, key: &Self::K) -> &R {
+        self.key2node(key).leader()
+    }
+
+    fn key2group(&self, key: &Self::K) -> &ReplicaGroup<R> {
+        self.key2node(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shard::KeySpaceSpilt;
+    use crate::util::test::{run_in_tokio, IdSender};
+    use crate::util::Either;
+
+    fn group(ids: &[u64]) -> ReplicaGroup<IdSender> {
+        ReplicaGroup::new(ids.iter().map(|&id| IdSender(id)).collect())
+    }
+
+    #[test]
+    fn test_replica_group() {
+        let mut shard = KeySpaceSpilt::new();
+        shard.split(0, Either::Left(group(&[1, 2, 3]))).unwrap();
+        shard.split(50, Either::Right(group(&[4, 5, 6]))).unwrap();
+        run_in_tokio(async move {
+            assert_eq!(shard.key2leader(&10).0, 1);
+            assert_eq!(shard.key2leader(&60).0, 4);
+            let g = shard.key2group(&60);
+            let followers: Vec<_> = g.followers().iter().map(|s| s.0).collect();
+            assert_eq!(followers, vec![5, 6]);
+            assert_eq!(g.replicas().len(), 3);
+
+            // the requests go to the leader.
+            assert_eq!(shard.key2node(&60).send(7).await.unwrap(), 407);
+            g.set_leader(2);
+            assert_eq!(shard.key2leader(&60).0, 6);
+            assert_eq!(shard.key2node(&60).send(7).await.unwrap(), 607);
+            let followers: Vec<_> = g.followers().iter().map(|s| s.0).collect();
+            assert_eq!(followers, vec![4, 5]);
+            // the other group is not affected.
+            assert_eq!(shard.key2leader(&49).0, 1);
+
+            let res: Vec<_> = shard
+                .key2group(&10)
+                .send_all(1)
+                .await
+                .into_iter()
+                .map(|r| r.unwrap())
+                .collect();
+            assert_eq!(res, vec![101, 201, 301]);
+
+            let leaders: Vec<_> = shard
+                .split_range(&10, &60)
+                .into_iter()
+                .map(|r| r.node.leader().0)
+                .collect();
+            assert_eq!(leaders, vec![1, 6]);
+        });
+    }
+}
@@ -0,0 +1,259 @@
+use crate::codec::Key;
+use crate::request::Sender;
+use crate::shard::hash::FnvHasher;
+use crate::shard::{Shard, SubRange};
+use crate::util::{Either, Result, ShardError};
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+// hash_of places the keys and the tokens on the ring, the bits of fnv are mixed at last,
+// since fnv alone spreads the short keys poorly.
+fn hash_of<T: Hash + ?Sized>(t: &T) -> u64 {
+    let mut hasher = FnvHasher::default();
+    t.hash(&mut hasher);
+    let mut h = hasher.finish();
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^ (h >> 31)
+}
+
+/// MovedRange is an arc `(lower, upper]` of the hash ring whose owner is changed,
+/// the arc wraps around if `lower >= upper`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MovedRange {
+    pub lower: u64,
+    pub upper: u64,
+    pub from: u64,
+    pub to: u64,
+}
+
+impl MovedRange {
+    pub fn contains(&self, hash: u64) -> bool {
+        if self.lower < self.upper {
+            self.lower < hash && hash <= self.upper
+        } else {
+            self.lower < hash || hash <= self.upper
+        }
+    }
+
+    /// len returns the number of hashes in the arc.
+    pub fn len(&self) -> u64 {
+        self.upper.wrapping_sub(self.lower)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// HashRing is a consistent-hash ring, every node owns `vnodes` tokens on it,
+/// and a key belongs to the first token at or after its hash.
+pub struct HashRing<K: Key + Hash, S: Sender> {
+    vnodes: usize,
+    // token -> id of the node.
+    ring: BTreeMap<u64, u64>,
+    nodes: BTreeMap<u64, S>,
+    phantom: PhantomData<fn(&K)>,
+}
+
+impl<K: Key + Hash, S: Sender> HashRing<K, S> {
+    pub fn new(vnodes: usize) -> Self {
+        assert!(vnodes > 0);
+        Self {
+            vnodes,
+            ring: BTreeMap::new(),
+            nodes: BTreeMap::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn hash(&self, key: &K) -> u64 {
+        hash_of(key)
+    }
+
+    pub fn node_ids(&self) -> Vec<u64> {
+        self.nodes.keys().copied().collect()
+    }
+
+    /// tokens returns the tokens of node `id` in order.
+    pub fn tokens(&self, id: u64) -> Vec<u64> {
+        let tokens = self.ring.iter().filter(|(_, &node)| node == id);
+        tokens.map(|(&token, _)| token).collect()
+    }
+
+    /// add_node puts the tokens of the node `id` on the ring,
+    /// and returns the arcs moved to it.
+    pub fn add_node(&mut self, id: u64, sender: S) -> Result<Vec<MovedRange>> {
+        if self.nodes.contains_key(&id) {
+            return Err(ShardError::AddNodeError(id).into());
+        }
+        let before = self.ring.clone();
+        for i in 0..self.vnodes {
+            let mut token = hash_of(&(id, i as u64));
+            // the tokens collide rarely, take the next free one.
+            while self.ring.contains_key(&token) {
+                token = token.wrapping_add(1);
+            }
+            self.ring.insert(token, id);
+        }
+        self.nodes.insert(id, sender);
+        Ok(moved_ranges(&before, &self.ring))
+    }
+
+    /// remove_node takes the tokens of the node `id` off the ring,
+    /// and returns its sender and the arcs moved from it.
+    pub fn remove_node(&mut self, id: u64) -> Result<(S, Vec<MovedRange>)> {
+        let sender = match self.nodes.remove(&id) {
+            Some(sender) => sender,
+            None => return Err(ShardError::RemoveNodeError(id).into()),
+        };
+        let before = self.ring.clone();
+        self.ring.retain(|_, node| *node != id);
+        Ok((sender, moved_ranges(&before, &self.ring)))
+    }
+}
+
+fn owner(ring: &BTreeMap<u64, u64>, hash: u64) -> Option<u64> {
+    let mut next = ring.range(hash..).chain(ring.iter());
+    next.next().map(|(_, &node)| node)
+}
+
+// moved_ranges compares the owners of the arcs between all the tokens of both rings,
+// the adjacent arcs with the same move are merged.
+fn moved_ranges(before: &BTreeMap<u64, u64>, after: &BTreeMap<u64, u64>) -> Vec<MovedRange> {
+    let tokens: Vec<u64> = before
+        .keys()
+        .chain(after.keys())
+        .copied()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let mut res: Vec<MovedRange> = vec![];
+    for (i, &upper) in tokens.iter().enumerate() {
+        let lower = tokens[(i + tokens.len() - 1) % tokens.len()];
+        let (from, to) = match (owner(before, upper), owner(after, upper)) {
+            (Some(from), Some(to)) if from != to => (from, to),
+            _ => continue,
+        };
+        match res.last_mut() {
+            Some(last) if last.upper == lower && last.from == from && last.to == to => {
+                last.upper = upper
+            }
+            _ => res.push(MovedRange {
+                lower,
+                upper,
+                from,
+                to,
+            }),
+        }
+    }
+    res
+}
+
+impl<K: Key + Hash, S: Sender> Shard for HashRing<K, S> {
+    type K = K;
+    type S = S;
+
+    fn split(&mut self, key: Self::K, _: Either<Self::S>) -> Result<()> {
+        Err(ShardError::SplitError(key.to_string()).into())
+    }
+
+    fn merge(&mut self, key: &Self::K) -> Result<Self::S> {
+        Err(ShardError::MergeError(key.to_string()).into())
+    }
+
+    fn key2node(&self, key: &Self::K) -> &Self::S {
+        let id = owner(&self.ring, self.hash(key)).unwrap();
+        &self.nodes[&id]
+    }
+
+    /// Every node gets the whole range, in the order of node ids.
+    fn split_range(&self, lower: &K, upper: &K) -> Vec<SubRange<'_, K, S>> {
+        if lower >= upper {
+            return vec![];
+        }
+        let ranges = self.nodes.values().map(|node| SubRange {
+            lower: lower.to_owned(),
+            upper: upper.to_owned(),
+            node,
+        });
+        ranges.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test::IdSender;
+
+    fn route(ring: &HashRing<i32, IdSender>) -> Vec<u64> {
+        (0..1000).map(|key| ring.key2node(&key).0).collect()
+    }
+
+    // check_moved checks only the keys in the moved ranges change their nodes.
+    fn check_moved(ring: &HashRing<i32, IdSender>, before: &[u64], moved: &[MovedRange]) {
+        let after = route(ring);
+        for key in 0..1000 {
+            let hash = ring.hash(&key);
+            match moved.iter().find(|r| r.contains(hash)) {
+                Some(r) => {
+                    assert_eq!(before[key as usize], r.from);
+                    assert_eq!(after[key as usize], r.to);
+                }
+                None => assert_eq!(before[key as usize], after[key as usize]),
+            }
+        }
+    }
+
+    #[test]
+    fn test_ring() {
+        let mut ring = HashRing::new(64);
+        assert!(ring.add_node(1, IdSender(1)).unwrap().is_empty());
+        assert!(route(&ring).iter().all(|&id| id == 1));
+        assert_eq!(ring.tokens(1).len(), 64);
+
+        let before = route(&ring);
+        let moved = ring.add_node(2, IdSender(2)).unwrap();
+        assert!(moved.iter().all(|r| r.from == 1 && r.to == 2));
+        check_moved(&ring, &before, &moved);
+        // about a half of the ring is moved.
+        let total: u128 = moved.iter().map(|r| r.len() as u128).sum();
+        let ratio = total as f64 / u64::MAX as f64;
+        assert!(ratio > 0.3 && ratio < 0.7, "{}", ratio);
+
+        let before = route(&ring);
+        let moved = ring.add_node(3, IdSender(3)).unwrap();
+        assert!(moved.iter().all(|r| r.to == 3));
+        check_moved(&ring, &before, &moved);
+        let after = route(&ring);
+        for id in 1..=3 {
+            let count = after.iter().filter(|&&n| n == id).count();
+            assert!(
+                count > 150 && count < 550,
+                "node {} owns {} keys",
+                id,
+                count
+            );
+        }
+        assert!(ring.add_node(3, IdSender(3)).is_err());
+
+        let before = route(&ring);
+        let (sender, moved) = ring.remove_node(2).unwrap();
+        assert_eq!(sender.0, 2);
+        assert!(moved.iter().all(|r| r.from == 2 && r.to != 2));
+        check_moved(&ring, &before, &moved);
+        assert!(ring.tokens(2).is_empty());
+        assert_eq!(ring.node_ids(), vec![1, 3]);
+        assert!(ring.remove_node(2).is_err());
+
+        let ranges: Vec<_> = ring
+            .split_range(&0, &10)
+            .into_iter()
+            .map(|r| (r.lower, r.upper, r.node.0))
+            .collect();
+        assert_eq!(ranges, vec![(0, 10, 1), (0, 10, 3)]);
+        assert!(ring.split_range(&10, &0).is_empty());
+        assert!(ring.split(5, Either::Right(IdSender(4))).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use crate::codec::Key;
+use crate::request::Sender;
+use crate::shard::{KeySpaceSpilt, Shard};
+use crate::util::{Either, Result};
+use std::collections::BTreeMap;
+use std::ops::Bound::{self, Excluded, Included, Unbounded};
+use std::time::Duration;
+
+/// A region is split once it exceeds any of the limits, `None` disables the limit.
+#[derive(Clone, Debug)]
+pub struct SplitConfig {
+    pub max_keys: Option<u64>,
+    pub max_bytes: Option<u64>,
+    /// The max requests per second in a load window.
+    pub max_qps: Option<f64>,
+}
+
+impl Default for SplitConfig {
+    fn default() -> Self {
+        Self {
+            max_keys: Some(1_440_000),
+            max_bytes: Some(144 << 20),
+            max_qps: None,
+        }
+    }
+}
+
+/// AutoSplitter watches the size and load of every key, and picks split keys
+/// of the `KeySpaceSpilt` regions exceeding the limits.
+///
+/// The split key divides the region into 2 halves with the same key count,
+/// byte size or request count, according to the exceeded limit.
+pub struct AutoSplitter<K: Key> {
+    config: SplitConfig,
+    // key -> bytes
+    sizes: BTreeMap<K, u64>,
+    // key -> requests in the current load window
+    requests: BTreeMap<K, u64>,
+    window_start: Duration,
+}
+
+impl<K: Key> AutoSplitter<K> {
+    pub fn new(config: SplitConfig) -> Self {
+        Self {
+            config,
+            sizes: BTreeMap::new(),
+            requests: BTreeMap::new(),
+            window_start: Duration::from_millis(0),
+        }
+    }
+
+    pub fn on_put(&mut self, key: &K, bytes: u64) {
+        match self.sizes.get_mut(key) {
+            Some(size) => *size = bytes,
+            None => {
+                self.sizes.insert(key.to_owned(), bytes);
+            }
+        }
+    }
+
+    pub fn on_del(&mut self, key: &K) {
+        self.sizes.remove(key);
+    }
+
+    pub fn on_request(&mut self, key: &K) {
+        match self.requests.get_mut(key) {
+            Some(count) => *count += 1,
+            None => {
+                self.requests.insert(key.to_owned(), 1);
+            }
+        }
+    }
+
+    /// split_keys returns the split keys of the regions separated by `boundaries`,
+    /// and starts a new load window at `now`.
+    pub fn split_keys(&mut self, boundaries: &[K], now: Duration) -> Vec<K> {
+        let window = now.saturating_sub(self.window_start).as_secs_f64();
+        let mut keys = vec![];
+        for i in 0..=boundaries.len() {
+            let lower = if i == 0 {
+                Unbounded
+            } else {
+                Included(&boundaries[i - 1])
+            };
+            let upper = boundaries.get(i).map_or(Unbounded, Excluded);
+            if let Some(key) = self.split_key(lower, upper, window) {
+                keys.push(key);
+            }
+        }
+        self.requests.clear();
+        self.window_start = now;
+        keys
+    }
+
+    /// split checks the regions of the shard and splits them,
+    /// the right half of a split region is served by the sender created by `new_node`.
+    pub fn split<S, F>(
+        &mut self,
+        shard: &mut KeySpaceSpilt<K, S>,
+        now: Duration,
+        mut new_node: F,
+    ) -> Result<Vec<K>>
+    where
+        S: Sender,
+        F: FnMut(&K) -> S,
+    {
+        let keys = self.split_keys(&shard.boundaries(), now);
+        for key in keys.iter() {
+            shard.split(key.to_owned(), Either::Right(new_node(key)))?;
+        }
+        Ok(keys)
+    }
+
+    fn split_key(&self, lower: Bound<&K>, upper: Bound<&K>, window: f64) -> Option<K> {
+        let sizes = || self.sizes.range((lower, upper));
+        let requests = || self.requests.range((lower, upper));
+        let count = sizes().count() as u64;
+        let bytes: u64 = sizes().map(|(_, b)| b).sum();
+        let load: u64 = requests().map(|(_, c)| c).sum();
+        let key = if matches!(self.config.max_keys, Some(max) if count > max) {
+            sizes().nth(count as usize / 2).map(|(k, _)| k)
+        } else if matches!(self.config.max_bytes, Some(max) if bytes > max) {
+            Self::half_key(sizes(), bytes)
+        } else if window > 0.0
+            && matches!(self.config.max_qps, Some(max) if load as f64 / window > max)
+        {
+            Self::half_key(requests(), load)
+        } else {
+            None
+        };
+        // the region can't be split at its start key, e.g. a single hot key.
+        match (key, lower) {
+            (Some(key), Included(lower)) if key == lower => None,
+            (key, _) => key.map(|k| k.to_owned()),
+        }
+    }
+
+    // half_key returns the first key whose accumulated weight reaches the half of `total`.
+    fn half_key<'a>(iter: impl Iterator<Item = (&'a K, &'a u64)>, total: u64) -> Option<&'a K> {
+        let mut sum = 0;
+        for (key, weight) in iter {
+            sum += weight;
+            if sum * 2 >= total {
+                return Some(key);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test::IdSender;
+
+    #[test]
+    fn test_split_by_size() {
+        let mut shard = KeySpaceSpilt::new();
+        shard.split(0, Either::Left(IdSender(0))).unwrap();
+        let config = SplitConfig {
+            max_keys: Some(100),
+            max_bytes: Some(1000),
+            max_qps: None,
+        };
+        let mut splitter = AutoSplitter::new(config);
+        for key in 0..100 {
+            splitter.on_put(&key, 1);
+        }
+        let mut id = 0;
+        let mut new_node = |_: &i32| {
+            id += 1;
+            IdSender(id)
+        };
+        let now = Duration::from_secs(1);
+        assert!(splitter
+            .split(&mut shard, now, &mut new_node)
+            .unwrap()
+            .is_empty());
+        splitter.on_put(&100, 1);
+        assert_eq!(
+            splitter.split(&mut shard, now, &mut new_node).unwrap(),
+            vec![50]
+        );
+        assert_eq!(shard.key2node(&49).0, 0);
+        assert_eq!(shard.key2node(&50).0, 1);
+
+        // the big values are in [90, 100].
+        for key in 90..=100 {
+            splitter.on_put(&key, 100);
+        }
+        assert_eq!(
+            splitter.split(&mut shard, now, &mut new_node).unwrap(),
+            vec![95]
+        );
+        assert_eq!(shard.key2node(&95).0, 2);
+        for key in 50..=100 {
+            splitter.on_del(&key);
+        }
+        assert!(splitter
+            .split(&mut shard, now, &mut new_node)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_split_by_load() {
+        let config = SplitConfig {
+            max_keys: None,
+            max_bytes: None,
+            max_qps: Some(10.0),
+        };
+        let mut splitter = AutoSplitter::new(config);
+        let boundaries = vec![0, 100];
+        for key in 0..10 {
+            splitter.on_request(&key);
+        }
+        assert!(splitter
+            .split_keys(&boundaries, Duration::from_secs(1))
+            .is_empty());
+        for key in 0..20 {
+            splitter.on_request(&(key % 10));
+        }
+        assert_eq!(
+            splitter.split_keys(&boundaries, Duration::from_secs(2)),
+            vec![4]
+        );
+        // the load window is reset.
+        assert!(splitter
+            .split_keys(&boundaries, Duration::from_secs(3))
+            .is_empty());
+        // a hot key can't be split.
+        for _ in 0..100 {
+            splitter.on_request(&100);
+        }
+        assert!(splitter
+            .split_keys(&boundaries, Duration::from_secs(4))
+            .is_empty());
+    }
+}
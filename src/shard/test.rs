@@ -0,0 +1,44 @@
+use crate::request::Sender;
+use crate::util::{Error, Result};
+use async_trait::async_trait;
+use std::fmt::Debug;
+use std::sync::mpsc::{channel, Receiver as stdReceiver, Sender as stdSender};
+use std::sync::Mutex;
+
+/// MockSender forwards the requests to a channel and fails them with `Error::Unknown`,
+/// the second field is the id of the node.
+pub struct MockSender<T: Copy + Send + Debug + PartialEq>(Mutex<stdSender<T>>, pub i32);
+
+impl<T: Copy + Send + Debug + PartialEq> MockSender<T> {
+    pub fn new(i: i32) -> (Self, stdReceiver<T>) {
+        let (tx, rx) = channel();
+        (MockSender(Mutex::new(tx), i), rx)
+    }
+}
+
+#[async_trait]
+impl<T: Copy + Send + Debug + PartialEq> Sender for MockSender<T> {
+    type Req = T;
+    type Res = T;
+
+    async fn send(&self, req: Self::Req) -> Result<Self::Res> {
+        let sender = self.0.lock().unwrap();
+        sender.send(req).unwrap();
+        Err(Error::Unknown)
+    }
+
+    fn close(&mut self) {
+        unreachable!()
+    }
+}
+
+pub async fn send_and_check<T: Copy + Send + Debug + PartialEq>(
+    sender: &MockSender<T>,
+    req: T,
+    rx: &mut stdReceiver<T>,
+) {
+    let send_res = sender.send(req).await;
+    assert_eq!(send_res.unwrap_err(), Error::Unknown);
+    let res = rx.recv().unwrap();
+    assert_eq!(res, req);
+}
@@ -0,0 +1,312 @@
+use crate::util::{Rng, Timer};
+use futures::future::BoxFuture;
+use futures::task::{self, ArcWake, Context, Poll};
+use std::collections::{BTreeMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+mod sender;
+pub use sender::SimSender;
+
+enum TaskState {
+    Idle(BoxFuture<'static, ()>),
+    // the future is taken out by the scheduler.
+    Running,
+}
+
+struct Inner {
+    tasks: Vec<Option<TaskState>>,
+    ready: Vec<usize>,
+    queued: HashSet<usize>,
+    // (deadline, seq) -> waker
+    timers: BTreeMap<(Duration, u64), task::Waker>,
+    timer_seq: u64,
+    now: Duration,
+    rng: Rng,
+    latency: (Duration, Duration),
+}
+
+impl Inner {
+    fn wake(&mut self, id: usize) {
+        if self.queued.insert(id) {
+            self.ready.push(id);
+        }
+    }
+}
+
+struct TaskWaker {
+    id: usize,
+    inner: Weak<Mutex<Inner>>,
+}
+
+impl ArcWake for TaskWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        if let Some(inner) = arc_self.inner.upgrade() {
+            inner.lock().unwrap().wake(arc_self.id);
+        }
+    }
+}
+
+/// Simulator is a single-threaded deterministic executor with a virtual clock.
+///
+/// The ready tasks are polled in an order chosen by the seeded rng,
+/// and the clock jumps to the next timer when no task is ready,
+/// so running the same seed twice gives exactly the same interleaving.
+/// The nodes should not use real time or spawn tasks into other runtimes.
+#[derive(Clone)]
+pub struct Simulator {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Simulator {
+    pub fn new(seed: u64) -> Self {
+        let inner = Inner {
+            tasks: vec![],
+            ready: vec![],
+            queued: HashSet::new(),
+            timers: BTreeMap::new(),
+            timer_seq: 0,
+            now: Duration::from_millis(0),
+            rng: Rng::new(seed),
+            latency: (Duration::from_millis(1), Duration::from_millis(10)),
+        };
+        Self {
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+
+    /// set_latency sets the range of one-way network latency of `SimSender`.
+    pub fn set_latency(&self, min: Duration, max: Duration) {
+        assert!(min <= max);
+        self.inner.lock().unwrap().latency = (min, max);
+    }
+
+    /// now returns the virtual time since the simulator is created.
+    pub fn now(&self) -> Duration {
+        self.inner.lock().unwrap().now
+    }
+
+    /// gen_range returns a random number in `[lower, upper)` from the seeded rng.
+    pub fn gen_range(&self, lower: u64, upper: u64) -> u64 {
+        self.inner.lock().unwrap().rng.gen_range(lower, upper)
+    }
+
+    pub(crate) fn latency(&self) -> Duration {
+        let mut inner = self.inner.lock().unwrap();
+        let (min, max) = inner.latency;
+        if min == max {
+            return min;
+        }
+        let nanos = inner
+            .rng
+            .gen_range(min.as_nanos() as u64, max.as_nanos() as u64);
+        Duration::from_nanos(nanos)
+    }
+
+    pub fn spawn<F>(&self, f: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut inner = self.inner.lock().unwrap();
+        let id = inner.tasks.len();
+        inner.tasks.push(Some(TaskState::Idle(Box::pin(f))));
+        inner.wake(id);
+    }
+
+    pub fn sleep(&self, duration: Duration) -> Sleep {
+        Sleep {
+            sim: self.clone(),
+            deadline: self.now() + duration,
+        }
+    }
+
+    /// block_on runs the simulation until `f` is finished,
+    /// the other tasks are left in the simulator and continue in the next `block_on`.
+    pub fn block_on<F>(&self, f: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let output = Arc::new(Mutex::new(None));
+        let o = output.clone();
+        self.spawn(async move {
+            let res = f.await;
+            *o.lock().unwrap() = Some(res);
+        });
+        loop {
+            if let Some(res) = output.lock().unwrap().take() {
+                return res;
+            }
+            if !self.poll_one() && !self.fire_timers() {
+                panic!("deadlock, no task can make progress");
+            }
+        }
+    }
+
+    /// shutdown drops all the tasks and timers.
+    pub fn shutdown(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        let tasks = std::mem::take(&mut inner.tasks);
+        let timers = std::mem::take(&mut inner.timers);
+        inner.ready.clear();
+        inner.queued.clear();
+        drop(inner);
+        // the tasks may hold the simulator, drop them without the lock.
+        drop(tasks);
+        drop(timers);
+    }
+
+    fn poll_one(&self) -> bool {
+        let (id, mut fut) = {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.ready.is_empty() {
+                return false;
+            }
+            let len = inner.ready.len() as u64;
+            let i = inner.rng.gen_range(0, len) as usize;
+            let id = inner.ready.swap_remove(i);
+            inner.queued.remove(&id);
+            match inner.tasks[id].replace(TaskState::Running) {
+                Some(TaskState::Idle(fut)) => (id, fut),
+                other => {
+                    // the task is finished.
+                    inner.tasks[id] = other;
+                    return true;
+                }
+            }
+        };
+        let waker = task::waker(Arc::new(TaskWaker {
+            id,
+            inner: Arc::downgrade(&self.inner),
+        }));
+        let mut cx = Context::from_waker(&waker);
+        let poll = fut.as_mut().poll(&mut cx);
+        let mut inner = self.inner.lock().unwrap();
+        if inner.tasks.len() <= id {
+            // the simulator is shut down.
+            return true;
+        }
+        match poll {
+            Poll::Pending => inner.tasks[id] = Some(TaskState::Idle(fut)),
+            Poll::Ready(()) => {
+                inner.tasks[id] = None;
+                drop(inner);
+                drop(fut);
+            }
+        }
+        true
+    }
+
+    fn fire_timers(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let deadline = match inner.timers.keys().next() {
+            Some((deadline, _)) => *deadline,
+            None => return false,
+        };
+        if deadline > inner.now {
+            inner.now = deadline;
+        }
+        while let Some((&(d, seq), _)) = inner.timers.iter().next() {
+            if d > deadline {
+                break;
+            }
+            let waker = inner.timers.remove(&(d, seq)).unwrap();
+            drop(inner);
+            waker.wake();
+            inner = self.inner.lock().unwrap();
+        }
+        true
+    }
+}
+
+impl Timer for Simulator {
+    fn now(&self) -> Duration {
+        Simulator::now(self)
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(Simulator::sleep(self, duration))
+    }
+}
+
+/// Sleep is finished when the virtual clock reaches the deadline.
+pub struct Sleep {
+    sim: Simulator,
+    deadline: Duration,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut inner = self.sim.inner.lock().unwrap();
+        if inner.now >= self.deadline {
+            return Poll::Ready(());
+        }
+        let seq = inner.timer_seq;
+        inner.timer_seq += 1;
+        inner
+            .timers
+            .insert((self.deadline, seq), cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace(seed: u64) -> (Vec<(u64, u64)>, Duration) {
+        let sim = Simulator::new(seed);
+        let trace = Arc::new(Mutex::new(vec![]));
+        let s = sim.clone();
+        let t = trace.clone();
+        let elapsed = sim.block_on(async move {
+            let (tx, rx) = futures::channel::mpsc::unbounded();
+            for task in 0..10 {
+                let s2 = s.clone();
+                let t = t.clone();
+                let tx = tx.clone();
+                s.spawn(async move {
+                    for step in 0..10 {
+                        let d = s2.latency();
+                        s2.sleep(d).await;
+                        t.lock().unwrap().push((task, step));
+                    }
+                    tx.unbounded_send(()).unwrap();
+                });
+            }
+            drop(tx);
+            futures::StreamExt::collect::<Vec<_>>(rx).await;
+            s.now()
+        });
+        sim.shutdown();
+        let trace = trace.lock().unwrap().clone();
+        (trace, elapsed)
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let (t1, e1) = trace(1);
+        let (t2, e2) = trace(1);
+        let (t3, _) = trace(2);
+        assert_eq!(t1.len(), 100);
+        assert_eq!(t1, t2);
+        assert_eq!(e1, e2);
+        assert_ne!(t1, t3);
+    }
+
+    #[test]
+    fn test_sleep() {
+        let sim = Simulator::new(0);
+        let s = sim.clone();
+        sim.block_on(async move {
+            s.sleep(Duration::from_secs(3600)).await;
+            assert_eq!(s.now(), Duration::from_secs(3600));
+        });
+        // the virtual clock is not affected by real time.
+        assert_eq!(sim.now(), Duration::from_secs(3600));
+    }
+}
@@ -0,0 +1,206 @@
+use crate::node::Node;
+use crate::request::{Request, Response, Sender, Transport};
+use crate::sim::Simulator;
+use crate::util::{RequestError, Result};
+use async_trait::async_trait;
+use futures::channel::oneshot;
+use futures::future::BoxFuture;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+type Handler<Req, Res> = dyn Fn(Req) -> BoxFuture<'static, Result<Res>> + Send + Sync;
+
+/// SimSender delivers the requests inside the simulator,
+/// both the request and the response are delayed by the simulated latency.
+pub struct SimSender<Req: Request, Res: Response> {
+    sim: Simulator,
+    handler: Arc<Handler<Req, Res>>,
+    closed: AtomicBool,
+}
+
+impl Simulator {
+    /// connect creates a sender to the node, the requests are processed by the simulator's tasks.
+    pub fn connect<Req, Res, N>(&self, node: Arc<N>) -> SimSender<Req, Res>
+    where
+        Req: Request + 'static,
+        Res: Response + 'static,
+        N: Node<Req = Req, Res = Res> + Sync + Send + 'static,
+    {
+        let handler = move |req: Req| -> BoxFuture<'static, Result<Res>> {
+            let node = node.clone();
+            Box::pin(async move { node.process(req).await })
+        };
+        SimSender {
+            sim: self.clone(),
+            handler: Arc::new(handler),
+            closed: AtomicBool::new(false),
+        }
+    }
+}
+
+#[async_trait]
+impl<N> Transport<N> for Simulator
+where
+    N: Node + Sync + Send + 'static,
+    N::Req: 'static,
+    N::Res: 'static,
+{
+    type S = SimSender<N::Req, N::Res>;
+
+    async fn connect(&self, node: Arc<N>) -> Result<Self::S> {
+        Ok(Simulator::connect(self, node))
+    }
+}
+
+#[async_trait]
+impl<Req, Res> Sender for SimSender<Req, Res>
+where
+    Req: Request + 'static,
+    Res: Response + 'static,
+{
+    type Req = Req;
+    type Res = Res;
+
+    async fn send(&self, req: Self::Req) -> Result<Self::Res> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err(RequestError::SendError("sender is closed".to_owned()).into());
+        }
+        self.sim.sleep(self.sim.latency()).await;
+        let (tx, rx) = oneshot::channel();
+        let f = (self.handler)(req);
+        self.sim.spawn(async move {
+            let _ = tx.send(f.await);
+        });
+        let res = rx
+            .await
+            .map_err(|e| RequestError::SendError(e.to_string()))?;
+        self.sim.sleep(self.sim.latency()).await;
+        res
+    }
+
+    fn close(&mut self) {
+        self.closed.store(true, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::{Consensus, RaftConfig, RaftNode, RaftRequest, RaftResponse};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    struct LogNode {
+        id: u64,
+        log: Arc<Mutex<Vec<(u64, u64)>>>,
+    }
+
+    #[async_trait]
+    impl Node for LogNode {
+        type Req = u64;
+        type Res = u64;
+
+        async fn process(&self, req: Self::Req) -> Result<Self::Res> {
+            self.log.lock().unwrap().push((self.id, req));
+            Ok(req)
+        }
+    }
+
+    fn message_order(seed: u64) -> Vec<(u64, u64)> {
+        let sim = Simulator::new(seed);
+        let log = Arc::new(Mutex::new(vec![]));
+        let senders: Vec<_> = (0..3)
+            .map(|id| {
+                let node = LogNode {
+                    id,
+                    log: log.clone(),
+                };
+                Arc::new(sim.connect(Arc::new(node)))
+            })
+            .collect();
+        let s = sim.clone();
+        sim.block_on(async move {
+            let handles: Vec<_> = (0..5)
+                .map(|client| {
+                    let (tx, rx) = oneshot::channel();
+                    let senders = senders.clone();
+                    s.spawn(async move {
+                        for i in 0..20 {
+                            let req = client * 100 + i;
+                            let res = senders[(req % 3) as usize].send(req).await.unwrap();
+                            assert_eq!(res, req);
+                        }
+                        tx.send(()).unwrap();
+                    });
+                    rx
+                })
+                .collect();
+            futures::future::join_all(handles).await;
+        });
+        sim.shutdown();
+        let log = log.lock().unwrap().clone();
+        log
+    }
+
+    #[test]
+    fn test_sim_sender() {
+        let order = message_order(7);
+        assert_eq!(order.len(), 100);
+        assert_eq!(order, message_order(7));
+        assert_ne!(order, message_order(8));
+    }
+
+    type Raft = RaftNode<u64, SimSender<RaftRequest<u64>, RaftResponse>>;
+
+    // elect_and_propose returns the leader, the virtual time it's elected and the applied entries.
+    fn elect_and_propose(seed: u64) -> (u64, Duration, Vec<(u64, u64)>) {
+        let sim = Simulator::new(seed);
+        let applied = Arc::new(Mutex::new(vec![]));
+        let group: Vec<Arc<Raft>> = (1..=3)
+            .map(|id| {
+                let applied = applied.clone();
+                let apply = move |index, data| applied.lock().unwrap().push((index, data));
+                Arc::new(RaftNode::new(id, RaftConfig::default(), apply))
+            })
+            .collect();
+        for node in group.iter() {
+            for peer in group.iter() {
+                if peer.id() != node.id() {
+                    node.add_peer(peer.id(), sim.connect(peer.clone()));
+                }
+            }
+            let (node, s) = (node.clone(), sim.clone());
+            sim.spawn(async move {
+                loop {
+                    s.sleep(Duration::from_millis(10)).await;
+                    node.tick().await;
+                }
+            });
+        }
+        let s = sim.clone();
+        let res = sim.block_on(async move {
+            let leader = loop {
+                s.sleep(Duration::from_millis(10)).await;
+                if let Some(leader) = group.iter().find(|n| n.is_leader()) {
+                    break leader.clone();
+                }
+            };
+            let elected_at = s.now();
+            for i in 0..10 {
+                leader.propose(i).await.unwrap();
+            }
+            (leader.id(), elected_at)
+        });
+        sim.shutdown();
+        let applied = applied.lock().unwrap().clone();
+        (res.0, res.1, applied)
+    }
+
+    #[test]
+    fn test_sim_raft() {
+        let (leader, elected_at, applied) = elect_and_propose(3);
+        assert!(elected_at >= Duration::from_millis(100));
+        assert!(applied.len() >= 10);
+        assert_eq!((leader, elected_at, applied), elect_and_propose(3));
+    }
+}
@@ -0,0 +1,441 @@
+use crate::codec::{take_bytes, Encode, Key, Value};
+use crate::storage::{decode_mutation, encode_mutation, scan_map, Engine, WriteBatch};
+use crate::util::{crc32, Result, StorageError};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+
+const WAL: &str = "wal.log";
+const SNAPSHOT: &str = "snapshot";
+const SNAPSHOT_TMP: &str = "snapshot.tmp";
+
+// a batch is a single record, so it's recovered all or nothing,
+// its tag follows the tags of the mutations.
+const BATCH: u8 = 2;
+
+#[derive(Clone, Debug)]
+pub struct DiskConfig {
+    /// A snapshot is written after every `snapshot_interval` records of the WAL, 0 disables it.
+    pub snapshot_interval: u64,
+    /// Sync the WAL to the disk on every write.
+    pub sync: bool,
+}
+
+impl Default for DiskConfig {
+    fn default() -> Self {
+        Self {
+            snapshot_interval: 10_000,
+            sync: true,
+        }
+    }
+}
+
+struct Wal {
+    file: File,
+    // the number of records since the last snapshot.
+    records: u64,
+}
+
+impl Wal {
+    fn append(&mut self, payload: &[u8], sync: bool) -> Result<()> {
+        let mut buf = Vec::with_capacity(payload.len() + 8);
+        encode_record(payload, &mut buf);
+        self.file.write_all(&buf)?;
+        if sync {
+            self.file.sync_data()?;
+        }
+        self.records += 1;
+        Ok(())
+    }
+}
+
+// a record is `len: u32 | crc32 of payload: u32 | payload`.
+fn encode_record(payload: &[u8], buf: &mut Vec<u8>) {
+    (payload.len() as u32).encode(buf);
+    crc32(payload).encode(buf);
+    buf.extend_from_slice(payload);
+}
+
+// read_records returns the payloads of the valid records and the length of them,
+// it stops at the first torn or broken record.
+fn read_records(data: &[u8]) -> (Vec<&[u8]>, usize) {
+    let mut buf = data;
+    let mut records = vec![];
+    loop {
+        let mut next = buf;
+        let payload = match (u32::decode(&mut next), u32::decode(&mut next)) {
+            (Ok(len), Ok(crc)) => match take_bytes(&mut next, len as usize) {
+                Ok(payload) if crc32(payload) == crc => payload,
+                _ => break,
+            },
+            _ => break,
+        };
+        records.push(payload);
+        buf = next;
+    }
+    (records, data.len() - buf.len())
+}
+
+// is_torn tells whether the broken record at the start of `data` is a torn write,
+// which is the case if it reaches the end, or no valid record follows it.
+fn is_torn(data: &[u8]) -> bool {
+    let mut next = data;
+    let record = u32::decode(&mut next).and_then(|len| {
+        u32::decode(&mut next)?;
+        take_bytes(&mut next, len as usize)
+    });
+    match record {
+        Ok(_) => read_records(next).0.is_empty(),
+        Err(_) => true,
+    }
+}
+
+/// DiskEngine keeps all the data in memory, and persists it by a WAL and snapshots.
+///
+/// Every write is appended to the WAL before it's applied,
+/// the WAL is truncated once a snapshot of all the data is written.
+/// On reopen, the snapshot is loaded and the WAL is replayed on it,
+/// a torn record at the end of the WAL is dropped, as the write of it never returned,
+/// but a broken record followed by valid ones fails the reopen with `StorageError::Corrupted`.
+pub struct DiskEngine<K, V>
+where
+    K: Key + Encode,
+    V: Value + Encode,
+{
+    dir: PathBuf,
+    config: DiskConfig,
+    inner: RwLock<BTreeMap<K, V>>,
+    // the WAL is locked before the map, so the records are in the order of applying.
+    wal: Mutex<Wal>,
+}
+
+impl<K, V> DiskEngine<K, V>
+where
+    K: Key + Encode,
+    V: Value + Encode,
+{
+    /// open recovers the data in `dir`, or creates an empty engine there.
+    pub fn open<P: AsRef<Path>>(dir: P, config: DiskConfig) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut map = BTreeMap::new();
+        let snapshot = dir.join(SNAPSHOT);
+        if snapshot.exists() {
+            let data = fs::read(&snapshot)?;
+            let (records, len) = read_records(&data);
+            // the snapshot is renamed into place after it's fully written.
+            if len != data.len() {
+                let msg = format!("snapshot {} at offset {}", snapshot.display(), len);
+                return Err(StorageError::Corrupted(msg).into());
+            }
+            for payload in records {
+                Self::apply(&mut map, payload)?;
+            }
+        }
+        let path = dir.join(WAL);
+        let data = if path.exists() {
+            fs::read(&path)?
+        } else {
+            vec![]
+        };
+        let (records, len) = read_records(&data);
+        // the records after a broken one may have been acknowledged, they can't be dropped.
+        if !is_torn(&data[len..]) {
+            let msg = format!("wal {} at offset {}", path.display(), len);
+            return Err(StorageError::Corrupted(msg).into());
+        }
+        for payload in &records {
+            Self::apply(&mut map, payload)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        file.set_len(len as u64)?;
+        let wal = Wal {
+            file,
+            records: records.len() as u64,
+        };
+        Ok(Self {
+            dir,
+            config,
+            inner: RwLock::new(map),
+            wal: Mutex::new(wal),
+        })
+    }
+
+    /// snapshot writes all the data into the snapshot file, and truncates the WAL.
+    pub fn snapshot(&self) -> Result<()> {
+        let mut wal = self.wal.lock().unwrap();
+        self.write_snapshot(&mut wal)
+    }
+
+    fn write_snapshot(&self, wal: &mut Wal) -> Result<()> {
+        let mut buf = vec![];
+        for (k, v) in self.inner.read().unwrap().iter() {
+            encode_record(&Self::put_payload(k, v), &mut buf);
+        }
+        let tmp = self.dir.join(SNAPSHOT_TMP);
+        let mut file = File::create(&tmp)?;
+        file.write_all(&buf)?;
+        file.sync_all()?;
+        fs::rename(&tmp, self.dir.join(SNAPSHOT))?;
+        File::open(&self.dir)?.sync_all()?;
+        // replaying the WAL on the new snapshot is harmless if we crash before truncating it.
+        wal.file.set_len(0)?;
+        wal.file.sync_all()?;
+        wal.records = 0;
+        Ok(())
+    }
+
+    fn put_payload(k: &K, v: &V) -> Vec<u8> {
+        let mut payload = vec![];
+        encode_mutation(k, Some(v), &mut payload);
+        payload
+    }
+
+    fn apply(map: &mut BTreeMap<K, V>, mut payload: &[u8]) -> Result<()> {
+        Self::apply_mutation(map, &mut payload)
+    }
+
+    fn apply_mutation(map: &mut BTreeMap<K, V>, payload: &mut &[u8]) -> Result<()> {
+        if payload.first() == Some(&BATCH) {
+            *payload = &payload[1..];
+            for _ in 0..u32::decode(payload)? {
+                Self::apply_mutation(map, payload)?;
+            }
+            return Ok(());
+        }
+        match decode_mutation::<K, V>(payload)? {
+            (k, Some(v)) => map.insert(k, v),
+            (k, None) => map.remove(&k),
+        };
+        Ok(())
+    }
+
+    fn log_and_apply(&self, payload: Vec<u8>) -> Result<()> {
+        let mut wal = self.wal.lock().unwrap();
+        wal.append(&payload, self.config.sync)?;
+        Self::apply(&mut self.inner.write().unwrap(), &payload)?;
+        let interval = self.config.snapshot_interval;
+        if interval > 0 && wal.records >= interval {
+            self.write_snapshot(&mut wal)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K, V> Engine for DiskEngine<K, V>
+where
+    K: Key + Encode + Send + Sync,
+    V: Value + Encode + Send + Sync,
+{
+    type K = K;
+    type V = V;
+
+    fn put(&self, k: K, v: V) -> Result<()> {
+        self.log_and_apply(Self::put_payload(&k, &v))
+    }
+
+    fn del(&self, k: &K) -> Result<()> {
+        let mut payload = vec![];
+        encode_mutation::<K, V>(k, None, &mut payload);
+        self.log_and_apply(payload)
+    }
+
+    fn get(&self, k: &K) -> Result<Option<V>> {
+        let inner = self.inner.read().unwrap();
+        Ok(inner.get(k).map(|v| v.to_owned()))
+    }
+
+    fn scan_kv(
+        &self,
+        lower: Bound<&K>,
+        upper: Bound<&K>,
+        limit: Option<usize>,
+        reverse: bool,
+    ) -> Result<Vec<(K, V)>> {
+        let inner = self.inner.read().unwrap();
+        Ok(scan_map(&inner, lower, upper, limit, reverse))
+    }
+
+    fn write(&self, batch: WriteBatch<K, V>) -> Result<()> {
+        let mut payload = vec![BATCH];
+        (batch.len() as u32).encode(&mut payload);
+        for (k, v) in batch.mutations() {
+            encode_mutation(k, v.as_ref(), &mut payload);
+        }
+        self.log_and_apply(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::byte::{ByteKey, ByteValue};
+
+    type TestEngine = DiskEngine<ByteKey, ByteValue>;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gensokyo-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn key(i: u32) -> ByteKey {
+        ByteKey::new(format!("k{:03}", i).as_bytes())
+    }
+
+    fn value(i: u32) -> ByteValue {
+        ByteValue::new(format!("v{}", i).as_bytes())
+    }
+
+    fn get(engine: &TestEngine, i: u32) -> Option<String> {
+        engine.get(&key(i)).unwrap().map(|v| v.to_string())
+    }
+
+    #[test]
+    fn test_recover() {
+        let dir = temp_dir("disk-recover");
+        let config = DiskConfig {
+            snapshot_interval: 0,
+            sync: false,
+        };
+        let engine = TestEngine::open(&dir, config.clone()).unwrap();
+        for i in 0..100 {
+            engine.put(key(i), value(i)).unwrap();
+        }
+        for i in (0..100).step_by(2) {
+            engine.del(&key(i)).unwrap();
+        }
+        engine.put(key(1), value(1000)).unwrap();
+        // crash without any snapshot.
+        drop(engine);
+
+        let engine = TestEngine::open(&dir, config).unwrap();
+        assert_eq!(get(&engine, 0), None);
+        assert_eq!(get(&engine, 1), Some("v1000".to_owned()));
+        assert_eq!(get(&engine, 99), Some("v99".to_owned()));
+        assert_eq!(engine.scan(&key(0), &key(100)).unwrap().len(), 50);
+        assert_eq!(engine.wal.lock().unwrap().records, 151);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let dir = temp_dir("disk-snapshot");
+        let config = DiskConfig {
+            snapshot_interval: 10,
+            sync: true,
+        };
+        let engine = TestEngine::open(&dir, config.clone()).unwrap();
+        for i in 0..25 {
+            engine.put(key(i), value(i)).unwrap();
+        }
+        assert!(dir.join(SNAPSHOT).exists());
+        assert!(!dir.join(SNAPSHOT_TMP).exists());
+        assert_eq!(engine.wal.lock().unwrap().records, 5);
+        engine.del(&key(3)).unwrap();
+        drop(engine);
+
+        let engine = TestEngine::open(&dir, config.clone()).unwrap();
+        assert_eq!(get(&engine, 3), None);
+        assert_eq!(get(&engine, 24), Some("v24".to_owned()));
+        assert_eq!(engine.wal.lock().unwrap().records, 6);
+        engine.snapshot().unwrap();
+        assert_eq!(fs::metadata(dir.join(WAL)).unwrap().len(), 0);
+        drop(engine);
+
+        let engine = TestEngine::open(&dir, config).unwrap();
+        assert_eq!(engine.scan(&key(0), &key(100)).unwrap().len(), 24);
+        // a broken snapshot can't be recovered.
+        drop(engine);
+        let snapshot = dir.join(SNAPSHOT);
+        let mut data = fs::read(&snapshot).unwrap();
+        data.truncate(data.len() - 1);
+        fs::write(&snapshot, data).unwrap();
+        assert!(matches!(
+            TestEngine::open(&dir, DiskConfig::default()),
+            Err(crate::util::Error::StorageError(StorageError::Corrupted(_)))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_broken_wal() {
+        let dir = temp_dir("disk-broken-wal");
+        let engine = TestEngine::open(&dir, DiskConfig::default()).unwrap();
+        for i in 0..3 {
+            engine.put(key(i), value(i)).unwrap();
+        }
+        drop(engine);
+
+        // the last write is torn.
+        let wal = dir.join(WAL);
+        let mut data = fs::read(&wal).unwrap();
+        data.truncate(data.len() - 2);
+        fs::write(&wal, &data).unwrap();
+        let engine = TestEngine::open(&dir, DiskConfig::default()).unwrap();
+        assert_eq!(get(&engine, 1), Some("v1".to_owned()));
+        assert_eq!(get(&engine, 2), None);
+        // the torn record is truncated, so the new ones can be read.
+        engine.put(key(3), value(3)).unwrap();
+        drop(engine);
+        let engine = TestEngine::open(&dir, DiskConfig::default()).unwrap();
+        assert_eq!(get(&engine, 3), Some("v3".to_owned()));
+        drop(engine);
+
+        // flip a byte of the last record, it's dropped as a torn write.
+        let mut data = fs::read(&wal).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+        fs::write(&wal, &data).unwrap();
+        let engine = TestEngine::open(&dir, DiskConfig::default()).unwrap();
+        assert_eq!(get(&engine, 1), Some("v1".to_owned()));
+        assert_eq!(get(&engine, 3), None);
+        engine.put(key(3), value(3)).unwrap();
+        drop(engine);
+
+        // flip a byte of the second record, the valid records after it can't be dropped.
+        let mut data = fs::read(&wal).unwrap();
+        let first = read_records(&data).0[0].len() + 8;
+        data[first + 10] ^= 0xff;
+        fs::write(&wal, &data).unwrap();
+        assert!(matches!(
+            TestEngine::open(&dir, DiskConfig::default()),
+            Err(crate::util::Error::StorageError(StorageError::Corrupted(_)))
+        ));
+        assert_eq!(fs::read(&wal).unwrap(), data);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_batch() {
+        let dir = temp_dir("disk-batch");
+        let engine = TestEngine::open(&dir, DiskConfig::default()).unwrap();
+        engine.put(key(0), value(0)).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put(key(1), value(1));
+        batch.del(key(0));
+        batch.put(key(2), value(2));
+        engine.write(batch).unwrap();
+        assert_eq!(get(&engine, 0), None);
+        assert_eq!(engine.wal.lock().unwrap().records, 2);
+        drop(engine);
+
+        let engine = TestEngine::open(&dir, DiskConfig::default()).unwrap();
+        assert_eq!(get(&engine, 0), None);
+        assert_eq!(get(&engine, 2), Some("v2".to_owned()));
+        drop(engine);
+
+        // a torn batch is dropped as a whole.
+        let wal = dir.join(WAL);
+        let mut data = fs::read(&wal).unwrap();
+        data.truncate(data.len() - 1);
+        fs::write(&wal, &data).unwrap();
+        let engine = TestEngine::open(&dir, DiskConfig::default()).unwrap();
+        assert_eq!(get(&engine, 0), Some("v0".to_owned()));
+        assert_eq!(get(&engine, 1), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,8 +1,8 @@
 use crate::codec::{Key, Value};
-use crate::storage::Engine;
+use crate::storage::{scan_map, Engine, WriteBatch};
 use crate::util::Result;
 use std::collections::BTreeMap;
-use std::ops::Bound::{Excluded, Included};
+use std::ops::Bound;
 use std::sync::RwLock;
 
 pub struct InMemEngine<K, V>
@@ -68,20 +68,33 @@ where
         Ok(v)
     }
 
-    fn scan(&self, lower: &K, upper: &K) -> Result<Vec<Self::V>> {
+    fn scan_kv(
+        &self,
+        lower: Bound<&K>,
+        upper: Bound<&K>,
+        limit: Option<usize>,
+        reverse: bool,
+    ) -> Result<Vec<(K, V)>> {
         let inner = self.inner.read().unwrap();
-        let (lower, upper) = (Included(lower), Excluded(upper));
-        let mut res = vec![];
-        for (_, value) in inner.range((lower, upper)) {
-            res.push(value.to_owned());
+        Ok(scan_map(&inner, lower, upper, limit, reverse))
+    }
+
+    fn write(&self, batch: WriteBatch<K, V>) -> Result<()> {
+        let mut inner = self.inner.write().unwrap();
+        for (k, v) in batch.into_mutations() {
+            match v {
+                Some(v) => inner.insert(k, v),
+                None => inner.remove(&k),
+            };
         }
-        Ok(res)
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::ops::Bound::{Excluded, Included, Unbounded};
 
     impl Key for i32 {}
     impl Value for i32 {}
@@ -114,4 +127,67 @@ mod tests {
         let vs = engine.scan(&995, &1002).unwrap();
         assert_eq!(vs, vec![1992, 1996]);
     }
+
+    #[test]
+    fn test_scan_kv() {
+        let engine = InMemEngine::new();
+        for i in 0..10 {
+            engine.put(i, i * 10).unwrap();
+        }
+        let kvs = engine
+            .scan_kv(Included(&3), Excluded(&6), None, false)
+            .unwrap();
+        assert_eq!(kvs, vec![(3, 30), (4, 40), (5, 50)]);
+        let kvs = engine
+            .scan_kv(Excluded(&3), Included(&6), None, true)
+            .unwrap();
+        assert_eq!(kvs, vec![(6, 60), (5, 50), (4, 40)]);
+        // the latest 2 keys.
+        let kvs = engine.scan_kv(Unbounded, Unbounded, Some(2), true).unwrap();
+        assert_eq!(kvs, vec![(9, 90), (8, 80)]);
+        // pagination
+        let kvs = engine
+            .scan_kv(Unbounded, Excluded(&5), Some(3), false)
+            .unwrap();
+        assert_eq!(kvs, vec![(0, 0), (1, 10), (2, 20)]);
+        let kvs = engine
+            .scan_kv(Excluded(&2), Excluded(&5), Some(3), false)
+            .unwrap();
+        assert_eq!(kvs, vec![(3, 30), (4, 40)]);
+        assert_eq!(
+            engine
+                .scan_kv(Unbounded, Unbounded, Some(0), false)
+                .unwrap(),
+            vec![]
+        );
+        assert_eq!(
+            engine
+                .scan_kv(Excluded(&3), Excluded(&3), None, false)
+                .unwrap(),
+            vec![]
+        );
+        assert_eq!(
+            engine
+                .scan_kv(Included(&5), Included(&3), None, false)
+                .unwrap(),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_write_batch() {
+        let engine = InMemEngine::new();
+        engine.put(1, 1).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put(2, 2);
+        batch.del(1);
+        batch.put(3, 3);
+        batch.put(2, 20);
+        assert_eq!(batch.len(), 4);
+        engine.write(batch).unwrap();
+        let kvs = engine.scan_kv(Unbounded, Unbounded, None, false).unwrap();
+        assert_eq!(kvs, vec![(2, 20), (3, 3)]);
+        engine.write(WriteBatch::new()).unwrap();
+        assert_eq!(engine.get(&3).unwrap(), Some(3));
+    }
 }
@@ -0,0 +1,149 @@
+use crate::codec::{Key, Value};
+use crate::storage::{is_empty_range, SnapshotEngine};
+use crate::tso::TimeStamp;
+use crate::util::Result;
+use std::collections::BTreeMap;
+use std::ops::Bound::{Excluded, Included, Unbounded};
+use std::sync::RwLock;
+
+// a version with `None` value is a tombstone written by `del`.
+type Versions<V> = BTreeMap<TimeStamp, Option<V>>;
+
+pub struct InMemSnapshotEngine<K, V>
+where
+    K: Key,
+    V: Value,
+{
+    inner: RwLock<BTreeMap<K, Versions<V>>>,
+}
+
+impl<K, V> InMemSnapshotEngine<K, V>
+where
+    K: Key,
+    V: Value,
+{
+    pub fn new() -> Self {
+        Self {
+            inner: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    fn write_version(&self, k: K, ts: TimeStamp, v: Option<V>) {
+        let mut inner = self.inner.write().unwrap();
+        if let Some(versions) = inner.get_mut(&k) {
+            versions.insert(ts, v);
+        } else {
+            let mut versions = BTreeMap::new();
+            versions.insert(ts, v);
+            inner.insert(k, versions);
+        }
+    }
+
+    fn visible(versions: &Versions<V>, ts: TimeStamp) -> Option<V> {
+        versions
+            .range((Unbounded, Included(ts)))
+            .next_back()
+            .and_then(|(_, v)| v.as_ref().map(|v| v.to_owned()))
+    }
+}
+
+impl<K, V> Default for InMemSnapshotEngine<K, V>
+where
+    K: Key,
+    V: Value,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> SnapshotEngine for InMemSnapshotEngine<K, V>
+where
+    K: Key + Send + Sync,
+    V: Value + Send + Sync,
+{
+    type K = K;
+    type V = V;
+
+    fn put(&self, k: K, v: V, ts: TimeStamp) -> Result<()> {
+        self.write_version(k, ts, Some(v));
+        Ok(())
+    }
+
+    fn del(&self, k: K, ts: TimeStamp) -> Result<()> {
+        self.write_version(k, ts, None);
+        Ok(())
+    }
+
+    fn get(&self, k: &K, ts: TimeStamp) -> Result<Option<V>> {
+        let inner = self.inner.read().unwrap();
+        Ok(inner
+            .get(k)
+            .and_then(|versions| Self::visible(versions, ts)))
+    }
+
+    fn scan(&self, lower: &K, upper: &K, ts: TimeStamp) -> Result<Vec<V>> {
+        let (lower, upper) = (Included(lower), Excluded(upper));
+        if is_empty_range(lower, upper) {
+            return Ok(vec![]);
+        }
+        let inner = self.inner.read().unwrap();
+        let mut res = vec![];
+        for (_, versions) in inner.range((lower, upper)) {
+            if let Some(v) = Self::visible(versions, ts) {
+                res.push(v);
+            }
+        }
+        Ok(res)
+    }
+
+    fn latest_version(&self, k: &K) -> Result<Option<TimeStamp>> {
+        let inner = self.inner.read().unwrap();
+        Ok(inner
+            .get(k)
+            .and_then(|versions| versions.keys().next_back().copied()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_mem_snapshot_engine() {
+        let engine = InMemSnapshotEngine::new();
+        let ts = |t: u64| TimeStamp::from(t);
+        for i in 0..100 {
+            assert_eq!(engine.latest_version(&i).unwrap(), None);
+            engine.put(i, i, ts(10)).unwrap();
+            engine.put(i, 2 * i, ts(20)).unwrap();
+            if i % 2 == 0 {
+                engine.del(i, ts(30)).unwrap();
+            }
+        }
+        for i in 0..100 {
+            assert_eq!(engine.get(&i, ts(9)).unwrap(), None);
+            assert_eq!(engine.get(&i, ts(10)).unwrap(), Some(i));
+            assert_eq!(engine.get(&i, ts(19)).unwrap(), Some(i));
+            assert_eq!(engine.get(&i, ts(20)).unwrap(), Some(2 * i));
+            let (expected_v, expected_ts) = if i % 2 == 0 {
+                (None, ts(30))
+            } else {
+                (Some(2 * i), ts(20))
+            };
+            assert_eq!(engine.get(&i, TimeStamp::max()).unwrap(), expected_v);
+            assert_eq!(engine.latest_version(&i).unwrap(), Some(expected_ts));
+        }
+        assert_eq!(engine.scan(&95, &102, ts(5)).unwrap(), vec![]);
+        assert_eq!(
+            engine.scan(&95, &102, ts(15)).unwrap(),
+            vec![95, 96, 97, 98, 99]
+        );
+        assert_eq!(
+            engine.scan(&95, &102, ts(25)).unwrap(),
+            vec![190, 192, 194, 196, 198]
+        );
+        assert_eq!(engine.scan(&95, &102, ts(35)).unwrap(), vec![190, 194, 198]);
+        assert_eq!(engine.scan(&102, &95, ts(35)).unwrap(), vec![]);
+    }
+}
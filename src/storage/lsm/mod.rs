@@ -0,0 +1,663 @@
+//! An LSM-tree engine to study the storage-level costs.
+//!
+//! The writes go into the memtable, which is flushed into a run of level 0 once it's full,
+//! the runs are merged into the lower levels by compaction.
+//! The memtable and the layout of the levels aren't persisted,
+//! use `DiskEngine` if the data should survive a crash.
+use crate::codec::{Encode, Key, Value};
+use crate::storage::{encode_mutation, is_empty_range, Engine, WriteBatch};
+use crate::util::{Error, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+
+mod run;
+use run::{Entry, Run};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompactionStyle {
+    /// Every level except level 0 is a sorted sequence of non-overlapping runs,
+    /// a run of an oversized level is merged with the overlapping runs of the next level.
+    Leveled,
+    /// Every level holds at most `level_ratio` overlapping runs,
+    /// a full level is merged into one run of the next level.
+    Tiered,
+}
+
+#[derive(Clone, Debug)]
+pub struct LsmConfig {
+    pub style: CompactionStyle,
+    pub memtable_bytes: u64,
+    /// The max number of runs in level 0 of the leveled compaction.
+    pub l0_runs: usize,
+    /// The max bytes of level 1 of the leveled compaction,
+    /// every level is `level_ratio` times larger than the previous one.
+    pub base_level_bytes: u64,
+    pub level_ratio: u64,
+    /// The max bytes of a run written by the leveled compaction.
+    pub run_bytes: u64,
+    /// The number of entries of an indexed block, it must be positive.
+    pub block_entries: usize,
+}
+
+impl Default for LsmConfig {
+    fn default() -> Self {
+        Self {
+            style: CompactionStyle::Leveled,
+            memtable_bytes: 4 << 20,
+            l0_runs: 4,
+            base_level_bytes: 64 << 20,
+            level_ratio: 10,
+            run_bytes: 8 << 20,
+            block_entries: 16,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LsmStats {
+    /// The encoded bytes of the puts and dels.
+    pub user_bytes: u64,
+    pub flush_bytes: u64,
+    pub compactions: u64,
+    pub compaction_read_bytes: u64,
+    pub compaction_write_bytes: u64,
+    pub gets: u64,
+    /// The runs searched by the gets, there are no bloom filters.
+    pub get_runs: u64,
+    pub get_read_bytes: u64,
+    pub scans: u64,
+    /// The runs read by the scans, every scan reads the whole runs.
+    pub scan_runs: u64,
+    pub scan_read_bytes: u64,
+}
+
+impl LsmStats {
+    /// write_amplification is the bytes written to the disk per user byte.
+    pub fn write_amplification(&self) -> f64 {
+        if self.user_bytes == 0 {
+            return 0.0;
+        }
+        (self.flush_bytes + self.compaction_write_bytes) as f64 / self.user_bytes as f64
+    }
+
+    /// read_amplification is the runs searched per get, the scans are counted by `scan_runs`.
+    pub fn read_amplification(&self) -> f64 {
+        if self.gets == 0 {
+            return 0.0;
+        }
+        self.get_runs as f64 / self.gets as f64
+    }
+}
+
+struct State<K, V> {
+    memtable: BTreeMap<K, Option<V>>,
+    memtable_bytes: u64,
+    // the runs of every level are ordered from old to new,
+    // the non-overlapping runs of the leveled compaction are ordered by keys.
+    levels: Vec<Vec<Run<K>>>,
+    next_run: u64,
+}
+
+pub struct LsmEngine<K, V>
+where
+    K: Key + Encode,
+    V: Value + Encode,
+{
+    dir: PathBuf,
+    config: LsmConfig,
+    state: RwLock<State<K, V>>,
+    stats: Mutex<LsmStats>,
+}
+
+impl<K, V> LsmEngine<K, V>
+where
+    K: Key + Encode,
+    V: Value + Encode,
+{
+    /// open creates an empty engine in `dir`, the runs left there are removed,
+    /// and the other files are kept. The config is rejected if it's invalid.
+    pub fn open<P: AsRef<Path>>(dir: P, config: LsmConfig) -> Result<Self> {
+        if config.block_entries == 0 {
+            return Err(Error::ConfigError(
+                "block_entries must be positive".to_owned(),
+            ));
+        }
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "run") {
+                fs::remove_file(&path)?;
+            }
+        }
+        let state = State {
+            memtable: BTreeMap::new(),
+            memtable_bytes: 0,
+            levels: vec![vec![]],
+            next_run: 0,
+        };
+        Ok(Self {
+            dir,
+            config,
+            state: RwLock::new(state),
+            stats: Mutex::new(LsmStats::default()),
+        })
+    }
+
+    pub fn stats(&self) -> LsmStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// runs returns the number of runs in every level.
+    pub fn runs(&self) -> Vec<usize> {
+        let state = self.state.read().unwrap();
+        state.levels.iter().map(|l| l.len()).collect()
+    }
+
+    /// flush writes the memtable into level 0, and compacts the levels if needed.
+    pub fn flush(&self) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+        self.flush_memtable(&mut state)
+    }
+
+    fn write_entries(&self, entries: Vec<Entry<K, V>>) -> Result<()> {
+        let mut buf = vec![];
+        for (k, v) in &entries {
+            encode_mutation(k, v.as_ref(), &mut buf);
+        }
+        self.stats.lock().unwrap().user_bytes += buf.len() as u64;
+        let mut state = self.state.write().unwrap();
+        state.memtable.extend(entries);
+        state.memtable_bytes += buf.len() as u64;
+        if state.memtable_bytes >= self.config.memtable_bytes {
+            self.flush_memtable(&mut state)?;
+        }
+        Ok(())
+    }
+
+    fn flush_memtable(&self, state: &mut State<K, V>) -> Result<()> {
+        if state.memtable.is_empty() {
+            return Ok(());
+        }
+        // the memtable is kept if the run can't be written.
+        let entries: Vec<_> = state
+            .memtable
+            .iter()
+            .map(|(k, v)| (k.to_owned(), v.as_ref().map(|v| v.to_owned())))
+            .collect();
+        let run = self.write_run(state, &entries)?;
+        state.memtable.clear();
+        state.memtable_bytes = 0;
+        self.stats.lock().unwrap().flush_bytes += run.bytes;
+        state.levels[0].push(run);
+        self.compact(state)
+    }
+
+    fn write_run(&self, state: &mut State<K, V>, entries: &[Entry<K, V>]) -> Result<Run<K>> {
+        let id = state.next_run;
+        state.next_run += 1;
+        let path = self.dir.join(format!("{:08}.run", id));
+        Run::write(path, entries, self.config.block_entries)
+    }
+
+    fn level_limit(&self, level: usize) -> u64 {
+        let ratio = self.config.level_ratio.saturating_pow(level as u32 - 1);
+        self.config.base_level_bytes.saturating_mul(ratio)
+    }
+
+    // compact merges the levels until none of them is oversized.
+    fn compact(&self, state: &mut State<K, V>) -> Result<()> {
+        loop {
+            let picked = match self.config.style {
+                CompactionStyle::Leveled => self.pick_leveled(state),
+                CompactionStyle::Tiered => self.pick_tiered(state),
+            };
+            let (level, inputs) = match picked {
+                Some(picked) => picked,
+                None => return Ok(()),
+            };
+            if state.levels.len() == level + 1 {
+                state.levels.push(vec![]);
+            }
+            self.merge(state, level, inputs)?;
+        }
+    }
+
+    // pick_leveled returns the level and the indexes of the runs to compact,
+    // the overlapping runs of the next level are added by `merge`.
+    fn pick_leveled(&self, state: &State<K, V>) -> Option<(usize, Vec<usize>)> {
+        if state.levels[0].len() > self.config.l0_runs {
+            return Some((0, (0..state.levels[0].len()).collect()));
+        }
+        (1..state.levels.len())
+            .find(|&i| {
+                let bytes: u64 = state.levels[i].iter().map(|r| r.bytes).sum();
+                bytes > self.level_limit(i)
+            })
+            .map(|i| (i, vec![0]))
+    }
+
+    fn pick_tiered(&self, state: &State<K, V>) -> Option<(usize, Vec<usize>)> {
+        let ratio = self.config.level_ratio as usize;
+        (0..state.levels.len())
+            .find(|&i| state.levels[i].len() >= ratio)
+            .map(|i| (i, (0..state.levels[i].len()).collect()))
+    }
+
+    // merge merges the runs `inputs` of `level` into the next level,
+    // the levels are changed only after the merged runs are written,
+    // so an I/O error leaves them as they were.
+    fn merge(&self, state: &mut State<K, V>, level: usize, inputs: Vec<usize>) -> Result<()> {
+        let sources: Vec<_> = inputs.iter().map(|&i| &state.levels[level][i]).collect();
+        let min = sources.iter().map(|r| &r.min).min().unwrap().to_owned();
+        let max = sources.iter().map(|r| &r.max).max().unwrap().to_owned();
+        let leveled = self.config.style == CompactionStyle::Leveled;
+        // the overlapping runs of the next level are older than the sources.
+        let overlapped: Vec<usize> = if leveled {
+            let next = &state.levels[level + 1];
+            (0..next.len())
+                .filter(|&i| next[i].overlaps(&min, &max))
+                .collect()
+        } else {
+            vec![]
+        };
+        let mut runs: Vec<_> = overlapped
+            .iter()
+            .map(|&i| &state.levels[level + 1][i])
+            .collect();
+        runs.extend(sources);
+        // the overlapped runs may widen the range of the merged keys.
+        let min = runs.iter().map(|r| &r.min).min().unwrap().to_owned();
+        let max = runs.iter().map(|r| &r.max).max().unwrap().to_owned();
+        // the tombstones are useless if no older run may contain the keys.
+        let rest = state.levels[level + 1]
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !overlapped.contains(i))
+            .map(|(_, r)| r);
+        let bottom = rest
+            .chain(state.levels[level + 2..].iter().flatten())
+            .all(|r| !r.overlaps(&min, &max));
+
+        let mut merged = BTreeMap::new();
+        let mut read_bytes = 0;
+        for run in &runs {
+            merged.extend(run.read_all::<V>()?);
+            read_bytes += run.bytes;
+        }
+        let entries: Vec<_> = merged
+            .into_iter()
+            .filter(|(_, v)| !bottom || v.is_some())
+            .collect();
+        let chunks = if leveled {
+            self.split_entries(entries)
+        } else if !entries.is_empty() {
+            vec![entries]
+        } else {
+            vec![]
+        };
+        let mut outputs = vec![];
+        for chunk in chunks {
+            match self.write_run(state, &chunk) {
+                Ok(run) => outputs.push(run),
+                Err(e) => {
+                    // the merged runs are still in the levels, drop the partial outputs.
+                    for run in outputs {
+                        let _ = run.remove();
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        let mut removed = vec![];
+        for &i in inputs.iter().rev() {
+            removed.push(state.levels[level].remove(i));
+        }
+        for &i in overlapped.iter().rev() {
+            removed.push(state.levels[level + 1].remove(i));
+        }
+        let mut stats = self.stats.lock().unwrap();
+        stats.compactions += 1;
+        stats.compaction_read_bytes += read_bytes;
+        stats.compaction_write_bytes += outputs.iter().map(|r| r.bytes).sum::<u64>();
+        drop(stats);
+        let next = &mut state.levels[level + 1];
+        next.extend(outputs);
+        if leveled {
+            next.sort_by(|a, b| a.min.cmp(&b.min));
+        }
+        for run in removed {
+            run.remove()?;
+        }
+        Ok(())
+    }
+
+    // split_entries splits the entries into the runs of at most `run_bytes`.
+    fn split_entries(&self, entries: Vec<Entry<K, V>>) -> Vec<Vec<Entry<K, V>>> {
+        let mut chunks = vec![];
+        let (mut chunk, mut bytes) = (vec![], 0);
+        let mut buf = vec![];
+        for (k, v) in entries {
+            buf.clear();
+            encode_mutation(&k, v.as_ref(), &mut buf);
+            if !chunk.is_empty() && bytes + buf.len() as u64 > self.config.run_bytes {
+                chunks.push(std::mem::take(&mut chunk));
+                bytes = 0;
+            }
+            bytes += buf.len() as u64;
+            chunk.push((k, v));
+        }
+        if !chunk.is_empty() {
+            chunks.push(chunk);
+        }
+        chunks
+    }
+}
+
+impl<K, V> Engine for LsmEngine<K, V>
+where
+    K: Key + Encode + Send + Sync,
+    V: Value + Encode + Send + Sync,
+{
+    type K = K;
+    type V = V;
+
+    fn put(&self, k: K, v: V) -> Result<()> {
+        self.write_entries(vec![(k, Some(v))])
+    }
+
+    fn del(&self, k: &K) -> Result<()> {
+        self.write_entries(vec![(k.to_owned(), None)])
+    }
+
+    fn get(&self, k: &K) -> Result<Option<V>> {
+        let state = self.state.read().unwrap();
+        let (mut runs, mut read_bytes) = (0, 0);
+        let mut res = state
+            .memtable
+            .get(k)
+            .map(|v| v.as_ref().map(|v| v.to_owned()));
+        // the newer runs are searched first.
+        let levels = state.levels.iter().flat_map(|l| l.iter().rev());
+        for run in levels.filter(|r| r.overlaps(k, k)) {
+            if res.is_some() {
+                break;
+            }
+            let (entry, bytes) = run.get(k)?;
+            runs += 1;
+            read_bytes += bytes;
+            res = entry;
+        }
+        let mut stats = self.stats.lock().unwrap();
+        stats.gets += 1;
+        stats.get_runs += runs;
+        stats.get_read_bytes += read_bytes;
+        Ok(res.flatten())
+    }
+
+    fn scan_kv(
+        &self,
+        lower: Bound<&K>,
+        upper: Bound<&K>,
+        limit: Option<usize>,
+        reverse: bool,
+    ) -> Result<Vec<(K, V)>> {
+        if is_empty_range(lower, upper) {
+            return Ok(vec![]);
+        }
+        let state = self.state.read().unwrap();
+        let (mut runs, mut read_bytes) = (0, 0);
+        let mut merged = BTreeMap::new();
+        let in_range = |k: &K| (lower, upper).contains(k);
+        // from the oldest to the newest, the newer entries overwrite the older ones.
+        for run in state.levels.iter().rev().flat_map(|l| l.iter()) {
+            let entries = run.read_all::<V>()?;
+            runs += 1;
+            read_bytes += run.bytes;
+            merged.extend(entries.into_iter().filter(|(k, _)| in_range(k)));
+        }
+        let mut stats = self.stats.lock().unwrap();
+        stats.scans += 1;
+        stats.scan_runs += runs;
+        stats.scan_read_bytes += read_bytes;
+        drop(stats);
+        let memtable = state.memtable.range((lower, upper));
+        merged.extend(memtable.map(|(k, v)| (k.to_owned(), v.as_ref().map(|v| v.to_owned()))));
+        let kvs = merged.into_iter().filter_map(|(k, v)| v.map(|v| (k, v)));
+        let limit = limit.unwrap_or(usize::MAX);
+        Ok(if reverse {
+            kvs.rev().take(limit).collect()
+        } else {
+            kvs.take(limit).collect()
+        })
+    }
+
+    fn write(&self, batch: WriteBatch<K, V>) -> Result<()> {
+        self.write_entries(batch.into_mutations())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::byte::{ByteKey, ByteValue};
+    use crate::util::Rng;
+    use std::ops::Bound::{Excluded, Included, Unbounded};
+
+    fn key(i: u64) -> ByteKey {
+        ByteKey::new(format!("k{:04}", i).as_bytes())
+    }
+
+    fn value(i: u64) -> ByteValue {
+        ByteValue::new(format!("value-{}", i).as_bytes())
+    }
+
+    fn to_strings(kvs: Vec<(ByteKey, ByteValue)>) -> Vec<(String, String)> {
+        kvs.into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    // check_model runs random writes on the engine and a map, and compares their reads.
+    fn check_model(name: &str, style: CompactionStyle) -> (LsmStats, Vec<usize>) {
+        let dir = std::env::temp_dir().join(format!("gensokyo-{}-{}", name, std::process::id()));
+        let config = LsmConfig {
+            style,
+            memtable_bytes: 1024,
+            l0_runs: 2,
+            base_level_bytes: 4096,
+            level_ratio: 4,
+            run_bytes: 2048,
+            block_entries: 4,
+        };
+        let engine = LsmEngine::open(&dir, config).unwrap();
+        let mut model = BTreeMap::new();
+        let mut rng = Rng::new(0);
+        for i in 0..5000 {
+            let k = rng.gen_range(0, 500);
+            if rng.gen_bool(0.2) {
+                engine.del(&key(k)).unwrap();
+                model.remove(&k);
+            } else {
+                engine.put(key(k), value(i)).unwrap();
+                model.insert(k, i);
+            }
+        }
+        for k in 0..500 {
+            let v = engine.get(&key(k)).unwrap().map(|v| v.to_string());
+            assert_eq!(v, model.get(&k).map(|&i| value(i).to_string()));
+        }
+        let expected = |range: Vec<(&u64, &u64)>| -> Vec<(String, String)> {
+            let kvs = range.into_iter().map(|(&k, &i)| (key(k), value(i)));
+            to_strings(kvs.collect())
+        };
+        let kvs = engine
+            .scan_kv(Included(&key(100)), Excluded(&key(200)), None, false)
+            .unwrap();
+        assert_eq!(to_strings(kvs), expected(model.range(100..200).collect()));
+        let kvs = engine
+            .scan_kv(Unbounded, Unbounded, Some(10), true)
+            .unwrap();
+        assert_eq!(
+            to_strings(kvs),
+            expected(model.iter().rev().take(10).collect())
+        );
+
+        // the tombstones in the memtable and the runs hide the older values.
+        for k in 0..500 {
+            engine.del(&key(k)).unwrap();
+        }
+        assert_eq!(engine.get(&key(7)).unwrap().map(|v| v.to_string()), None);
+        engine.flush().unwrap();
+        assert!(engine
+            .scan_kv(Unbounded, Unbounded, None, false)
+            .unwrap()
+            .is_empty());
+        let res = (engine.stats(), engine.runs());
+        fs::remove_dir_all(&dir).unwrap();
+        res
+    }
+
+    #[test]
+    fn test_leveled() {
+        let (stats, runs) = check_model("lsm-leveled", CompactionStyle::Leveled);
+        assert!(runs.len() > 2, "{:?}", runs);
+        assert!(runs[0] <= 2);
+        assert!(stats.compactions > 0);
+        assert!(stats.compaction_read_bytes > 0);
+        assert!(stats.write_amplification() > 1.0);
+        assert!(stats.read_amplification() >= 1.0);
+        assert_eq!(stats.gets, 501);
+        assert_eq!(stats.scans, 3);
+        assert!(stats.scan_runs > 0);
+        assert!(stats.scan_read_bytes > 0);
+    }
+
+    #[test]
+    fn test_tiered() {
+        let (stats, runs) = check_model("lsm-tiered", CompactionStyle::Tiered);
+        assert!(runs.len() > 2, "{:?}", runs);
+        assert!(runs.iter().all(|&n| n < 4));
+        assert!(stats.compactions > 0);
+        assert!(stats.write_amplification() > 1.0);
+    }
+
+    #[test]
+    fn test_drop_tombstones() {
+        let dir = std::env::temp_dir().join(format!("gensokyo-lsm-gc-{}", std::process::id()));
+        let config = LsmConfig {
+            style: CompactionStyle::Tiered,
+            level_ratio: 2,
+            ..Default::default()
+        };
+        let engine = LsmEngine::open(&dir, config).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put(key(1), value(1));
+        batch.put(key(2), value(2));
+        batch.del(key(2));
+        engine.write(batch).unwrap();
+        engine.flush().unwrap();
+        assert_eq!(engine.runs(), vec![1]);
+        assert_eq!(engine.get(&key(2)).unwrap().map(|v| v.to_string()), None);
+        engine.del(&key(1)).unwrap();
+        // the tombstone meets the put in the bottom level, both of them are gone.
+        engine.flush().unwrap();
+        assert_eq!(engine.runs(), vec![0, 0]);
+        assert_eq!(engine.get(&key(1)).unwrap().map(|v| v.to_string()), None);
+        assert_eq!(engine.stats().compaction_write_bytes, 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reopen() {
+        let dir = std::env::temp_dir().join(format!("gensokyo-lsm-reopen-{}", std::process::id()));
+        let engine = LsmEngine::open(&dir, LsmConfig::default()).unwrap();
+        engine.put(key(1), value(1)).unwrap();
+        engine.flush().unwrap();
+        fs::write(dir.join("notes.txt"), b"keep me").unwrap();
+        drop(engine);
+
+        // only the runs are removed.
+        let engine = LsmEngine::<ByteKey, ByteValue>::open(&dir, LsmConfig::default()).unwrap();
+        assert!(engine.get(&key(1)).unwrap().is_none());
+        let mut files: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["notes.txt".to_owned()]);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let config = LsmConfig {
+            block_entries: 0,
+            ..Default::default()
+        };
+        assert!(matches!(
+            LsmEngine::<ByteKey, ByteValue>::open(&dir, config),
+            Err(Error::ConfigError(_))
+        ));
+    }
+
+    #[test]
+    fn test_keep_tombstones() {
+        let dir = std::env::temp_dir().join(format!("gensokyo-lsm-keep-{}", std::process::id()));
+        let engine = LsmEngine::open(&dir, LsmConfig::default()).unwrap();
+        let mut state = engine.state.write().unwrap();
+        let mut run = |entries: &[(u64, Option<u64>)]| {
+            let entries: Vec<_> = entries
+                .iter()
+                .map(|&(k, v)| (key(k), v.map(value)))
+                .collect();
+            engine.write_run(&mut state, &entries).unwrap()
+        };
+        let l2 = run(&[(2, Some(99))]);
+        let l1 = run(&[(1, Some(1)), (2, None), (10, Some(10))]);
+        let l0 = run(&[(5, Some(5)), (6, Some(6))]);
+        state.levels = vec![vec![l0], vec![l1], vec![l2]];
+        // the sources don't overlap the level 2, but the overlapped run of level 1 does,
+        // so the tombstone of k0002 must be kept.
+        engine.merge(&mut state, 0, vec![0]).unwrap();
+        drop(state);
+        assert_eq!(engine.runs(), vec![0, 1, 1]);
+        let get = |k| engine.get(&key(k)).unwrap().map(|v| v.to_string());
+        assert_eq!(get(2), None);
+        assert_eq!(get(5), Some(value(5).to_string()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_merge_error() {
+        let dir = std::env::temp_dir().join(format!("gensokyo-lsm-error-{}", std::process::id()));
+        let config = LsmConfig {
+            style: CompactionStyle::Tiered,
+            level_ratio: 2,
+            ..Default::default()
+        };
+        let engine = LsmEngine::open(&dir, config).unwrap();
+        engine.put(key(1), value(1)).unwrap();
+        engine.flush().unwrap();
+        // the merged run of the next flush can't be created.
+        fs::create_dir(dir.join(format!("{:08}.run", 2))).unwrap();
+        engine.put(key(2), value(2)).unwrap();
+        assert!(engine.flush().is_err());
+        assert_eq!(engine.runs(), vec![2, 0]);
+        assert_eq!(engine.stats().compactions, 0);
+        for i in 1..=2 {
+            let v = engine.get(&key(i)).unwrap().map(|v| v.to_string());
+            assert_eq!(v, Some(value(i).to_string()));
+        }
+
+        fs::remove_dir(dir.join(format!("{:08}.run", 2))).unwrap();
+        engine.put(key(3), value(3)).unwrap();
+        engine.flush().unwrap();
+        assert_eq!(engine.runs(), vec![0, 1]);
+        let kvs = engine.scan_kv(Unbounded, Unbounded, None, false).unwrap();
+        let expected: Vec<_> = (1..=3).map(|i| (key(i), value(i))).collect();
+        assert_eq!(to_strings(kvs), to_strings(expected));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
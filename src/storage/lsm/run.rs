@@ -0,0 +1,93 @@
+use crate::codec::{Encode, Key, Value};
+use crate::storage::{decode_mutation, encode_mutation};
+use crate::util::Result;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+/// Entry is a put or a tombstone of a key.
+pub(super) type Entry<K, V> = (K, Option<V>);
+
+/// Run is an immutable file of sorted entries,
+/// the first key of every block is indexed in memory.
+pub(super) struct Run<K> {
+    pub min: K,
+    pub max: K,
+    pub bytes: u64,
+    path: PathBuf,
+    // (the first key, offset) of the blocks.
+    index: Vec<(K, u64)>,
+}
+
+impl<K: Key + Encode> Run<K> {
+    /// write writes the sorted and non-empty `entries` into `path`.
+    pub fn write<V: Value + Encode>(
+        path: PathBuf,
+        entries: &[Entry<K, V>],
+        block_entries: usize,
+    ) -> Result<Self> {
+        assert!(!entries.is_empty());
+        let mut buf = vec![];
+        let mut index = vec![];
+        for (i, (k, v)) in entries.iter().enumerate() {
+            if i % block_entries == 0 {
+                index.push((k.to_owned(), buf.len() as u64));
+            }
+            encode_mutation(k, v.as_ref(), &mut buf);
+        }
+        let mut file = File::create(&path)?;
+        file.write_all(&buf)?;
+        file.sync_all()?;
+        Ok(Self {
+            min: entries[0].0.to_owned(),
+            max: entries[entries.len() - 1].0.to_owned(),
+            bytes: buf.len() as u64,
+            path,
+            index,
+        })
+    }
+
+    pub fn overlaps(&self, min: &K, max: &K) -> bool {
+        &self.min <= max && min <= &self.max
+    }
+
+    /// get returns the entry of `key` if it's in the run, and the bytes read.
+    pub fn get<V: Value + Encode>(&self, key: &K) -> Result<(Option<Option<V>>, u64)> {
+        if !self.overlaps(key, key) {
+            return Ok((None, 0));
+        }
+        let block = self.index.partition_point(|(k, _)| k <= key) - 1;
+        let start = self.index[block].1;
+        let end = self
+            .index
+            .get(block + 1)
+            .map_or(self.bytes, |(_, off)| *off);
+        let mut data = vec![0; (end - start) as usize];
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut data)?;
+        let mut buf = data.as_slice();
+        while !buf.is_empty() {
+            let (k, v) = decode_mutation::<K, V>(&mut buf)?;
+            if &k == key {
+                return Ok((Some(v), data.len() as u64));
+            }
+        }
+        Ok((None, data.len() as u64))
+    }
+
+    pub fn read_all<V: Value + Encode>(&self) -> Result<Vec<Entry<K, V>>> {
+        let data = fs::read(&self.path)?;
+        let mut buf = data.as_slice();
+        let mut entries = vec![];
+        while !buf.is_empty() {
+            entries.push(decode_mutation(&mut buf)?);
+        }
+        Ok(entries)
+    }
+
+    pub fn remove(self) -> Result<()> {
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
@@ -1,8 +1,17 @@
-use crate::codec::{Key, Value};
-use crate::util::Result;
+use crate::codec::{Encode, Key, Value};
+use crate::tso::TimeStamp;
+use crate::util::{CodecError, Result};
+use std::collections::BTreeMap;
+use std::ops::Bound::{self, Excluded, Included, Unbounded};
 
+mod disk;
 mod in_mem;
+mod in_mem_snapshot;
+mod lsm;
+pub use disk::{DiskConfig, DiskEngine};
 pub use in_mem::InMemEngine;
+pub use in_mem_snapshot::InMemSnapshotEngine;
+pub use lsm::{CompactionStyle, LsmConfig, LsmEngine, LsmStats};
 
 pub trait Engine: Sync + Send {
     type K: Key;
@@ -15,7 +24,143 @@ pub trait Engine: Sync + Send {
     // you should return value with owner ship, because it'll be sent to other nodes(other machines in real world)
     fn get(&self, k: &Self::K) -> Result<Option<Self::V>>;
     /// scan get the values between [lower, upper).
-    fn scan(&self, lower: &Self::K, upper: &Self::K) -> Result<Vec<Self::V>>;
+    fn scan(&self, lower: &Self::K, upper: &Self::K) -> Result<Vec<Self::V>> {
+        let kvs = self.scan_kv(Included(lower), Excluded(upper), None, false)?;
+        Ok(kvs.into_iter().map(|(_, v)| v).collect())
+    }
+    /// scan_kv gets at most `limit` key-value pairs in the range,
+    /// they are sorted by the keys in descending order if `reverse` is true.
+    fn scan_kv(
+        &self,
+        lower: Bound<&Self::K>,
+        upper: Bound<&Self::K>,
+        limit: Option<usize>,
+        reverse: bool,
+    ) -> Result<Vec<(Self::K, Self::V)>>;
+    /// write applies all the mutations of `batch` atomically,
+    /// a read sees either none or all of them.
+    fn write(&self, batch: WriteBatch<Self::K, Self::V>) -> Result<()>;
 }
 
-pub trait SnapshotEngine {}
+/// WriteBatch collects the mutations for `Engine::write`,
+/// they are applied in order, so the later one of a key wins.
+pub struct WriteBatch<K, V> {
+    // a `None` value is a deletion.
+    mutations: Vec<(K, Option<V>)>,
+}
+
+impl<K, V> WriteBatch<K, V> {
+    pub fn new() -> Self {
+        Self { mutations: vec![] }
+    }
+
+    pub fn put(&mut self, k: K, v: V) {
+        self.mutations.push((k, Some(v)));
+    }
+
+    pub fn del(&mut self, k: K) {
+        self.mutations.push((k, None));
+    }
+
+    pub fn len(&self) -> usize {
+        self.mutations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mutations.is_empty()
+    }
+
+    pub fn mutations(&self) -> &[(K, Option<V>)] {
+        &self.mutations
+    }
+
+    pub fn into_mutations(self) -> Vec<(K, Option<V>)> {
+        self.mutations
+    }
+}
+
+impl<K, V> Default for WriteBatch<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// the tags of the mutations, the other tags are free for the formats embedding them.
+const PUT: u8 = 0;
+const DEL: u8 = 1;
+
+/// encode_mutation encodes a put, or a deletion if `v` is `None`,
+/// it's the format of the mutations in both the WAL and the LSM runs.
+pub(crate) fn encode_mutation<K: Encode, V: Encode>(k: &K, v: Option<&V>, buf: &mut Vec<u8>) {
+    match v {
+        Some(v) => {
+            PUT.encode(buf);
+            k.encode(buf);
+            v.encode(buf);
+        }
+        None => {
+            DEL.encode(buf);
+            k.encode(buf);
+        }
+    }
+}
+
+pub(crate) fn decode_mutation<K: Encode, V: Encode>(buf: &mut &[u8]) -> Result<(K, Option<V>)> {
+    match u8::decode(buf)? {
+        PUT => {
+            let k = K::decode(buf)?;
+            Ok((k, Some(V::decode(buf)?)))
+        }
+        DEL => Ok((K::decode(buf)?, None)),
+        tag => Err(CodecError::InvalidData(format!("unknown mutation tag {}", tag)).into()),
+    }
+}
+
+/// is_empty_range tells whether no key is in the range,
+/// `BTreeMap::range` panics on such ranges.
+pub fn is_empty_range<K: Ord>(lower: Bound<&K>, upper: Bound<&K>) -> bool {
+    match (lower, upper) {
+        (Unbounded, _) | (_, Unbounded) => false,
+        (Included(l), Included(u)) => l > u,
+        (Included(l), Excluded(u)) | (Excluded(l), Included(u)) | (Excluded(l), Excluded(u)) => {
+            l >= u
+        }
+    }
+}
+
+// scan_map implements `Engine::scan_kv` on a map.
+pub(crate) fn scan_map<K: Key, V: Value>(
+    map: &BTreeMap<K, V>,
+    lower: Bound<&K>,
+    upper: Bound<&K>,
+    limit: Option<usize>,
+    reverse: bool,
+) -> Vec<(K, V)> {
+    if is_empty_range(lower, upper) {
+        return vec![];
+    }
+    let range = map.range((lower, upper));
+    let limit = limit.unwrap_or(usize::MAX);
+    let kv = |(k, v): (&K, &V)| (k.to_owned(), v.to_owned());
+    if reverse {
+        range.rev().take(limit).map(kv).collect()
+    } else {
+        range.take(limit).map(kv).collect()
+    }
+}
+
+/// SnapshotEngine keeps multiple versions for every key,
+/// a read at `ts` sees the latest version whose timestamp is not greater than `ts`.
+pub trait SnapshotEngine: Sync + Send {
+    type K: Key;
+    type V: Value + ToOwned<Owned = Self::V>;
+
+    fn put(&self, k: Self::K, v: Self::V, ts: TimeStamp) -> Result<()>;
+    /// del writes a tombstone version at `ts`, the older versions are still readable.
+    fn del(&self, k: Self::K, ts: TimeStamp) -> Result<()>;
+    fn get(&self, k: &Self::K, ts: TimeStamp) -> Result<Option<Self::V>>;
+    /// scan get the values between [lower, upper) which are visible at `ts`.
+    fn scan(&self, lower: &Self::K, upper: &Self::K, ts: TimeStamp) -> Result<Vec<Self::V>>;
+    /// latest_version returns the timestamp of the newest version of the key, tombstones included.
+    fn latest_version(&self, k: &Self::K) -> Result<Option<TimeStamp>>;
+}
@@ -0,0 +1,69 @@
+use crate::request::Sender;
+use crate::tso::{TSORequest, TSOResponse, TimeStamp};
+use crate::util::Result;
+
+/// TSOClient fetches timestamps from a TSO node through any `Sender`.
+pub struct TSOClient<S>
+where
+    S: Sender<Req = TSORequest, Res = TSOResponse>,
+{
+    sender: S,
+}
+
+impl<S> TSOClient<S>
+where
+    S: Sender<Req = TSORequest, Res = TSOResponse>,
+{
+    pub fn new(sender: S) -> Self {
+        Self { sender }
+    }
+
+    pub async fn get_ts(&self) -> Result<TimeStamp> {
+        let res = self.sender.send(TSORequest { count: 1 }).await?;
+        Ok(res.first)
+    }
+
+    /// batch_get_ts allocates `count` timestamps in a single round trip,
+    /// the result is in ascending order.
+    pub async fn batch_get_ts(&self, count: u32) -> Result<Vec<TimeStamp>> {
+        let res = self.sender.send(TSORequest { count }).await?;
+        Ok(res.timestamps())
+    }
+
+    pub fn close(&mut self) {
+        self.sender.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::channel::new_channel_connect;
+    use crate::tso::TSONode;
+    use crate::util::test::run_in_tokio;
+    use crate::util::{Error, TSOError};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_tso_client() {
+        run_in_tokio(async move {
+            let client = TSOClient::new(new_channel_connect(Arc::new(TSONode::new())));
+            let mut last = client.get_ts().await.unwrap();
+            for i in 1..100 {
+                let ts = client.get_ts().await.unwrap();
+                assert!(ts > last);
+                let tss = client.batch_get_ts(i).await.unwrap();
+                assert_eq!(tss.len(), i as usize);
+                assert!(tss[0] > ts);
+                assert!(tss.windows(2).all(|w| w[0] < w[1]));
+                last = *tss.last().unwrap();
+            }
+            assert_eq!(
+                client.batch_get_ts(0).await.unwrap_err(),
+                Error::TSOError(TSOError::InvalidCount(0))
+            );
+            // hack the test
+            std::mem::forget(client);
+        });
+    }
+}
@@ -0,0 +1,79 @@
+use std::fmt;
+
+mod client;
+mod node;
+pub use client::TSOClient;
+pub use node::TSONode;
+
+/// The number of bits used by the logical part of a timestamp.
+pub const LOGICAL_BITS: u32 = 18;
+pub const MAX_LOGICAL: u64 = (1 << LOGICAL_BITS) - 1;
+pub const MAX_PHYSICAL: u64 = (1 << (64 - LOGICAL_BITS)) - 1;
+
+/// TimeStamp is composed by a physical part(milliseconds) in the high bits
+/// and a logical counter in the low `LOGICAL_BITS` bits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimeStamp(u64);
+
+impl TimeStamp {
+    pub fn new(physical: u64, logical: u64) -> TimeStamp {
+        debug_assert!(physical <= MAX_PHYSICAL && logical <= MAX_LOGICAL);
+        TimeStamp(physical << LOGICAL_BITS | logical)
+    }
+
+    pub fn zero() -> TimeStamp {
+        TimeStamp(0)
+    }
+
+    pub fn max() -> TimeStamp {
+        TimeStamp(u64::MAX)
+    }
+
+    #[inline]
+    pub fn physical(&self) -> u64 {
+        self.0 >> LOGICAL_BITS
+    }
+
+    #[inline]
+    pub fn logical(&self) -> u64 {
+        self.0 & MAX_LOGICAL
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> u64 {
+        self.0
+    }
+}
+
+impl From<u64> for TimeStamp {
+    fn from(ts: u64) -> TimeStamp {
+        TimeStamp(ts)
+    }
+}
+
+impl fmt::Display for TimeStamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Ask the oracle for `count` consecutive timestamps.
+pub struct TSORequest {
+    pub count: u32,
+}
+
+/// The allocated timestamps are `[first, first + count)`,
+/// they share the same physical part.
+#[derive(Debug)]
+pub struct TSOResponse {
+    pub first: TimeStamp,
+    pub count: u32,
+}
+
+impl TSOResponse {
+    pub fn timestamps(&self) -> Vec<TimeStamp> {
+        (0..self.count as u64)
+            .map(|i| TimeStamp(self.first.0 + i))
+            .collect()
+    }
+}
@@ -0,0 +1,167 @@
+use crate::node::Node;
+use crate::tso::{TSORequest, TSOResponse, TimeStamp, MAX_LOGICAL, MAX_PHYSICAL};
+use crate::util::{Result, TSOError};
+use async_trait::async_trait;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type Clock = dyn Fn() -> Result<u64> + Send + Sync;
+
+/// TSONode is a centralized timestamp oracle, the timestamps it allocates are strictly increasing.
+pub struct TSONode {
+    // (physical, logical) of the last allocated timestamp.
+    last: Mutex<(u64, u64)>,
+    // returns the physical time in milliseconds.
+    clock: Box<Clock>,
+}
+
+impl TSONode {
+    pub fn new() -> Self {
+        Self {
+            last: Mutex::new((0, 0)),
+            clock: Box::new(system_clock),
+        }
+    }
+
+    /// with_clock creates a TSO whose physical time comes from `clock`,
+    /// e.g. the virtual clock of the simulator.
+    pub fn with_clock<F>(clock: F) -> Self
+    where
+        F: Fn() -> u64 + Send + Sync + 'static,
+    {
+        Self {
+            last: Mutex::new((0, 0)),
+            clock: Box::new(move || Ok(clock())),
+        }
+    }
+
+    /// alloc returns the first timestamp of `count` consecutive timestamps.
+    pub fn alloc(&self, count: u32) -> Result<TimeStamp> {
+        let count = count as u64;
+        if count == 0 || count > MAX_LOGICAL {
+            return Err(TSOError::InvalidCount(count).into());
+        }
+        let now = (self.clock)()?;
+        let mut last = self.last.lock().unwrap();
+        let (mut physical, mut logical) = *last;
+        if now > physical {
+            physical = now;
+            logical = 0;
+        }
+        // the logical part of this batch would overflow, borrow the next millisecond.
+        if logical + count > MAX_LOGICAL {
+            physical += 1;
+            logical = 0;
+        }
+        if physical > MAX_PHYSICAL {
+            return Err(TSOError::PhysicalOverflow(physical).into());
+        }
+        // the logical part of the very first timestamp in a millisecond is 1,
+        // so that `TimeStamp::new(physical, 0)` is smaller than any allocated one.
+        let first = TimeStamp::new(physical, logical + 1);
+        *last = (physical, logical + count);
+        Ok(first)
+    }
+}
+
+impl Default for TSONode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn system_clock() -> Result<u64> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| TSOError::ClockError(e.to_string()))?;
+    Ok(now.as_millis() as u64)
+}
+
+#[async_trait]
+impl Node for TSONode {
+    type Req = TSORequest;
+    type Res = TSOResponse;
+
+    async fn process(&self, req: Self::Req) -> Result<Self::Res> {
+        let first = self.alloc(req.count)?;
+        Ok(TSOResponse {
+            first,
+            count: req.count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::Error;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_alloc() {
+        let tso = TSONode::new();
+        let mut last = TimeStamp::zero();
+        for i in 1..1000 {
+            let ts = tso.alloc(i).unwrap();
+            assert!(ts > last);
+            last = TimeStamp::from(ts.into_inner() + i as u64 - 1);
+            assert_eq!(ts.physical(), last.physical());
+        }
+        assert_eq!(
+            tso.alloc(0).unwrap_err(),
+            Error::TSOError(TSOError::InvalidCount(0))
+        );
+        let count = MAX_LOGICAL + 1;
+        assert_eq!(
+            tso.alloc(count as u32).unwrap_err(),
+            Error::TSOError(TSOError::InvalidCount(count))
+        );
+        // a full batch still fits in one millisecond.
+        let ts = tso.alloc(MAX_LOGICAL as u32).unwrap();
+        assert!(ts > last);
+        assert_eq!(ts.logical(), 1);
+    }
+
+    #[test]
+    fn test_clock() {
+        let now = Arc::new(AtomicU64::new(100));
+        let n = now.clone();
+        let tso = TSONode::with_clock(move || n.load(Ordering::SeqCst));
+        assert_eq!(tso.alloc(1).unwrap(), TimeStamp::new(100, 1));
+        assert_eq!(tso.alloc(2).unwrap(), TimeStamp::new(100, 2));
+        now.store(200, Ordering::SeqCst);
+        assert_eq!(tso.alloc(1).unwrap(), TimeStamp::new(200, 1));
+        // the clock goes backwards, the timestamps are still increasing.
+        now.store(50, Ordering::SeqCst);
+        assert_eq!(tso.alloc(1).unwrap(), TimeStamp::new(200, 2));
+    }
+
+    #[test]
+    fn test_concurrent_alloc() {
+        let tso = Arc::new(TSONode::new());
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let tso = tso.clone();
+                thread::spawn(move || {
+                    let mut tss = Vec::with_capacity(10000);
+                    for _ in 0..10000 {
+                        tss.push(tso.alloc(1).unwrap());
+                    }
+                    tss
+                })
+            })
+            .collect();
+        let mut all = vec![];
+        for h in handles {
+            let tss = h.join().unwrap();
+            assert!(tss.windows(2).all(|w| w[0] < w[1]));
+            all.extend(tss);
+        }
+        let total = all.len();
+        all.sort();
+        all.dedup();
+        assert_eq!(all.len(), total);
+    }
+}
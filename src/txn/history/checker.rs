@@ -0,0 +1,660 @@
+use crate::txn::history::Event;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IsolationLevel {
+    /// Forbids G1 and G-single, write skew is allowed.
+    SnapshotIsolation,
+    /// Forbids all the anomalies.
+    Serializable,
+}
+
+/// The anomalies are named after Adya's "Weak Consistency" thesis.
+///
+/// G0 is never reported since the versions of all keys are ordered by the same commit order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnomalyKind {
+    /// A value is read but never written.
+    GarbageRead,
+    /// A committed txn reads the write of an aborted txn.
+    G1a,
+    /// A committed txn reads a value overwritten later by the same writer.
+    G1b,
+    /// A cycle of ww and wr dependencies.
+    G1c,
+    /// A cycle with exactly one rw dependency, e.g. lost update and read skew.
+    GSingle,
+    /// A cycle with more than one rw dependencies, e.g. write skew.
+    G2,
+}
+
+impl fmt::Display for AnomalyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AnomalyKind::GarbageRead => "garbage read",
+            AnomalyKind::G1a => "G1a (aborted read)",
+            AnomalyKind::G1b => "G1b (intermediate read)",
+            AnomalyKind::G1c => "G1c (circular information flow)",
+            AnomalyKind::GSingle => "G-single (single anti-dependency cycle)",
+            AnomalyKind::G2 => "G2 (anti-dependency cycle)",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepKind {
+    /// `to` overwrites the version written by `from`.
+    WW,
+    /// `to` reads the version written by `from`.
+    WR,
+    /// `to` overwrites the version read by `from`.
+    RW,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dependency {
+    pub from: u64,
+    pub to: u64,
+    pub kind: DepKind,
+    pub key: String,
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            DepKind::WW => write!(
+                f,
+                "T{} overwrites {} written by T{}",
+                self.to, self.key, self.from
+            ),
+            DepKind::WR => write!(
+                f,
+                "T{} reads {} written by T{}",
+                self.to, self.key, self.from
+            ),
+            DepKind::RW => write!(
+                f,
+                "T{} reads {} before T{} overwrites it",
+                self.from, self.key, self.to
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    pub txns: Vec<u64>,
+    /// The shortest cycle found, it's empty for the read anomalies.
+    pub cycle: Vec<Dependency>,
+    pub explanation: String,
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.kind, self.explanation)
+    }
+}
+
+enum Op {
+    Read(String, Option<String>),
+    Write(String, Option<String>),
+    // (lower, upper, the pairs found)
+    Scan(String, String, Vec<(String, String)>),
+}
+
+// cmp_keys orders the keys as integers if both of them are, otherwise as strings,
+// since the history only records the keys by `to_string`.
+fn cmp_keys(a: &str, b: &str) -> Ordering {
+    match (a.parse::<i128>(), b.parse::<i128>()) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pending,
+    // (version, position of the commit event)
+    Committed(Option<u64>, usize),
+    Aborted,
+    // the commit may or may not take effect.
+    Unknown,
+}
+
+struct TxnInfo {
+    ops: Vec<Op>,
+    status: Status,
+}
+
+/// check returns the anomalies forbidden by `level` in the history.
+///
+/// Only the committed txns are checked, a txn without completion is ignored,
+/// a txn with unknown result is possibly committed, so reading its write is not an anomaly,
+/// and a read of the txn's own write is not a dependency.
+/// A scan reads the pairs it finds, and reads the other committed keys in its range as not found.
+pub fn check(events: &[Event], level: IsolationLevel) -> Vec<Anomaly> {
+    let mut checker = Checker::new(events);
+    checker.check_reads();
+    checker.check_cycles(level);
+    checker.anomalies
+}
+
+type Filter = fn(DepKind) -> bool;
+
+struct Checker {
+    txns: BTreeMap<u64, TxnInfo>,
+    // (key, value) -> (writer, whether it's the final write of the key in the writer)
+    writers: HashMap<(String, String), (u64, bool)>,
+    // the committed writers of every key in the version order.
+    versions: BTreeMap<String, Vec<u64>>,
+    // a read of the deleted key can't tell which version it observes.
+    deleted: HashSet<String>,
+    deps: Vec<Dependency>,
+    anomalies: Vec<Anomaly>,
+}
+
+impl Checker {
+    fn new(events: &[Event]) -> Self {
+        let mut txns = BTreeMap::new();
+        for (i, event) in events.iter().enumerate() {
+            let info = txns.entry(event.txn()).or_insert_with(|| TxnInfo {
+                ops: vec![],
+                status: Status::Pending,
+            });
+            match event {
+                Event::Invoke { .. } => {}
+                Event::Read { key, value, .. } => {
+                    info.ops.push(Op::Read(key.clone(), value.clone()))
+                }
+                Event::Write { key, value, .. } => {
+                    info.ops.push(Op::Write(key.clone(), value.clone()))
+                }
+                Event::Scan {
+                    lower, upper, kvs, ..
+                } => info
+                    .ops
+                    .push(Op::Scan(lower.clone(), upper.clone(), kvs.clone())),
+                Event::Commit { version, .. } => info.status = Status::Committed(*version, i),
+                Event::Abort { .. } => info.status = Status::Aborted,
+                Event::Unknown { .. } => info.status = Status::Unknown,
+            }
+        }
+
+        let mut writers = HashMap::new();
+        let mut committed = vec![];
+        for (&id, info) in txns.iter() {
+            let mut last = BTreeMap::new();
+            for (i, op) in info.ops.iter().enumerate() {
+                if let Op::Write(key, value) = op {
+                    last.insert(key, (i, value));
+                }
+            }
+            for (i, op) in info.ops.iter().enumerate() {
+                if let Op::Write(key, Some(value)) = op {
+                    let is_final = last[key].0 == i;
+                    writers.insert((key.clone(), value.clone()), (id, is_final));
+                }
+            }
+            if let Status::Committed(version, pos) = info.status {
+                committed.push((version, pos, id, last));
+            }
+        }
+        committed.sort_by_key(|(version, pos, _, _)| (*version, *pos));
+        let mut versions: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+        let mut deleted = HashSet::new();
+        for (_, _, id, last) in committed {
+            for (key, (_, value)) in last {
+                versions.entry(key.clone()).or_default().push(id);
+                if value.is_none() {
+                    deleted.insert(key.clone());
+                }
+            }
+        }
+
+        let mut deps = vec![];
+        for (key, writers) in versions.iter() {
+            for w in writers.windows(2) {
+                deps.push(Dependency {
+                    from: w[0],
+                    to: w[1],
+                    kind: DepKind::WW,
+                    key: key.clone(),
+                });
+            }
+        }
+
+        Self {
+            txns,
+            writers,
+            versions,
+            deleted,
+            deps,
+            anomalies: vec![],
+        }
+    }
+
+    fn check_reads(&mut self) {
+        let mut deps = vec![];
+        let mut anomalies = vec![];
+        for (&reader, info) in self.txns.iter() {
+            if !matches!(info.status, Status::Committed(..)) {
+                continue;
+            }
+            let mut written = HashSet::new();
+            for op in info.ops.iter() {
+                let reads = match op {
+                    Op::Write(key, _) => {
+                        written.insert(key);
+                        continue;
+                    }
+                    Op::Read(key, value) => vec![(key, value.as_ref())],
+                    Op::Scan(lower, upper, kvs) => self.scan_reads(lower, upper, kvs),
+                };
+                for (key, value) in reads {
+                    if written.contains(key) {
+                        continue;
+                    }
+                    self.check_read(reader, key, value, &mut deps, &mut anomalies);
+                }
+            }
+        }
+        self.deps.extend(deps);
+        self.anomalies = anomalies;
+    }
+
+    // scan_reads returns the pairs found by the scan,
+    // and the committed keys in its range which are not found.
+    fn scan_reads<'a>(
+        &'a self,
+        lower: &str,
+        upper: &str,
+        kvs: &'a [(String, String)],
+    ) -> Vec<(&'a String, Option<&'a String>)> {
+        let found: HashSet<_> = kvs.iter().map(|(k, _)| k).collect();
+        let absent = self.versions.keys().filter(|key| {
+            cmp_keys(key, lower) != Ordering::Less
+                && cmp_keys(key, upper) == Ordering::Less
+                && !found.contains(key)
+        });
+        kvs.iter()
+            .map(|(k, v)| (k, Some(v)))
+            .chain(absent.map(|key| (key, None)))
+            .collect()
+    }
+
+    fn check_read(
+        &self,
+        reader: u64,
+        key: &str,
+        value: Option<&String>,
+        deps: &mut Vec<Dependency>,
+        anomalies: &mut Vec<Anomaly>,
+    ) {
+        let value = match value {
+            Some(value) => value,
+            None => {
+                if !self.deleted.contains(key) {
+                    deps.extend(self.anti_dependency(reader, key, None));
+                }
+                return;
+            }
+        };
+        let read_anomaly = |kind, explanation| Anomaly {
+            kind,
+            txns: vec![reader],
+            cycle: vec![],
+            explanation,
+        };
+        let (writer, is_final) = match self.writers.get(&(key.to_owned(), value.clone())) {
+            Some(&(writer, _)) if writer == reader => return,
+            Some(&w) => w,
+            None => {
+                anomalies.push(read_anomaly(
+                    AnomalyKind::GarbageRead,
+                    format!(
+                        "T{} reads {} = {} which is never written",
+                        reader, key, value
+                    ),
+                ));
+                return;
+            }
+        };
+        match self.txns[&writer].status {
+            Status::Pending | Status::Unknown => {}
+            Status::Aborted => anomalies.push(Anomaly {
+                txns: vec![writer, reader],
+                ..read_anomaly(
+                    AnomalyKind::G1a,
+                    format!(
+                        "T{} reads {} = {} written by aborted T{}",
+                        reader, key, value, writer
+                    ),
+                )
+            }),
+            Status::Committed(..) if !is_final => anomalies.push(Anomaly {
+                txns: vec![writer, reader],
+                ..read_anomaly(
+                    AnomalyKind::G1b,
+                    format!(
+                        "T{} reads {} = {} which is overwritten later by T{}",
+                        reader, key, value, writer
+                    ),
+                )
+            }),
+            Status::Committed(..) => {
+                deps.push(Dependency {
+                    from: writer,
+                    to: reader,
+                    kind: DepKind::WR,
+                    key: key.to_owned(),
+                });
+                deps.extend(self.anti_dependency(reader, key, Some(writer)));
+            }
+        }
+    }
+
+    // anti_dependency points from the reader to the txn installing the next version,
+    // `writer` is `None` if the reader observes the initial version.
+    fn anti_dependency(&self, reader: u64, key: &str, writer: Option<u64>) -> Option<Dependency> {
+        let versions = self.versions.get(key)?;
+        let next = match writer {
+            Some(writer) => versions.iter().position(|&w| w == writer)? + 1,
+            None => 0,
+        };
+        match versions.get(next) {
+            Some(&to) if to != reader => Some(Dependency {
+                from: reader,
+                to,
+                kind: DepKind::RW,
+                key: key.to_owned(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn check_cycles(&mut self, level: IsolationLevel) {
+        let mut graph: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (i, dep) in self.deps.iter().enumerate() {
+            graph.entry(dep.from).or_default().push(i);
+        }
+        let not_rw: Filter = |kind| kind != DepKind::RW;
+        let is_rw: Filter = |kind| kind == DepKind::RW;
+        // (the first edge of the cycle, the rest edges of the cycle)
+        let mut searches = vec![(not_rw, not_rw), (is_rw, not_rw)];
+        if level == IsolationLevel::Serializable {
+            searches.push((is_rw, |_| true));
+        }
+
+        let mut seen = HashSet::new();
+        for (first, rest) in searches {
+            for (i, dep) in self.deps.iter().enumerate() {
+                if !first(dep.kind) {
+                    continue;
+                }
+                let path = match self.shortest_path(&graph, dep.to, dep.from, rest) {
+                    Some(path) => path,
+                    None => continue,
+                };
+                let cycle: Vec<_> = std::iter::once(i)
+                    .chain(path)
+                    .map(|i| self.deps[i].clone())
+                    .collect();
+                let mut txns: Vec<_> = cycle.iter().map(|d| d.from).collect();
+                txns.sort_unstable();
+                if !seen.insert(txns) {
+                    continue;
+                }
+                let kind = match cycle.iter().filter(|d| d.kind == DepKind::RW).count() {
+                    0 => AnomalyKind::G1c,
+                    1 => AnomalyKind::GSingle,
+                    _ => AnomalyKind::G2,
+                };
+                let explanation = cycle
+                    .iter()
+                    .map(|d| d.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                self.anomalies.push(Anomaly {
+                    kind,
+                    txns: cycle.iter().map(|d| d.from).collect(),
+                    cycle,
+                    explanation,
+                });
+            }
+        }
+    }
+
+    // shortest_path returns the edges from `from` to `to` by BFS.
+    fn shortest_path(
+        &self,
+        graph: &HashMap<u64, Vec<usize>>,
+        from: u64,
+        to: u64,
+        allowed: Filter,
+    ) -> Option<Vec<usize>> {
+        // txn -> the edge reaching it
+        let mut parent: HashMap<u64, Option<usize>> = HashMap::new();
+        parent.insert(from, None);
+        let mut queue = VecDeque::new();
+        queue.push_back(from);
+        while let Some(txn) = queue.pop_front() {
+            if txn == to {
+                let mut path = vec![];
+                let mut cur = to;
+                while let Some(i) = parent[&cur] {
+                    path.push(i);
+                    cur = self.deps[i].from;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for &i in graph.get(&txn).into_iter().flatten() {
+                let dep = &self.deps[i];
+                if allowed(dep.kind) && !parent.contains_key(&dep.to) {
+                    parent.insert(dep.to, Some(i));
+                    queue.push_back(dep.to);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // HistoryBuilder writes the events of a txn at once, so the txns are serial by default.
+    #[derive(Default)]
+    struct HistoryBuilder {
+        events: Vec<Event>,
+    }
+
+    impl HistoryBuilder {
+        fn read(mut self, txn: u64, key: &str, value: Option<&str>) -> Self {
+            self.events.push(Event::Read {
+                txn,
+                key: key.to_owned(),
+                value: value.map(|v| v.to_owned()),
+            });
+            self
+        }
+
+        fn write(mut self, txn: u64, key: &str, value: &str) -> Self {
+            self.events.push(Event::Write {
+                txn,
+                key: key.to_owned(),
+                value: Some(value.to_owned()),
+            });
+            self
+        }
+
+        fn scan(mut self, txn: u64, lower: &str, upper: &str, kvs: &[(&str, &str)]) -> Self {
+            self.events.push(Event::Scan {
+                txn,
+                lower: lower.to_owned(),
+                upper: upper.to_owned(),
+                kvs: kvs
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            });
+            self
+        }
+
+        fn commit(mut self, txn: u64) -> Self {
+            self.events.push(Event::Commit { txn, version: None });
+            self
+        }
+
+        fn abort(mut self, txn: u64) -> Self {
+            self.events.push(Event::Abort { txn });
+            self
+        }
+
+        fn unknown(mut self, txn: u64) -> Self {
+            self.events.push(Event::Unknown { txn });
+            self
+        }
+
+        fn check(&self, level: IsolationLevel) -> Vec<AnomalyKind> {
+            check(&self.events, level)
+                .into_iter()
+                .map(|a| a.kind)
+                .collect()
+        }
+    }
+
+    use IsolationLevel::{Serializable, SnapshotIsolation};
+
+    #[test]
+    fn test_serializable() {
+        let h = HistoryBuilder::default()
+            .write(1, "x", "1")
+            .commit(1)
+            .read(2, "x", Some("1"))
+            .write(2, "y", "2")
+            .read(3, "y", None)
+            .read(3, "x", Some("1"))
+            .commit(3)
+            .commit(2);
+        assert_eq!(h.check(Serializable), vec![]);
+        assert_eq!(h.check(SnapshotIsolation), vec![]);
+    }
+
+    #[test]
+    fn test_read_anomalies() {
+        let h = HistoryBuilder::default()
+            .write(1, "x", "1")
+            .abort(1)
+            .write(2, "y", "1")
+            .write(2, "y", "2")
+            .commit(2)
+            .read(3, "x", Some("1"))
+            .read(3, "y", Some("1"))
+            .read(3, "z", Some("1"))
+            .commit(3)
+            // the uncommitted reader is not checked.
+            .read(4, "x", Some("1"))
+            // the txn with unknown result may be committed.
+            .write(5, "w", "1")
+            .unknown(5)
+            .read(6, "w", Some("1"))
+            .commit(6);
+        assert_eq!(
+            h.check(SnapshotIsolation),
+            vec![AnomalyKind::G1a, AnomalyKind::G1b, AnomalyKind::GarbageRead]
+        );
+    }
+
+    #[test]
+    fn test_circular_information_flow() {
+        let h = HistoryBuilder::default()
+            .write(1, "x", "1")
+            .write(2, "y", "2")
+            .read(1, "y", Some("2"))
+            .read(2, "x", Some("1"))
+            .commit(1)
+            .commit(2);
+        let anomalies = check(&h.events, SnapshotIsolation);
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].kind, AnomalyKind::G1c);
+        assert_eq!(
+            anomalies[0].to_string(),
+            "G1c (circular information flow): T1 reads y written by T2, T2 reads x written by T1"
+        );
+    }
+
+    #[test]
+    fn test_lost_update() {
+        let h = HistoryBuilder::default()
+            .read(1, "x", None)
+            .read(2, "x", None)
+            .write(1, "x", "1")
+            .write(2, "x", "2")
+            .commit(1)
+            .commit(2);
+        let anomalies = check(&h.events, SnapshotIsolation);
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].kind, AnomalyKind::GSingle);
+        assert_eq!(anomalies[0].txns, vec![2, 1]);
+        assert_eq!(
+            anomalies[0].explanation,
+            "T2 reads x before T1 overwrites it, T2 overwrites x written by T1"
+        );
+    }
+
+    #[test]
+    fn test_write_skew() {
+        let h = HistoryBuilder::default()
+            .read(1, "x", None)
+            .read(1, "y", None)
+            .read(2, "x", None)
+            .read(2, "y", None)
+            .write(1, "x", "1")
+            .write(2, "y", "2")
+            .commit(1)
+            .commit(2);
+        assert_eq!(h.check(SnapshotIsolation), vec![]);
+        assert_eq!(h.check(Serializable), vec![AnomalyKind::G2]);
+    }
+
+    #[test]
+    fn test_scan() {
+        // the scans of T1 and T2 miss the inserts of each other.
+        let h = HistoryBuilder::default()
+            .write(1, "10", "1")
+            .commit(1)
+            .scan(2, "2", "9", &[])
+            .scan(3, "2", "9", &[])
+            .write(2, "3", "2")
+            .write(3, "4", "3")
+            .commit(2)
+            .commit(3)
+            .scan(4, "2", "9", &[("3", "2"), ("4", "3")])
+            .commit(4);
+        assert_eq!(h.check(SnapshotIsolation), vec![]);
+        assert_eq!(h.check(Serializable), vec![AnomalyKind::G2]);
+
+        // T2 misses the insert of T1, and then overwrites it.
+        let h = HistoryBuilder::default()
+            .scan(1, "2", "9", &[])
+            .scan(2, "2", "9", &[])
+            .write(1, "3", "1")
+            .write(2, "3", "2")
+            .commit(1)
+            .commit(2)
+            .write(3, "5", "3")
+            .abort(3)
+            .scan(4, "2", "9", &[("3", "2"), ("5", "3")])
+            .commit(4);
+        assert_eq!(
+            h.check(SnapshotIsolation),
+            vec![AnomalyKind::G1a, AnomalyKind::GSingle]
+        );
+    }
+}
@@ -0,0 +1,335 @@
+//! Record the history of txns and check the isolation level it satisfies.
+//!
+//! The checker infers the dependencies between txns from the values they read,
+//! so every value written to a key should be unique in the history.
+use crate::codec::{Key, Value};
+use crate::txn::percolator::{PercolatorServer, PercolatorTxn};
+use crate::txn::Txn;
+use crate::util::{Error, Result, TxnError};
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+mod checker;
+pub use checker::{check, Anomaly, AnomalyKind, DepKind, Dependency, IsolationLevel};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    Invoke {
+        txn: u64,
+    },
+    /// `None` means the key is not found.
+    Read {
+        txn: u64,
+        key: String,
+        value: Option<String>,
+    },
+    /// `None` means the key is deleted.
+    Write {
+        txn: u64,
+        key: String,
+        value: Option<String>,
+    },
+    /// A predicate read of the keys in `[lower, upper)`, `kvs` are the pairs found,
+    /// so the keys absent from it are read as not found.
+    Scan {
+        txn: u64,
+        lower: String,
+        upper: String,
+        kvs: Vec<(String, String)>,
+    },
+    /// The committed txns take effect in the order of `version`,
+    /// or in the order of their commit events if the version is unknown.
+    Commit {
+        txn: u64,
+        version: Option<u64>,
+    },
+    Abort {
+        txn: u64,
+    },
+    /// The commit fails without knowing its result, e.g. the response is lost,
+    /// so the txn may be committed or not.
+    Unknown {
+        txn: u64,
+    },
+}
+
+impl Event {
+    pub fn txn(&self) -> u64 {
+        match self {
+            Event::Invoke { txn }
+            | Event::Read { txn, .. }
+            | Event::Write { txn, .. }
+            | Event::Scan { txn, .. }
+            | Event::Commit { txn, .. }
+            | Event::Abort { txn }
+            | Event::Unknown { txn } => *txn,
+        }
+    }
+}
+
+/// History is a concurrent log of txn events.
+#[derive(Default)]
+pub struct History {
+    events: Mutex<Vec<Event>>,
+    next_txn: AtomicU64,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// invoke allocates an id for a new txn and records its invocation.
+    pub fn invoke(&self) -> u64 {
+        let txn = self.next_txn.fetch_add(1, Ordering::SeqCst) + 1;
+        self.record(Event::Invoke { txn });
+        txn
+    }
+
+    pub fn record(&self, event: Event) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    pub fn events(&self) -> Vec<Event> {
+        self.events.lock().unwrap().clone()
+    }
+
+    pub fn check(&self, level: IsolationLevel) -> Vec<Anomaly> {
+        check(&self.events(), level)
+    }
+}
+
+/// CommitVersion tells the history the order in which the committed txns take effect.
+pub trait CommitVersion {
+    fn commit_version(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl<S: PercolatorServer> CommitVersion for PercolatorTxn<S> {
+    fn commit_version(&self) -> Option<u64> {
+        self.commit_ts().map(|ts| ts.into_inner())
+    }
+}
+
+/// RecordedTxn records the invocation and completion of the inner txn,
+/// a commit failed by the txn errors is recorded as aborted,
+/// and the other failures, e.g. the request errors, are recorded as unknown.
+pub struct RecordedTxn<T> {
+    inner: T,
+    history: Arc<History>,
+    id: u64,
+}
+
+impl<T> RecordedTxn<T> {
+    pub fn new(inner: T, history: Arc<History>) -> Self {
+        let id = history.invoke();
+        Self { inner, history, id }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    fn read_event<K: Key, V: Value>(&self, key: &K, value: Option<&V>) -> Event {
+        Event::Read {
+            txn: self.id,
+            key: key.to_string(),
+            value: value.map(|v| v.to_string()),
+        }
+    }
+
+    fn write_event<K: Key, V: Value>(&self, key: &K, value: Option<&V>) -> Event {
+        Event::Write {
+            txn: self.id,
+            key: key.to_string(),
+            value: value.map(|v| v.to_string()),
+        }
+    }
+}
+
+impl<S: PercolatorServer> RecordedTxn<PercolatorTxn<S>> {
+    pub async fn get(&mut self, server: &S, key: &S::K) -> Result<Option<S::V>> {
+        let value = self.inner.get(server, key).await?;
+        self.history.record(self.read_event(key, value.as_ref()));
+        Ok(value)
+    }
+
+    pub async fn scan(
+        &mut self,
+        server: &S,
+        lower: &S::K,
+        upper: &S::K,
+    ) -> Result<Vec<(S::K, S::V)>> {
+        let kvs = self.inner.scan(server, lower, upper).await?;
+        self.history.record(Event::Scan {
+            txn: self.id,
+            lower: lower.to_string(),
+            upper: upper.to_string(),
+            kvs: kvs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        });
+        Ok(kvs)
+    }
+
+    pub fn put(&mut self, key: S::K, value: S::V) -> Result<()> {
+        let event = self.write_event(&key, Some(&value));
+        self.inner.put(key, value)?;
+        self.history.record(event);
+        Ok(())
+    }
+
+    pub fn del(&mut self, key: S::K) -> Result<()> {
+        let event = self.write_event::<_, S::V>(&key, None);
+        self.inner.del(key)?;
+        self.history.record(event);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: Txn + CommitVersion + Send> Txn for RecordedTxn<T> {
+    type Server = T::Server;
+
+    async fn execute(&mut self, server: &Self::Server) -> Result<()> {
+        self.inner.execute(server).await
+    }
+
+    async fn commit(&mut self, server: &Self::Server) -> Result<()> {
+        let res = self.inner.commit(server).await;
+        let event = match res {
+            Ok(()) => Event::Commit {
+                txn: self.id,
+                version: self.inner.commit_version(),
+            },
+            // the failed prewrite is rolled back, and the rolled back txn is never committed.
+            Err(Error::TxnError(
+                TxnError::WriteConflict { .. }
+                | TxnError::KeyIsLocked { .. }
+                | TxnError::RolledBack(_),
+            )) => Event::Abort { txn: self.id },
+            Err(_) => Event::Unknown { txn: self.id },
+        };
+        self.history.record(event);
+        res
+    }
+
+    async fn rollback(&mut self, server: &Self::Server) -> Result<()> {
+        let res = self.inner.rollback(server).await;
+        self.history.record(Event::Abort { txn: self.id });
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::txn::percolator::test::{new_server, TestServer};
+    use crate::util::test::run_in_tokio;
+    use crate::util::Rng;
+
+    const KEYS: [i32; 6] = [1, 2, 3, 51, 52, 53];
+
+    // transfer reads 2 random keys and writes unique values to them.
+    async fn transfer(
+        txn: &mut RecordedTxn<PercolatorTxn<TestServer>>,
+        server: &TestServer,
+        rng: &mut Rng,
+    ) -> Result<()> {
+        txn.execute(server).await?;
+        let a = KEYS[rng.gen_range(0, 6) as usize];
+        let b = KEYS[rng.gen_range(0, 6) as usize];
+        txn.get(server, &a).await?;
+        txn.get(server, &b).await?;
+        let id = txn.id() as i32;
+        txn.put(a, id * 10)?;
+        if a != b && rng.gen_bool(0.5) {
+            txn.put(b, id * 10 + 1)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_percolator() {
+        run_in_tokio(async move {
+            let server = Arc::new(new_server());
+            let history = Arc::new(History::new());
+            let handles: Vec<_> = (0..8)
+                .map(|client| {
+                    let (server, history) = (server.clone(), history.clone());
+                    tokio::spawn(async move {
+                        let mut rng = Rng::new(client);
+                        for _ in 0..20 {
+                            let mut txn = RecordedTxn::new(PercolatorTxn::new(), history.clone());
+                            match transfer(&mut txn, &server, &mut rng).await {
+                                Ok(()) => {
+                                    let _ = txn.commit(&server).await;
+                                }
+                                Err(_) => txn.rollback(&server).await.unwrap(),
+                            }
+                        }
+                    })
+                })
+                .collect();
+            futures::future::join_all(handles).await;
+
+            let events = history.events();
+            let completed = events
+                .iter()
+                .filter(|e| {
+                    matches!(
+                        e,
+                        Event::Commit { .. } | Event::Abort { .. } | Event::Unknown { .. }
+                    )
+                })
+                .count();
+            assert_eq!(completed, 160);
+            assert!(events.iter().any(|e| matches!(e, Event::Commit { .. })));
+            assert_eq!(history.check(IsolationLevel::SnapshotIsolation), vec![]);
+            std::mem::forget(server);
+        });
+    }
+
+    #[test]
+    fn test_record_abort_and_scan() {
+        run_in_tokio(async move {
+            let server = new_server();
+            let history = Arc::new(History::new());
+            let mut txn1 = RecordedTxn::new(PercolatorTxn::new(), history.clone());
+            txn1.execute(&server).await.unwrap();
+            let mut txn2 = RecordedTxn::new(PercolatorTxn::new(), history.clone());
+            txn2.execute(&server).await.unwrap();
+            assert_eq!(txn1.scan(&server, &0, &100).await.unwrap(), vec![]);
+            txn1.put(1, 1).unwrap();
+            txn2.put(1, 2).unwrap();
+            txn2.commit(&server).await.unwrap();
+            // the write conflict is a definite abort.
+            assert!(txn1.commit(&server).await.is_err());
+
+            let (id1, id2) = (txn1.id(), txn2.id());
+            let events = history.events();
+            assert_eq!(
+                events[2],
+                Event::Scan {
+                    txn: id1,
+                    lower: "0".to_owned(),
+                    upper: "100".to_owned(),
+                    kvs: vec![],
+                }
+            );
+            assert!(events.contains(&Event::Abort { txn: id1 }));
+            assert!(events
+                .iter()
+                .any(|e| matches!(e, Event::Commit { txn, .. } if *txn == id2)));
+            assert_eq!(history.check(IsolationLevel::Serializable), vec![]);
+            std::mem::forget(server);
+        });
+    }
+}
@@ -1,43 +1,371 @@
-use crate::codec::{Key, Value};
-use crate::util::Result;
-use crate::node::Server;
+use crate::client::{TxnRequest, TxnResponse};
+use crate::codec::{Encode, Key, Value};
+use crate::node::{Node, Server};
 use crate::txn::Txn;
+use crate::util::{CodecError, Error, Result, TxnError};
 use async_trait::async_trait;
+use std::marker::PhantomData;
 
 pub enum Op<K: Key, V: Value> {
     Put(K, V),
     Get(K),
     Del(K),
+    /// Scan the keys in `[lower, upper)`.
     Scan(K, K),
+    /// Commit the ops before, the following ops run in a new txn.
     Commit,
+    /// Roll back the ops before, the following ops run in a new txn.
     Rollback,
 }
 
-pub enum KVOps<K: Key, V: Value> {
-    Ops(Ops<K, V>),
+/// OpResult is the result of the op at the same position.
+#[derive(Debug, PartialEq, Eq)]
+pub enum OpResult<K: Key, V: Value> {
+    Put,
+    Get(Option<V>),
+    Del,
+    Scan(Vec<(K, V)>),
+    Commit,
+    Rollback,
+}
+
+/// KVOps runs on any server which serves the `TxnRequest`s, e.g. `SessionServer`.
+pub enum KVOps<K: Key, V: Value, S> {
+    Ops(Ops<K, V, S>),
+    /// The ops are sent one by one by an `InteractiveTxnClient`, nothing to execute here.
     InteractiveTxn,
 }
 
-struct Ops<K: Key, V: Value> {
+pub struct Ops<K: Key, V: Value, S> {
     ops: Vec<Op<K, V>>,
+    results: Vec<OpResult<K, V>>,
+    // the id of the running txn in the server.
+    txn: Option<u64>,
+    phantom: PhantomData<fn(&S)>,
+}
+
+impl<K, V, S> KVOps<K, V, S>
+where
+    K: Key + Send + Sync,
+    V: Value + Send + Sync,
+    S: Server + Node<Req = TxnRequest<K, V>, Res = TxnResponse<K, V>>,
+{
+    pub fn new(ops: Vec<Op<K, V>>) -> Self {
+        KVOps::Ops(Ops {
+            ops,
+            results: vec![],
+            txn: None,
+            phantom: PhantomData,
+        })
+    }
+
+    /// results returns the results of the executed ops.
+    pub fn results(&self) -> &[OpResult<K, V>] {
+        match self {
+            KVOps::Ops(ops) => &ops.results,
+            KVOps::InteractiveTxn => &[],
+        }
+    }
+
+    /// run executes the ops and commits the last txn, like `Server::execute`,
+    /// but the results are returned.
+    pub async fn run(mut self, server: &S) -> Result<Vec<OpResult<K, V>>> {
+        if let Err(e) = self.execute(server).await {
+            self.rollback(server).await?;
+            return Err(e);
+        }
+        self.commit(server).await?;
+        match self {
+            KVOps::Ops(ops) => Ok(ops.results),
+            KVOps::InteractiveTxn => Ok(vec![]),
+        }
+    }
+}
+
+impl<K, V, S> Ops<K, V, S>
+where
+    K: Key + Send + Sync,
+    V: Value + Send + Sync,
+    S: Server + Node<Req = TxnRequest<K, V>, Res = TxnResponse<K, V>>,
+{
+    async fn begin(&mut self, server: &S) -> Result<u64> {
+        if let Some(txn) = self.txn {
+            return Ok(txn);
+        }
+        match server.process(TxnRequest::Begin).await? {
+            TxnResponse::Begin(txn) => {
+                self.txn = Some(txn);
+                Ok(txn)
+            }
+            _ => Err(Error::Unknown),
+        }
+    }
+
+    // finish commits or rolls back the running txn.
+    async fn finish(&mut self, server: &S, commit: bool) -> Result<()> {
+        let txn = match self.txn.take() {
+            Some(txn) => txn,
+            None => return Ok(()),
+        };
+        let req = if commit {
+            TxnRequest::Commit { txn }
+        } else {
+            TxnRequest::Rollback { txn }
+        };
+        server.process(req).await?;
+        Ok(())
+    }
+
+    async fn execute(&mut self, server: &S) -> Result<()> {
+        // the ops are executed only once.
+        let ops = std::mem::take(&mut self.ops);
+        for op in ops {
+            let result = match op {
+                Op::Put(key, value) => {
+                    let txn = self.begin(server).await?;
+                    server.process(TxnRequest::Put { txn, key, value }).await?;
+                    OpResult::Put
+                }
+                Op::Get(key) => {
+                    let txn = self.begin(server).await?;
+                    match server.process(TxnRequest::Get { txn, key }).await? {
+                        TxnResponse::Get(v) => OpResult::Get(v),
+                        _ => return Err(Error::Unknown),
+                    }
+                }
+                Op::Del(key) => {
+                    let txn = self.begin(server).await?;
+                    server.process(TxnRequest::Del { txn, key }).await?;
+                    OpResult::Del
+                }
+                Op::Scan(lower, upper) => {
+                    let txn = self.begin(server).await?;
+                    match server
+                        .process(TxnRequest::Scan { txn, lower, upper })
+                        .await?
+                    {
+                        TxnResponse::Scan(kvs) => OpResult::Scan(kvs),
+                        _ => return Err(Error::Unknown),
+                    }
+                }
+                Op::Commit => {
+                    self.finish(server, true).await?;
+                    OpResult::Commit
+                }
+                Op::Rollback => {
+                    self.finish(server, false).await?;
+                    OpResult::Rollback
+                }
+            };
+            self.results.push(result);
+        }
+        Ok(())
+    }
 }
 
 #[async_trait]
-impl<K: Key, V: Value, S: Server> Txn for KVOps<K, V> {
+impl<K, V, S> Txn for KVOps<K, V, S>
+where
+    K: Key + Send + Sync,
+    V: Value + Send + Sync,
+    S: Server + Node<Req = TxnRequest<K, V>, Res = TxnResponse<K, V>>,
+{
     type Server = S;
 
     /// The execution order should be:
     /// 1. execute,
     /// 2. commit or rollback.
     async fn execute(&mut self, server: &Self::Server) -> Result<()> {
-
+        match self {
+            KVOps::Ops(ops) => ops.execute(server).await,
+            KVOps::InteractiveTxn => Err(TxnError::InvalidState(
+                "interactive txn is driven by the client".to_owned(),
+            )
+            .into()),
+        }
     }
 
     async fn commit(&mut self, server: &Self::Server) -> Result<()> {
-
+        match self {
+            KVOps::Ops(ops) => ops.finish(server, true).await,
+            KVOps::InteractiveTxn => Ok(()),
+        }
     }
 
     async fn rollback(&mut self, server: &Self::Server) -> Result<()> {
+        match self {
+            KVOps::Ops(ops) => ops.finish(server, false).await,
+            KVOps::InteractiveTxn => Ok(()),
+        }
+    }
+}
+
+impl<K: Key + Encode, V: Value + Encode> Encode for Op<K, V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Op::Put(key, value) => {
+                0u8.encode(buf);
+                key.encode(buf);
+                value.encode(buf);
+            }
+            Op::Get(key) => {
+                1u8.encode(buf);
+                key.encode(buf);
+            }
+            Op::Del(key) => {
+                2u8.encode(buf);
+                key.encode(buf);
+            }
+            Op::Scan(lower, upper) => {
+                3u8.encode(buf);
+                lower.encode(buf);
+                upper.encode(buf);
+            }
+            Op::Commit => 4u8.encode(buf),
+            Op::Rollback => 5u8.encode(buf),
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        let op = match u8::decode(buf)? {
+            0 => {
+                let key = K::decode(buf)?;
+                Op::Put(key, V::decode(buf)?)
+            }
+            1 => Op::Get(K::decode(buf)?),
+            2 => Op::Del(K::decode(buf)?),
+            3 => {
+                let lower = K::decode(buf)?;
+                Op::Scan(lower, K::decode(buf)?)
+            }
+            4 => Op::Commit,
+            5 => Op::Rollback,
+            tag => return Err(CodecError::InvalidData(format!("invalid op tag {}", tag)).into()),
+        };
+        Ok(op)
+    }
+}
+
+impl<K: Key + Encode, V: Value + Encode> Encode for OpResult<K, V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            OpResult::Put => 0u8.encode(buf),
+            OpResult::Get(value) => {
+                1u8.encode(buf);
+                value.encode(buf);
+            }
+            OpResult::Del => 2u8.encode(buf),
+            OpResult::Scan(kvs) => {
+                3u8.encode(buf);
+                kvs.encode(buf);
+            }
+            OpResult::Commit => 4u8.encode(buf),
+            OpResult::Rollback => 5u8.encode(buf),
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        let res = match u8::decode(buf)? {
+            0 => OpResult::Put,
+            1 => OpResult::Get(Option::decode(buf)?),
+            2 => OpResult::Del,
+            3 => OpResult::Scan(Vec::decode(buf)?),
+            4 => OpResult::Commit,
+            5 => OpResult::Rollback,
+            tag => {
+                let msg = format!("invalid op result tag {}", tag);
+                return Err(CodecError::InvalidData(msg).into());
+            }
+        };
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::txn::percolator::test::new_server;
+    use crate::txn::percolator::SessionServer;
+    use crate::util::test::run_in_tokio;
+
+    #[test]
+    fn test_kv_ops() {
+        run_in_tokio(async move {
+            let server = SessionServer::new(new_server());
+            let ops = KVOps::new(vec![
+                Op::Put(1, 1),
+                Op::Put(51, 51),
+                Op::Get(1),
+                Op::Commit,
+                Op::Del(1),
+                Op::Put(52, 52),
+                Op::Scan(0, 100),
+                Op::Rollback,
+                Op::Get(1),
+                Op::Put(2, 2),
+            ]);
+            let results = ops.run(&server).await.unwrap();
+            assert_eq!(
+                results,
+                vec![
+                    OpResult::Put,
+                    OpResult::Put,
+                    OpResult::Get(Some(1)),
+                    OpResult::Commit,
+                    OpResult::Del,
+                    OpResult::Put,
+                    OpResult::Scan(vec![(51, 51), (52, 52)]),
+                    OpResult::Rollback,
+                    OpResult::Get(Some(1)),
+                    OpResult::Put,
+                ]
+            );
+
+            let mut ops = KVOps::new(vec![Op::Scan(0, 100)]);
+            ops.execute(&server).await.unwrap();
+            ops.commit(&server).await.unwrap();
+            assert_eq!(
+                ops.results(),
+                &[OpResult::Scan(vec![(1, 1), (2, 2), (51, 51)])]
+            );
+            // the last txn is committed by `Server::execute`.
+            server.execute(KVOps::new(vec![Op::Del(2)])).await.unwrap();
+            let results = KVOps::new(vec![Op::Get(2)]).run(&server).await.unwrap();
+            assert_eq!(results, vec![OpResult::Get(None)]);
+            std::mem::forget(server);
+        });
+    }
+
+    #[test]
+    fn test_encode_op() {
+        let ops: Vec<Op<i32, i32>> = vec![
+            Op::Put(1, -1),
+            Op::Get(2),
+            Op::Del(3),
+            Op::Scan(0, 100),
+            Op::Commit,
+            Op::Rollback,
+        ];
+        let mut buf = vec![];
+        ops.encode(&mut buf);
+        assert_eq!(buf.len(), 4 + 9 + 5 + 5 + 9 + 1 + 1);
+        let decoded = Vec::<Op<i32, i32>>::decode(&mut buf.as_slice()).unwrap();
+        let mut again = vec![];
+        decoded.encode(&mut again);
+        assert_eq!(buf, again);
 
+        let results: Vec<OpResult<i32, i32>> = vec![
+            OpResult::Get(Some(1)),
+            OpResult::Get(None),
+            OpResult::Scan(vec![(1, 1), (2, 2)]),
+            OpResult::Commit,
+        ];
+        let mut buf = vec![];
+        results.encode(&mut buf);
+        assert_eq!(
+            Vec::<OpResult<i32, i32>>::decode(&mut buf.as_slice()).unwrap(),
+            results
+        );
+        assert!(Op::<i32, i32>::decode(&mut [6u8].as_ref()).is_err());
     }
 }
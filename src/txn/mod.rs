@@ -15,4 +15,6 @@ pub trait Txn {
     async fn rollback(&mut self, server: &Self::Server) -> Result<()>;
 }
 
+pub mod history;
 pub mod kv_ops;
+pub mod percolator;
@@ -0,0 +1,127 @@
+//! A reference implementation of [Percolator](https://research.google/pubs/pub36726/).
+//!
+//! Every key has 3 columns in the storage node:
+//! - data, the values written by prewrite, versioned by `start_ts`.
+//! - lock, at most one lock per key, which points to the primary key of the txn.
+//! - write, the commit and rollback records, versioned by `commit_ts`.
+//!
+//! The txn is committed once the primary key is committed,
+//! the secondary locks left by a crashed txn are resolved by the readers.
+use crate::codec::{Key, Value};
+use crate::node::Server;
+use crate::request::Sender;
+use crate::shard::SubRange;
+use crate::tso::{TSOClient, TSORequest, TSOResponse, TimeStamp};
+
+mod node;
+mod session;
+mod txn;
+pub use node::PercolatorNode;
+pub use session::SessionServer;
+pub use txn::PercolatorTxn;
+
+#[cfg(test)]
+pub mod test;
+
+/// The lock will be treated as expired `DEFAULT_LOCK_TTL` milliseconds after it's written.
+pub const DEFAULT_LOCK_TTL: u64 = 3000;
+
+pub enum Mutation<K: Key, V: Value> {
+    Put(K, V),
+    Del(K),
+}
+
+impl<K: Key, V: Value> Mutation<K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            Mutation::Put(k, _) => k,
+            Mutation::Del(k) => k,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct LockInfo<K: Key> {
+    pub key: K,
+    pub primary: K,
+    pub start_ts: TimeStamp,
+    pub ttl: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TxnStatus {
+    Committed(TimeStamp),
+    RolledBack,
+    /// The primary lock is still alive, it'll expire after `ttl` milliseconds since `start_ts`.
+    Locked {
+        ttl: u64,
+    },
+}
+
+pub enum PercolatorRequest<K: Key, V: Value> {
+    Get {
+        key: K,
+        ts: TimeStamp,
+    },
+    /// Scan the keys in `[lower, upper)` stored in the node.
+    Scan {
+        lower: K,
+        upper: K,
+        ts: TimeStamp,
+    },
+    Prewrite {
+        mutations: Vec<Mutation<K, V>>,
+        primary: K,
+        start_ts: TimeStamp,
+        ttl: u64,
+    },
+    Commit {
+        keys: Vec<K>,
+        start_ts: TimeStamp,
+        commit_ts: TimeStamp,
+    },
+    Rollback {
+        keys: Vec<K>,
+        start_ts: TimeStamp,
+    },
+    /// Check the status of the txn by its primary key,
+    /// the primary lock will be rolled back if it's expired at `current_ts`.
+    CheckTxnStatus {
+        primary: K,
+        start_ts: TimeStamp,
+        current_ts: TimeStamp,
+    },
+}
+
+#[derive(Debug)]
+pub enum PercolatorResponse<K: Key, V: Value> {
+    Get(Option<V>),
+    Scan(Vec<(K, V)>),
+    Prewrite,
+    Commit,
+    Rollback,
+    TxnStatus(TxnStatus),
+    /// The request is blocked by a lock of another txn.
+    Locked(LockInfo<K>),
+}
+
+/// PercolatorServer provides everything a `PercolatorTxn` needs from the server.
+pub trait PercolatorServer: Server {
+    type K: Key + Send + Sync + 'static;
+    type V: Value + Send + Sync + 'static;
+    type Storage: Sender<
+            Req = PercolatorRequest<Self::K, Self::V>,
+            Res = PercolatorResponse<Self::K, Self::V>,
+        > + Sync;
+    type TSO: Sender<Req = TSORequest, Res = TSOResponse> + Sync;
+
+    /// route finds the storage node of the key, usually by `Shard::key2node`.
+    fn route(&self, key: &Self::K) -> &Self::Storage;
+    /// route_range splits `[lower, upper)` by the storage nodes, usually by `Shard::split_range`.
+    fn route_range(
+        &self,
+        lower: &Self::K,
+        upper: &Self::K,
+    ) -> Vec<SubRange<'_, Self::K, Self::Storage>>;
+    fn tso(&self) -> &TSOClient<Self::TSO>;
+}
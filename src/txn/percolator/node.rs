@@ -0,0 +1,462 @@
+use crate::codec::Key;
+use crate::node::Node;
+use crate::storage::SnapshotEngine;
+use crate::tso::TimeStamp;
+use crate::txn::percolator::{
+    LockInfo, Mutation, PercolatorRequest, PercolatorResponse, TxnStatus,
+};
+use crate::util::{Result, TxnError};
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::ops::Bound::{Excluded, Included, Unbounded};
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum WriteKind {
+    Put,
+    Del,
+    Rollback,
+}
+
+struct Lock<K: Key> {
+    primary: K,
+    start_ts: TimeStamp,
+    ttl: u64,
+    kind: WriteKind,
+}
+
+struct Write {
+    start_ts: TimeStamp,
+    kind: WriteKind,
+}
+
+// the lock and write columns, the data column is stored in the engine.
+struct Columns<K: Key> {
+    locks: BTreeMap<K, Lock<K>>,
+    // key -> commit_ts -> write, the rollback records are written at start_ts.
+    writes: BTreeMap<K, BTreeMap<TimeStamp, Write>>,
+}
+
+impl<K: Key> Columns<K> {
+    fn lock_info(&self, key: &K) -> Option<LockInfo<K>> {
+        self.locks.get(key).map(|lock| LockInfo {
+            key: key.to_owned(),
+            primary: lock.primary.to_owned(),
+            start_ts: lock.start_ts,
+            ttl: lock.ttl,
+        })
+    }
+
+    // find_write_by_start_ts returns the commit_ts and kind of the txn's write record.
+    fn find_write_by_start_ts(
+        &self,
+        key: &K,
+        start_ts: TimeStamp,
+    ) -> Option<(TimeStamp, WriteKind)> {
+        self.writes.get(key).and_then(|writes| {
+            writes
+                .range((Included(start_ts), Unbounded))
+                .find(|(_, w)| w.start_ts == start_ts)
+                .map(|(commit_ts, w)| (*commit_ts, w.kind))
+        })
+    }
+
+    // newest_commit returns the commit_ts of the newest committed write no earlier than start_ts.
+    fn newest_commit(&self, key: &K, start_ts: TimeStamp) -> Option<TimeStamp> {
+        self.writes.get(key).and_then(|writes| {
+            writes
+                .range((Included(start_ts), Unbounded))
+                .rev()
+                .find(|(_, w)| w.kind != WriteKind::Rollback)
+                .map(|(commit_ts, _)| *commit_ts)
+        })
+    }
+
+    // visible_write returns the newest committed write no later than ts.
+    fn visible_write(&self, key: &K, ts: TimeStamp) -> Option<&Write> {
+        self.writes.get(key).and_then(|writes| {
+            writes
+                .range((Unbounded, Included(ts)))
+                .rev()
+                .map(|(_, w)| w)
+                .find(|w| w.kind != WriteKind::Rollback)
+        })
+    }
+
+    fn write(&mut self, key: &K, commit_ts: TimeStamp, w: Write) {
+        if let Some(writes) = self.writes.get_mut(key) {
+            writes.insert(commit_ts, w);
+        } else {
+            let mut writes = BTreeMap::new();
+            writes.insert(commit_ts, w);
+            self.writes.insert(key.to_owned(), writes);
+        }
+    }
+
+    fn rollback(&mut self, key: &K, start_ts: TimeStamp) {
+        if let Some(lock) = self.locks.get(key) {
+            if lock.start_ts == start_ts {
+                self.locks.remove(key);
+            }
+        }
+        let w = Write {
+            start_ts,
+            kind: WriteKind::Rollback,
+        };
+        self.write(key, start_ts, w);
+    }
+}
+
+/// PercolatorNode is the storage node of the percolator protocol.
+pub struct PercolatorNode<E: SnapshotEngine> {
+    data: E,
+    columns: Mutex<Columns<E::K>>,
+}
+
+impl<E: SnapshotEngine> PercolatorNode<E> {
+    pub fn new(engine: E) -> Self {
+        Self {
+            data: engine,
+            columns: Mutex::new(Columns {
+                locks: BTreeMap::new(),
+                writes: BTreeMap::new(),
+            }),
+        }
+    }
+
+    fn get(&self, key: E::K, ts: TimeStamp) -> Result<PercolatorResponse<E::K, E::V>> {
+        let columns = self.columns.lock().unwrap();
+        if let Some(lock) = columns.lock_info(&key) {
+            if lock.start_ts <= ts {
+                return Ok(PercolatorResponse::Locked(lock));
+            }
+        }
+        let v = match columns.visible_write(&key, ts) {
+            Some(w) if w.kind == WriteKind::Put => self.data.get(&key, w.start_ts)?,
+            _ => None,
+        };
+        Ok(PercolatorResponse::Get(v))
+    }
+
+    fn scan(
+        &self,
+        lower: E::K,
+        upper: E::K,
+        ts: TimeStamp,
+    ) -> Result<PercolatorResponse<E::K, E::V>> {
+        if lower >= upper {
+            return Ok(PercolatorResponse::Scan(vec![]));
+        }
+        let columns = self.columns.lock().unwrap();
+        let range = (Included(&lower), Excluded(&upper));
+        let locked = columns.locks.range(range).find(|(_, l)| l.start_ts <= ts);
+        if let Some((key, _)) = locked {
+            return Ok(PercolatorResponse::Locked(columns.lock_info(key).unwrap()));
+        }
+        let mut kvs = vec![];
+        for key in columns.writes.range(range).map(|(k, _)| k) {
+            match columns.visible_write(key, ts) {
+                Some(w) if w.kind == WriteKind::Put => {
+                    if let Some(v) = self.data.get(key, w.start_ts)? {
+                        kvs.push((key.to_owned(), v));
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(PercolatorResponse::Scan(kvs))
+    }
+
+    fn prewrite(
+        &self,
+        mutations: Vec<Mutation<E::K, E::V>>,
+        primary: E::K,
+        start_ts: TimeStamp,
+        ttl: u64,
+    ) -> Result<PercolatorResponse<E::K, E::V>> {
+        let mut columns = self.columns.lock().unwrap();
+        for m in mutations.iter() {
+            let key = m.key();
+            if let Some(lock) = columns.lock_info(key) {
+                if lock.start_ts != start_ts {
+                    return Ok(PercolatorResponse::Locked(lock));
+                }
+                continue;
+            }
+            match columns.find_write_by_start_ts(key, start_ts) {
+                Some((_, WriteKind::Rollback)) => {
+                    return Err(TxnError::RolledBack(start_ts.into_inner()).into())
+                }
+                Some(_) => return Err(TxnError::Committed(start_ts.into_inner()).into()),
+                None => (),
+            }
+            if let Some(commit_ts) = columns.newest_commit(key, start_ts) {
+                return Err(TxnError::WriteConflict {
+                    key: key.to_string(),
+                    start_ts: start_ts.into_inner(),
+                    conflict_ts: commit_ts.into_inner(),
+                }
+                .into());
+            }
+        }
+        for m in mutations {
+            let (key, kind) = match m {
+                Mutation::Put(k, v) => {
+                    self.data.put(k.to_owned(), v, start_ts)?;
+                    (k, WriteKind::Put)
+                }
+                Mutation::Del(k) => (k, WriteKind::Del),
+            };
+            let lock = Lock {
+                primary: primary.to_owned(),
+                start_ts,
+                ttl,
+                kind,
+            };
+            columns.locks.insert(key, lock);
+        }
+        Ok(PercolatorResponse::Prewrite)
+    }
+
+    fn commit(
+        &self,
+        keys: Vec<E::K>,
+        start_ts: TimeStamp,
+        commit_ts: TimeStamp,
+    ) -> Result<PercolatorResponse<E::K, E::V>> {
+        let mut columns = self.columns.lock().unwrap();
+        let mut locked = Vec::with_capacity(keys.len());
+        for key in keys.iter() {
+            match columns.locks.get(key) {
+                Some(lock) if lock.start_ts == start_ts => locked.push(true),
+                _ => match columns.find_write_by_start_ts(key, start_ts) {
+                    // committed before, the commit request is retried.
+                    Some((_, kind)) if kind != WriteKind::Rollback => locked.push(false),
+                    _ => return Err(TxnError::RolledBack(start_ts.into_inner()).into()),
+                },
+            }
+        }
+        for (key, locked) in keys.iter().zip(locked) {
+            if !locked {
+                continue;
+            }
+            let lock = columns.locks.remove(key).unwrap();
+            let w = Write {
+                start_ts,
+                kind: lock.kind,
+            };
+            columns.write(key, commit_ts, w);
+        }
+        Ok(PercolatorResponse::Commit)
+    }
+
+    fn rollback(
+        &self,
+        keys: Vec<E::K>,
+        start_ts: TimeStamp,
+    ) -> Result<PercolatorResponse<E::K, E::V>> {
+        let mut columns = self.columns.lock().unwrap();
+        for key in keys.iter() {
+            if let Some((_, kind)) = columns.find_write_by_start_ts(key, start_ts) {
+                if kind != WriteKind::Rollback {
+                    return Err(TxnError::Committed(start_ts.into_inner()).into());
+                }
+            }
+        }
+        for key in keys.iter() {
+            columns.rollback(key, start_ts);
+        }
+        Ok(PercolatorResponse::Rollback)
+    }
+
+    fn check_txn_status(
+        &self,
+        primary: E::K,
+        start_ts: TimeStamp,
+        current_ts: TimeStamp,
+    ) -> Result<PercolatorResponse<E::K, E::V>> {
+        let mut columns = self.columns.lock().unwrap();
+        let status = match columns.locks.get(&primary) {
+            Some(lock) if lock.start_ts == start_ts => {
+                if current_ts.physical() >= start_ts.physical() + lock.ttl {
+                    columns.rollback(&primary, start_ts);
+                    TxnStatus::RolledBack
+                } else {
+                    TxnStatus::Locked { ttl: lock.ttl }
+                }
+            }
+            _ => match columns.find_write_by_start_ts(&primary, start_ts) {
+                Some((_, WriteKind::Rollback)) => TxnStatus::RolledBack,
+                Some((commit_ts, _)) => TxnStatus::Committed(commit_ts),
+                None => {
+                    // the primary is not prewritten yet, write a rollback record
+                    // so that the prewrite will fail when it arrives.
+                    columns.rollback(&primary, start_ts);
+                    TxnStatus::RolledBack
+                }
+            },
+        };
+        Ok(PercolatorResponse::TxnStatus(status))
+    }
+}
+
+#[async_trait]
+impl<E: SnapshotEngine> Node for PercolatorNode<E>
+where
+    E::K: Send,
+    E::V: Send,
+{
+    type Req = PercolatorRequest<E::K, E::V>;
+    type Res = PercolatorResponse<E::K, E::V>;
+
+    async fn process(&self, req: Self::Req) -> Result<Self::Res> {
+        match req {
+            PercolatorRequest::Get { key, ts } => self.get(key, ts),
+            PercolatorRequest::Scan { lower, upper, ts } => self.scan(lower, upper, ts),
+            PercolatorRequest::Prewrite {
+                mutations,
+                primary,
+                start_ts,
+                ttl,
+            } => self.prewrite(mutations, primary, start_ts, ttl),
+            PercolatorRequest::Commit {
+                keys,
+                start_ts,
+                commit_ts,
+            } => self.commit(keys, start_ts, commit_ts),
+            PercolatorRequest::Rollback { keys, start_ts } => self.rollback(keys, start_ts),
+            PercolatorRequest::CheckTxnStatus {
+                primary,
+                start_ts,
+                current_ts,
+            } => self.check_txn_status(primary, start_ts, current_ts),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::InMemSnapshotEngine;
+    use crate::util::test::run_in_tokio;
+    use crate::util::Error;
+
+    type Req = PercolatorRequest<i32, i32>;
+    type Res = PercolatorResponse<i32, i32>;
+
+    fn ts(physical: u64) -> TimeStamp {
+        TimeStamp::new(physical, 0)
+    }
+
+    async fn get(node: &PercolatorNode<InMemSnapshotEngine<i32, i32>>, key: i32, t: u64) -> Res {
+        node.process(Req::Get { key, ts: ts(t) }).await.unwrap()
+    }
+
+    fn prewrite(mutations: Vec<Mutation<i32, i32>>, primary: i32, start_ts: u64) -> Req {
+        Req::Prewrite {
+            mutations,
+            primary,
+            start_ts: ts(start_ts),
+            ttl: 10,
+        }
+    }
+
+    #[test]
+    fn test_percolator_node() {
+        run_in_tokio(async move {
+            let node = PercolatorNode::new(InMemSnapshotEngine::new());
+            let req = prewrite(vec![Mutation::Put(1, 10), Mutation::Put(2, 20)], 1, 10);
+            assert!(matches!(node.process(req).await, Ok(Res::Prewrite)));
+            // prewrite is idempotent.
+            let req = prewrite(vec![Mutation::Put(2, 20)], 1, 10);
+            assert!(matches!(node.process(req).await, Ok(Res::Prewrite)));
+            match get(&node, 1, 15).await {
+                Res::Locked(lock) => {
+                    assert_eq!(lock.primary, 1);
+                    assert_eq!(lock.start_ts, ts(10));
+                }
+                _ => panic!("key should be locked"),
+            }
+            // the lock is invisible to older readers.
+            assert!(matches!(get(&node, 1, 5).await, Res::Get(None)));
+            let req = Req::Commit {
+                keys: vec![1, 2],
+                start_ts: ts(10),
+                commit_ts: ts(11),
+            };
+            assert!(matches!(node.process(req).await, Ok(Res::Commit)));
+            assert!(matches!(get(&node, 2, 10).await, Res::Get(None)));
+            assert!(matches!(get(&node, 2, 11).await, Res::Get(Some(20))));
+            let req = Req::Scan {
+                lower: 0,
+                upper: 10,
+                ts: ts(11),
+            };
+            match node.process(req).await.unwrap() {
+                Res::Scan(kvs) => assert_eq!(kvs, vec![(1, 10), (2, 20)]),
+                _ => panic!("unexpected response"),
+            }
+
+            // write conflict with the committed txn.
+            let req = prewrite(vec![Mutation::Del(2)], 2, 9);
+            assert_eq!(
+                node.process(req).await.unwrap_err(),
+                Error::TxnError(TxnError::WriteConflict {
+                    key: "2".to_owned(),
+                    start_ts: ts(9).into_inner(),
+                    conflict_ts: ts(11).into_inner(),
+                })
+            );
+
+            let req = prewrite(vec![Mutation::Del(2)], 2, 20);
+            assert!(matches!(node.process(req).await, Ok(Res::Prewrite)));
+            // the lock is not expired.
+            let req = Req::CheckTxnStatus {
+                primary: 2,
+                start_ts: ts(20),
+                current_ts: ts(21),
+            };
+            match node.process(req).await.unwrap() {
+                Res::TxnStatus(status) => assert_eq!(status, TxnStatus::Locked { ttl: 10 }),
+                _ => unreachable!(),
+            }
+            // the lock is expired and rolled back.
+            let req = Req::CheckTxnStatus {
+                primary: 2,
+                start_ts: ts(20),
+                current_ts: ts(30),
+            };
+            match node.process(req).await.unwrap() {
+                Res::TxnStatus(status) => assert_eq!(status, TxnStatus::RolledBack),
+                _ => unreachable!(),
+            }
+            let req = Req::Commit {
+                keys: vec![2],
+                start_ts: ts(20),
+                commit_ts: ts(31),
+            };
+            assert_eq!(
+                node.process(req).await.unwrap_err(),
+                Error::TxnError(TxnError::RolledBack(ts(20).into_inner()))
+            );
+            assert!(matches!(get(&node, 2, 40).await, Res::Get(Some(20))));
+            let req = Req::CheckTxnStatus {
+                primary: 1,
+                start_ts: ts(10),
+                current_ts: ts(40),
+            };
+            match node.process(req).await.unwrap() {
+                Res::TxnStatus(status) => assert_eq!(status, TxnStatus::Committed(ts(11))),
+                _ => unreachable!(),
+            }
+            let req = Req::Rollback {
+                keys: vec![1],
+                start_ts: ts(10),
+            };
+            assert_eq!(
+                node.process(req).await.unwrap_err(),
+                Error::TxnError(TxnError::Committed(ts(10).into_inner()))
+            );
+        });
+    }
+}
@@ -0,0 +1,176 @@
+use crate::client::{TxnRequest, TxnResponse};
+use crate::node::{Node, Server};
+use crate::txn::percolator::{PercolatorServer, PercolatorTxn};
+use crate::txn::Txn;
+use crate::util::{Result, TxnError};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Mutex as AsyncMutex;
+
+type Session<S> = Arc<AsyncMutex<PercolatorTxn<S>>>;
+
+/// SessionServer serves the interactive txns of clients by `PercolatorTxn`s,
+/// a txn processes one request at a time, and the concurrent ones wait for it.
+pub struct SessionServer<S: PercolatorServer> {
+    server: S,
+    txns: Mutex<HashMap<u64, Session<S>>>,
+    next_id: AtomicU64,
+}
+
+impl<S: PercolatorServer> SessionServer<S> {
+    pub fn new(server: S) -> Self {
+        Self {
+            server,
+            txns: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.server
+    }
+
+    fn session(&self, id: u64) -> Result<Session<S>> {
+        self.txns
+            .lock()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| TxnError::InvalidState(format!("txn {} is not found", id)).into())
+    }
+
+    // finish removes the txn from the sessions, the requests waiting for it
+    // fail as the txn is finished.
+    fn finish(&self, id: u64) {
+        self.txns.lock().unwrap().remove(&id);
+    }
+}
+
+#[async_trait]
+impl<S: PercolatorServer> Node for SessionServer<S>
+where
+    S: Send,
+{
+    type Req = TxnRequest<S::K, S::V>;
+    type Res = TxnResponse<S::K, S::V>;
+
+    async fn process(&self, req: Self::Req) -> Result<Self::Res> {
+        let server = &self.server;
+        match req {
+            TxnRequest::Begin => {
+                let mut txn = PercolatorTxn::new();
+                txn.execute(server).await?;
+                let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                let session = Arc::new(AsyncMutex::new(txn));
+                self.txns.lock().unwrap().insert(id, session);
+                Ok(TxnResponse::Begin(id))
+            }
+            TxnRequest::Get { txn: id, key } => {
+                let session = self.session(id)?;
+                let mut txn = session.lock().await;
+                Ok(TxnResponse::Get(txn.get(server, &key).await?))
+            }
+            TxnRequest::Put {
+                txn: id,
+                key,
+                value,
+            } => {
+                let session = self.session(id)?;
+                let mut txn = session.lock().await;
+                txn.put(key, value).map(|_| TxnResponse::Done)
+            }
+            TxnRequest::Del { txn: id, key } => {
+                let session = self.session(id)?;
+                let mut txn = session.lock().await;
+                txn.del(key).map(|_| TxnResponse::Done)
+            }
+            TxnRequest::Scan {
+                txn: id,
+                lower,
+                upper,
+            } => {
+                let session = self.session(id)?;
+                let mut txn = session.lock().await;
+                Ok(TxnResponse::Scan(txn.scan(server, &lower, &upper).await?))
+            }
+            TxnRequest::Commit { txn: id } => {
+                let session = self.session(id)?;
+                let mut txn = session.lock().await;
+                let res = txn.commit(server).await;
+                self.finish(id);
+                res.map(|_| TxnResponse::Done)
+            }
+            TxnRequest::Rollback { txn: id } => {
+                let session = self.session(id)?;
+                let mut txn = session.lock().await;
+                let res = txn.rollback(server).await;
+                self.finish(id);
+                res.map(|_| TxnResponse::Done)
+            }
+        }
+    }
+}
+
+impl<S: PercolatorServer> Server for SessionServer<S>
+where
+    S: Send,
+{
+    type S = S::S;
+
+    fn register_shard(&mut self, s: Arc<Self::S>) {
+        self.server.register_shard(s);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::txn::percolator::test::new_server;
+    use crate::util::test::run_in_tokio;
+
+    #[test]
+    fn test_concurrent_requests() {
+        run_in_tokio(async move {
+            let session = SessionServer::new(new_server());
+            let id = match session.process(TxnRequest::Begin).await.unwrap() {
+                TxnResponse::Begin(id) => id,
+                _ => panic!("unexpected response"),
+            };
+            // the requests on the same txn wait for the one holding it.
+            let txn = session.session(id).unwrap();
+            let guard = txn.lock().await;
+            let mut put = session.process(TxnRequest::Put {
+                txn: id,
+                key: 1,
+                value: 1,
+            });
+            assert!(futures::poll!(&mut put).is_pending());
+            drop(guard);
+            assert!(matches!(put.await.unwrap(), TxnResponse::Done));
+
+            // the requests waiting for the commit find the txn finished.
+            let guard = txn.lock().await;
+            let mut commit = session.process(TxnRequest::Commit { txn: id });
+            assert!(futures::poll!(&mut commit).is_pending());
+            let mut get = session.process(TxnRequest::Get { txn: id, key: 1 });
+            assert!(futures::poll!(&mut get).is_pending());
+            drop(guard);
+            let (commit, get) = futures::join!(commit, get);
+            assert!(matches!(commit.unwrap(), TxnResponse::Done));
+            assert_eq!(
+                get.unwrap_err(),
+                TxnError::InvalidState("txn is finished".to_owned()).into()
+            );
+            assert_eq!(
+                session
+                    .process(TxnRequest::Get { txn: id, key: 0 })
+                    .await
+                    .unwrap_err(),
+                TxnError::InvalidState(format!("txn {} is not found", id)).into()
+            );
+            std::mem::forget(session);
+        });
+    }
+}
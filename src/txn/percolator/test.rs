@@ -0,0 +1,74 @@
+use crate::node::{Node, Server};
+use crate::request::channel::{new_channel_connect, ChannelSender};
+use crate::shard::{KeySpaceSpilt, Shard, SubRange};
+use crate::storage::InMemSnapshotEngine;
+use crate::tso::{TSOClient, TSONode, TSORequest, TSOResponse};
+use crate::txn::percolator::{
+    PercolatorNode, PercolatorRequest, PercolatorResponse, PercolatorServer,
+};
+use crate::util::{Either, Result};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+type Storage = ChannelSender<PercolatorRequest<i32, i32>, PercolatorResponse<i32, i32>>;
+
+pub struct TestServer {
+    shard: Arc<KeySpaceSpilt<i32, Storage>>,
+    tso: TSOClient<ChannelSender<TSORequest, TSOResponse>>,
+}
+
+#[async_trait]
+impl Node for TestServer {
+    type Req = ();
+    type Res = ();
+
+    async fn process(&self, _: Self::Req) -> Result<Self::Res> {
+        Ok(())
+    }
+}
+
+impl Server for TestServer {
+    type S = KeySpaceSpilt<i32, Storage>;
+
+    fn register_shard(&mut self, s: Arc<Self::S>) {
+        self.shard = s;
+    }
+}
+
+impl PercolatorServer for TestServer {
+    type K = i32;
+    type V = i32;
+    type Storage = Storage;
+    type TSO = ChannelSender<TSORequest, TSOResponse>;
+
+    fn route(&self, key: &i32) -> &Storage {
+        self.shard.key2node(key)
+    }
+
+    fn route_range(&self, lower: &i32, upper: &i32) -> Vec<SubRange<'_, i32, Storage>> {
+        self.shard.split_range(lower, upper)
+    }
+
+    fn tso(&self) -> &TSOClient<Self::TSO> {
+        &self.tso
+    }
+}
+
+// keys in [.., 50) are stored in the first node, [50, ..) in the second one.
+pub fn new_server() -> TestServer {
+    let mut shard = KeySpaceSpilt::new();
+    for (i, key) in [0, 50].iter().enumerate() {
+        let node = Arc::new(PercolatorNode::new(InMemSnapshotEngine::new()));
+        let sender = new_channel_connect(node);
+        let sender = if i == 0 {
+            Either::Left(sender)
+        } else {
+            Either::Right(sender)
+        };
+        shard.split(*key, sender).unwrap();
+    }
+    TestServer {
+        shard: Arc::new(shard),
+        tso: TSOClient::new(new_channel_connect(Arc::new(TSONode::new()))),
+    }
+}
@@ -0,0 +1,465 @@
+use crate::request::Sender;
+use crate::shard::SubRange;
+use crate::tso::TimeStamp;
+use crate::txn::percolator::{
+    LockInfo, Mutation, PercolatorRequest, PercolatorResponse, PercolatorServer, TxnStatus,
+    DEFAULT_LOCK_TTL,
+};
+use crate::txn::Txn;
+use crate::util::{Error, Result, TxnError};
+use async_trait::async_trait;
+use futures::future::{join_all, try_join_all};
+use std::collections::BTreeMap;
+use std::ops::Bound::{Excluded, Included};
+
+/// PercolatorTxn buffers the writes in memory and commits them by 2PC,
+/// the smallest written key is chosen as the primary key.
+pub struct PercolatorTxn<S: PercolatorServer> {
+    start_ts: Option<TimeStamp>,
+    commit_ts: Option<TimeStamp>,
+    // `None` means the key is deleted.
+    mutations: BTreeMap<S::K, Option<S::V>>,
+    ttl: u64,
+    finished: bool,
+}
+
+impl<S: PercolatorServer> PercolatorTxn<S> {
+    pub fn new() -> Self {
+        Self::with_ttl(DEFAULT_LOCK_TTL)
+    }
+
+    pub fn with_ttl(ttl: u64) -> Self {
+        Self {
+            start_ts: None,
+            commit_ts: None,
+            mutations: BTreeMap::new(),
+            ttl,
+            finished: false,
+        }
+    }
+
+    pub fn start_ts(&self) -> Option<TimeStamp> {
+        self.start_ts
+    }
+
+    /// commit_ts is set once the primary key is committed.
+    pub fn commit_ts(&self) -> Option<TimeStamp> {
+        self.commit_ts
+    }
+
+    pub async fn begin(&mut self, server: &S) -> Result<()> {
+        self.check_active()?;
+        if self.start_ts.is_none() {
+            self.start_ts = Some(server.tso().get_ts().await?);
+        }
+        Ok(())
+    }
+
+    /// get reads the key from the snapshot of `start_ts`, the writes of this txn are visible.
+    pub async fn get(&mut self, server: &S, key: &S::K) -> Result<Option<S::V>> {
+        self.begin(server).await?;
+        if let Some(v) = self.mutations.get(key) {
+            return Ok(v.as_ref().map(|v| v.to_owned()));
+        }
+        let ts = self.start_ts.unwrap();
+        loop {
+            let req = PercolatorRequest::Get {
+                key: key.to_owned(),
+                ts,
+            };
+            match server.route(key).send(req).await? {
+                PercolatorResponse::Get(v) => return Ok(v),
+                PercolatorResponse::Locked(lock) => self.resolve_lock(server, lock).await?,
+                _ => return Err(Error::Unknown),
+            }
+        }
+    }
+
+    /// scan reads the keys in `[lower, upper)` from the snapshot of `start_ts`,
+    /// the writes of this txn are visible.
+    pub async fn scan(
+        &mut self,
+        server: &S,
+        lower: &S::K,
+        upper: &S::K,
+    ) -> Result<Vec<(S::K, S::V)>> {
+        self.begin(server).await?;
+        let ts = self.start_ts.unwrap();
+        let parts = try_join_all(
+            server
+                .route_range(lower, upper)
+                .into_iter()
+                .map(|r| self.scan_node(server, r, ts)),
+        )
+        .await?;
+        let mut kvs: BTreeMap<_, _> = parts.into_iter().flatten().collect();
+        if lower < upper {
+            for (k, v) in self.mutations.range((Included(lower), Excluded(upper))) {
+                match v {
+                    Some(v) => kvs.insert(k.to_owned(), v.to_owned()),
+                    None => kvs.remove(k),
+                };
+            }
+        }
+        Ok(kvs.into_iter().collect())
+    }
+
+    async fn scan_node(
+        &self,
+        server: &S,
+        range: SubRange<'_, S::K, S::Storage>,
+        ts: TimeStamp,
+    ) -> Result<Vec<(S::K, S::V)>> {
+        loop {
+            let req = PercolatorRequest::Scan {
+                lower: range.lower.to_owned(),
+                upper: range.upper.to_owned(),
+                ts,
+            };
+            match range.node.send(req).await? {
+                PercolatorResponse::Scan(kvs) => return Ok(kvs),
+                PercolatorResponse::Locked(lock) => self.resolve_lock(server, lock).await?,
+                _ => return Err(Error::Unknown),
+            }
+        }
+    }
+
+    pub fn put(&mut self, key: S::K, value: S::V) -> Result<()> {
+        self.check_active()?;
+        self.mutations.insert(key, Some(value));
+        Ok(())
+    }
+
+    pub fn del(&mut self, key: S::K) -> Result<()> {
+        self.check_active()?;
+        self.mutations.insert(key, None);
+        Ok(())
+    }
+
+    fn check_active(&self) -> Result<()> {
+        if self.finished {
+            return Err(TxnError::InvalidState("txn is finished".to_owned()).into());
+        }
+        Ok(())
+    }
+
+    /// resolve_lock commits or rolls back the lock according to the status of its primary key.
+    async fn resolve_lock(&self, server: &S, lock: LockInfo<S::K>) -> Result<()> {
+        let current_ts = server.tso().get_ts().await?;
+        let req = PercolatorRequest::CheckTxnStatus {
+            primary: lock.primary.to_owned(),
+            start_ts: lock.start_ts,
+            current_ts,
+        };
+        let status = match server.route(&lock.primary).send(req).await? {
+            PercolatorResponse::TxnStatus(status) => status,
+            _ => return Err(Error::Unknown),
+        };
+        let req = match status {
+            TxnStatus::Committed(commit_ts) => PercolatorRequest::Commit {
+                keys: vec![lock.key.to_owned()],
+                start_ts: lock.start_ts,
+                commit_ts,
+            },
+            TxnStatus::RolledBack => PercolatorRequest::Rollback {
+                keys: vec![lock.key.to_owned()],
+                start_ts: lock.start_ts,
+            },
+            TxnStatus::Locked { .. } => {
+                return Err(TxnError::KeyIsLocked {
+                    key: lock.key.to_string(),
+                    lock_ts: lock.start_ts.into_inner(),
+                }
+                .into())
+            }
+        };
+        server.route(&lock.key).send(req).await?;
+        Ok(())
+    }
+
+    // group_by_node splits the keys by their storage nodes, the order of keys is kept.
+    fn group_by_node<'a>(
+        server: &'a S,
+        keys: impl Iterator<Item = &'a S::K>,
+    ) -> Vec<(&'a S::Storage, Vec<&'a S::K>)> {
+        let mut groups: Vec<(&S::Storage, Vec<&S::K>)> = vec![];
+        for key in keys {
+            let storage = server.route(key);
+            match groups.iter_mut().find(|(s, _)| std::ptr::eq(*s, storage)) {
+                Some((_, keys)) => keys.push(key),
+                None => groups.push((storage, vec![key])),
+            }
+        }
+        groups
+    }
+
+    async fn prewrite_keys(
+        &self,
+        server: &S,
+        storage: &S::Storage,
+        keys: &[&S::K],
+        primary: &S::K,
+        start_ts: TimeStamp,
+    ) -> Result<()> {
+        loop {
+            let mutations = keys
+                .iter()
+                .map(|&k| match self.mutations.get(k).unwrap() {
+                    Some(v) => Mutation::Put(k.to_owned(), v.to_owned()),
+                    None => Mutation::Del(k.to_owned()),
+                })
+                .collect();
+            let req = PercolatorRequest::Prewrite {
+                mutations,
+                primary: primary.to_owned(),
+                start_ts,
+                ttl: self.ttl,
+            };
+            match storage.send(req).await? {
+                PercolatorResponse::Prewrite => return Ok(()),
+                PercolatorResponse::Locked(lock) => self.resolve_lock(server, lock).await?,
+                _ => return Err(Error::Unknown),
+            }
+        }
+    }
+
+    async fn prewrite(&self, server: &S, start_ts: TimeStamp) -> Result<()> {
+        let primary = self.mutations.keys().next().unwrap();
+        let mut groups = Self::group_by_node(server, self.mutations.keys());
+        // the primary key must be locked before any secondary key.
+        let (storage, keys) = groups.remove(0);
+        self.prewrite_keys(server, storage, &keys, primary, start_ts)
+            .await?;
+        try_join_all(
+            groups.iter().map(|(storage, keys)| {
+                self.prewrite_keys(server, storage, keys, primary, start_ts)
+            }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    // rollback_keys cleans up the locks of this txn, it's best-effort.
+    async fn rollback_keys(&self, server: &S, start_ts: TimeStamp) {
+        let groups = Self::group_by_node(server, self.mutations.keys());
+        join_all(groups.into_iter().map(|(storage, keys)| {
+            let req = PercolatorRequest::Rollback {
+                keys: keys.into_iter().map(|k| k.to_owned()).collect(),
+                start_ts,
+            };
+            storage.send(req)
+        }))
+        .await;
+    }
+}
+
+impl<S: PercolatorServer> Default for PercolatorTxn<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl<S: PercolatorServer> Txn for PercolatorTxn<S> {
+    type Server = S;
+
+    async fn execute(&mut self, server: &Self::Server) -> Result<()> {
+        self.begin(server).await
+    }
+
+    async fn commit(&mut self, server: &Self::Server) -> Result<()> {
+        self.check_active()?;
+        let start_ts = match self.start_ts {
+            Some(ts) => ts,
+            None => return Err(TxnError::InvalidState("txn is not started".to_owned()).into()),
+        };
+        self.finished = true;
+        if self.mutations.is_empty() {
+            return Ok(());
+        }
+        if let Err(e) = self.prewrite(server, start_ts).await {
+            self.rollback_keys(server, start_ts).await;
+            return Err(e);
+        }
+        let commit_ts = match server.tso().get_ts().await {
+            Ok(ts) => ts,
+            Err(e) => {
+                self.rollback_keys(server, start_ts).await;
+                return Err(e);
+            }
+        };
+        let primary = self.mutations.keys().next().unwrap();
+        let req = PercolatorRequest::Commit {
+            keys: vec![primary.to_owned()],
+            start_ts,
+            commit_ts,
+        };
+        server.route(primary).send(req).await?;
+        self.commit_ts = Some(commit_ts);
+        // the txn is committed, the secondary locks left by failures will be resolved by readers.
+        let groups = Self::group_by_node(server, self.mutations.keys().skip(1));
+        join_all(groups.into_iter().map(|(storage, keys)| {
+            let req = PercolatorRequest::Commit {
+                keys: keys.into_iter().map(|k| k.to_owned()).collect(),
+                start_ts,
+                commit_ts,
+            };
+            storage.send(req)
+        }))
+        .await;
+        Ok(())
+    }
+
+    async fn rollback(&mut self, _: &Self::Server) -> Result<()> {
+        self.check_active()?;
+        // nothing is written before commit, and a failed commit cleans up its locks.
+        self.finished = true;
+        self.mutations.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Server;
+    use crate::txn::percolator::test::new_server;
+    use crate::util::test::run_in_tokio;
+
+    #[test]
+    fn test_commit_and_read() {
+        run_in_tokio(async move {
+            let server = new_server();
+            let mut txn = PercolatorTxn::new();
+            for i in 0..100 {
+                txn.put(i, i).unwrap();
+            }
+            server.execute(txn).await.unwrap();
+
+            let mut reader = PercolatorTxn::new();
+            reader.begin(&server).await.unwrap();
+            let mut writer = PercolatorTxn::new();
+            writer.begin(&server).await.unwrap();
+            for i in (0..100).step_by(2) {
+                writer.del(i).unwrap();
+            }
+            assert_eq!(writer.get(&server, &2).await.unwrap(), None);
+            assert_eq!(writer.get(&server, &3).await.unwrap(), Some(3));
+            writer.commit(&server).await.unwrap();
+            assert_eq!(
+                writer.get(&server, &3).await.unwrap_err(),
+                Error::TxnError(TxnError::InvalidState("txn is finished".to_owned()))
+            );
+
+            let mut new_reader = PercolatorTxn::new();
+            for i in 0..100 {
+                assert_eq!(reader.get(&server, &i).await.unwrap(), Some(i));
+                let expected = if i % 2 == 0 { None } else { Some(i) };
+                assert_eq!(new_reader.get(&server, &i).await.unwrap(), expected);
+            }
+            std::mem::forget(server);
+        });
+    }
+
+    #[test]
+    fn test_scan() {
+        run_in_tokio(async move {
+            let server = new_server();
+            let mut txn = PercolatorTxn::new();
+            for i in (0..100).step_by(2) {
+                txn.put(i, i).unwrap();
+            }
+            server.execute(txn).await.unwrap();
+
+            let mut txn = PercolatorTxn::new();
+            txn.put(47, 47).unwrap();
+            txn.del(48).unwrap();
+            // the range crosses both nodes.
+            let kvs = txn.scan(&server, &45, &55).await.unwrap();
+            assert_eq!(kvs, vec![(46, 46), (47, 47), (50, 50), (52, 52), (54, 54)]);
+            assert_eq!(txn.scan(&server, &55, &45).await.unwrap(), vec![]);
+            std::mem::forget(server);
+        });
+    }
+
+    #[test]
+    fn test_write_conflict() {
+        run_in_tokio(async move {
+            let server = new_server();
+            let mut txn1 = PercolatorTxn::new();
+            txn1.begin(&server).await.unwrap();
+            let mut txn2 = PercolatorTxn::new();
+            txn2.begin(&server).await.unwrap();
+            txn1.put(1, 1).unwrap();
+            txn1.put(51, 51).unwrap();
+            txn2.put(51, 0).unwrap();
+            txn2.commit(&server).await.unwrap();
+            match txn1.commit(&server).await.unwrap_err() {
+                Error::TxnError(TxnError::WriteConflict { key, .. }) => assert_eq!(key, "51"),
+                e => panic!("unexpected error {}", e),
+            }
+            // the lock of the primary key is cleaned up.
+            let mut reader = PercolatorTxn::new();
+            assert_eq!(reader.get(&server, &1).await.unwrap(), None);
+            assert_eq!(reader.get(&server, &51).await.unwrap(), Some(0));
+            std::mem::forget(server);
+        });
+    }
+
+    #[test]
+    fn test_resolve_lock() {
+        run_in_tokio(async move {
+            let server = new_server();
+            // txn1 crashes after committing the primary key.
+            let txn1_ts = server.tso().get_ts().await.unwrap();
+            // txn2 crashes after prewrite, and its locks expire immediately.
+            let txn2_ts = server.tso().get_ts().await.unwrap();
+            // txn3 crashes after prewrite, but its locks are alive.
+            let txn3_ts = server.tso().get_ts().await.unwrap();
+            for (primary, secondary, start_ts, ttl) in [
+                (1, 51, txn1_ts, 0),
+                (2, 52, txn2_ts, 0),
+                (3, 53, txn3_ts, 1000),
+            ] {
+                for key in [primary, secondary] {
+                    let req = PercolatorRequest::Prewrite {
+                        mutations: vec![Mutation::Put(key, key)],
+                        primary,
+                        start_ts,
+                        ttl,
+                    };
+                    server.route(&key).send(req).await.unwrap();
+                }
+            }
+            let req = PercolatorRequest::Commit {
+                keys: vec![1],
+                start_ts: txn1_ts,
+                commit_ts: server.tso().get_ts().await.unwrap(),
+            };
+            server.route(&1).send(req).await.unwrap();
+
+            let mut reader = PercolatorTxn::new();
+            assert_eq!(reader.get(&server, &51).await.unwrap(), Some(51));
+            assert_eq!(reader.get(&server, &52).await.unwrap(), None);
+            assert_eq!(reader.get(&server, &2).await.unwrap(), None);
+            assert_eq!(
+                reader.get(&server, &53).await.unwrap_err(),
+                Error::TxnError(TxnError::KeyIsLocked {
+                    key: "53".to_owned(),
+                    lock_ts: txn3_ts.into_inner(),
+                })
+            );
+            // txn2 can't be committed after it's rolled back.
+            let req = PercolatorRequest::Commit {
+                keys: vec![2],
+                start_ts: txn2_ts,
+                commit_ts: server.tso().get_ts().await.unwrap(),
+            };
+            assert_eq!(
+                server.route(&2).send(req).await.unwrap_err(),
+                Error::TxnError(TxnError::RolledBack(txn2_ts.into_inner()))
+            );
+            std::mem::forget(server);
+        });
+    }
+}
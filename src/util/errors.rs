@@ -1,5 +1,7 @@
+use crate::cluster::Role;
 use crate::request::Request;
 use std::result::Result as stdResult;
+use std::time::Duration;
 use thiserror::Error;
 
 pub type Result<T> = stdResult<T, Error>;
@@ -12,6 +14,16 @@ pub enum Error {
     RequestError(RequestError),
     #[error("shard error {0}")]
     ShardError(ShardError),
+    #[error("txn error {0}")]
+    TxnError(TxnError),
+    #[error("consensus error {0}")]
+    ConsensusError(ConsensusError),
+    #[error("codec error {0}")]
+    CodecError(CodecError),
+    #[error("storage error {0}")]
+    StorageError(StorageError),
+    #[error("config error {0}")]
+    ConfigError(String),
     #[error("unknown error")]
     Unknown,
 }
@@ -20,6 +32,14 @@ pub enum Error {
 pub enum ShardError {
     #[error("split on {0} failed")]
     SplitError(String),
+    #[error("merge on {0} failed")]
+    MergeError(String),
+    #[error("reassign bucket {bucket} to node {node} failed")]
+    ReassignError { bucket: usize, node: usize },
+    #[error("add node {0} failed")]
+    AddNodeError(u64),
+    #[error("remove node {0} failed")]
+    RemoveNodeError(u64),
 }
 
 impl From<ShardError> for Error {
@@ -29,12 +49,105 @@ impl From<ShardError> for Error {
 }
 
 #[derive(Error, Debug, PartialEq, Eq)]
-pub enum TSOError {}
+pub enum TxnError {
+    #[error("write conflict on {key}, start_ts {start_ts}, conflict_ts {conflict_ts}")]
+    WriteConflict {
+        key: String,
+        start_ts: u64,
+        conflict_ts: u64,
+    },
+    #[error("key {key} is locked by txn {lock_ts}")]
+    KeyIsLocked { key: String, lock_ts: u64 },
+    #[error("txn {0} has been rolled back")]
+    RolledBack(u64),
+    #[error("txn {0} has been committed")]
+    Committed(u64),
+    #[error("invalid txn state {0}")]
+    InvalidState(String),
+}
+
+impl From<TxnError> for Error {
+    fn from(e: TxnError) -> Error {
+        Error::TxnError(e)
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ConsensusError {
+    #[error("not leader, the leader may be {0:?}")]
+    NotLeader(Option<u64>),
+    #[error("proposal at {0} is dropped")]
+    ProposalDropped(u64),
+}
+
+impl From<ConsensusError> for Error {
+    fn from(e: ConsensusError) -> Error {
+        Error::ConsensusError(e)
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CodecError {
+    #[error("unexpected end of input")]
+    UnexpectedEof,
+    #[error("invalid data {0}")]
+    InvalidData(String),
+}
+
+impl From<CodecError> for Error {
+    fn from(e: CodecError) -> Error {
+        Error::CodecError(e)
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum StorageError {
+    #[error("io error {0}")]
+    Io(String),
+    #[error("data corrupted {0}")]
+    Corrupted(String),
+}
+
+impl From<StorageError> for Error {
+    fn from(e: StorageError) -> Error {
+        Error::StorageError(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::StorageError(StorageError::Io(e.to_string()))
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TSOError {
+    #[error("invalid timestamp count {0}")]
+    InvalidCount(u64),
+    #[error("physical time {0} overflow")]
+    PhysicalOverflow(u64),
+    #[error("clock error {0}")]
+    ClockError(String),
+}
+
+impl From<TSOError> for Error {
+    fn from(e: TSOError) -> Error {
+        Error::TSOError(e)
+    }
+}
 
 #[derive(Error, Debug, PartialEq, Eq)]
 pub enum RequestError {
     #[error("channel send error {0}")]
     SendError(String),
+    #[error("network error {0}")]
+    NetworkError(String),
+    #[error("request timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("node {0} not found")]
+    NodeNotFound(u64),
+    #[error("node {id} is a {actual}, not a {expected}")]
+    RoleMismatch { id: u64, expected: Role, actual: Role },
 }
 
 impl From<RequestError> for Error {
@@ -14,7 +14,7 @@ use std::sync::Arc;
 /// it will register the waker. When the event is ready, the waker will
 /// be notified, then the internal future is immediately polled in the
 /// thread calling `wake()`.
-pub fn poll_future_notify<T, F: Future<Output = T> + Send + 'static>(f: F) -> T {
+pub fn poll_future_notify<T: 'static, F: Future<Output = T> + Send + 'static>(f: F) -> T {
     let f: BoxFuture<'static, T> = Box::pin(f);
     let poller = Arc::new(PollAtWake {
         f: UnsafeCell::new(Some(f)),
@@ -36,7 +36,7 @@ pub struct PollAtWake<T> {
     state: AtomicU8,
 }
 
-impl<T> PollAtWake<T> {
+impl<T: 'static> PollAtWake<T> {
     fn poll(arc_self: &Arc<PollAtWake<T>>) -> Option<T> {
         let mut state = arc_self.state.load(Ordering::Relaxed);
         loop {
@@ -124,7 +124,7 @@ impl<T> PollAtWake<T> {
 unsafe impl<T> Send for PollAtWake<T> {}
 unsafe impl<T> Sync for PollAtWake<T> {}
 
-impl<T> ArcWake for PollAtWake<T> {
+impl<T: 'static> ArcWake for PollAtWake<T> {
     #[inline]
     fn wake_by_ref(arc_self: &Arc<Self>) {
         PollAtWake::poll(arc_self);
@@ -9,3 +9,12 @@ pub use future::{poll_future_notify, PollAtWake};
 
 mod either;
 pub use either::*;
+
+mod checksum;
+pub use checksum::crc32;
+
+mod rand;
+pub use rand::Rng;
+
+mod timer;
+pub use timer::{Timer, TokioTimer};
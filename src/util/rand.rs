@@ -0,0 +1,59 @@
+/// Rng is a small xorshift64* generator, the same seed always produces the same sequence.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64 scrambles the seed, so that the adjacent seeds produce unrelated sequences.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Self {
+            state: if z == 0 { 1 } else { z },
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// gen_range returns a number in `[lower, upper)`.
+    pub fn gen_range(&mut self, lower: u64, upper: u64) -> u64 {
+        assert!(lower < upper);
+        lower + self.next_u64() % (upper - lower)
+    }
+
+    /// gen_bool returns true with the probability `p`.
+    pub fn gen_bool(&mut self, p: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rng() {
+        let mut r1 = Rng::new(42);
+        let mut r2 = Rng::new(42);
+        let mut r3 = Rng::new(43);
+        let s1: Vec<_> = (0..100).map(|_| r1.next_u64()).collect();
+        let s2: Vec<_> = (0..100).map(|_| r2.next_u64()).collect();
+        let s3: Vec<_> = (0..100).map(|_| r3.next_u64()).collect();
+        assert_eq!(s1, s2);
+        assert_ne!(s1, s3);
+        for _ in 0..1000 {
+            let n = r1.gen_range(10, 20);
+            assert!((10..20).contains(&n));
+        }
+        assert!(!r1.gen_bool(0.0));
+        assert!(r1.gen_bool(1.0));
+    }
+}
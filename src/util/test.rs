@@ -1,3 +1,6 @@
+use crate::request::Sender;
+use crate::util::Result;
+use async_trait::async_trait;
 use std::future::Future;
 use tokio::runtime::Runtime;
 
@@ -11,3 +14,18 @@ where
     });
     rt.shutdown_background();
 }
+
+/// IdSender tells which node a request is routed to, it responds `id * 100 + req`.
+pub struct IdSender(pub u64);
+
+#[async_trait]
+impl Sender for IdSender {
+    type Req = u64;
+    type Res = u64;
+
+    async fn send(&self, req: Self::Req) -> Result<Self::Res> {
+        Ok(self.0 * 100 + req)
+    }
+
+    fn close(&mut self) {}
+}
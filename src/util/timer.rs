@@ -0,0 +1,39 @@
+use futures::future::BoxFuture;
+use std::time::{Duration, Instant};
+
+/// Timer abstracts the clock, so that the same component can run
+/// in the real world or in the simulator.
+pub trait Timer: Send + Sync {
+    /// now returns the elapsed time since the timer is created.
+    fn now(&self) -> Duration;
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// TokioTimer uses the real clock, it must be used inside a tokio runtime.
+pub struct TokioTimer {
+    start: Instant,
+}
+
+impl TokioTimer {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for TokioTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Timer for TokioTimer {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}